@@ -0,0 +1,428 @@
+//! Render barcodes as images that can be printed on their own or composited with text
+//!
+//! Bars run across the tape so a barcode's image x axis is along the length of the label, the same as the images
+//! `TextRasterizer` draws. Every module (the narrowest bar or space) is a whole number of printer dots wide so the
+//! barcode stays crisp and scannable. QR codes are square so they're drawn the same either way.
+
+use image::{GrayImage, Luma};
+
+error_chain! {
+    types {
+        BarcodeError, BarcodeErrorKind, ResultExt, Result;
+    }
+    errors {
+        InvalidCharacter(data: String, character: char) {
+            description("data contains a character the barcode can't encode")
+            display("\"{}\" contains '{}' which can't be encoded in this barcode", data, character)
+        }
+        InvalidLength(data: String) {
+            description("data is the wrong length for the barcode")
+            display("\"{}\" is the wrong length for this barcode", data)
+        }
+        InvalidCheckDigit(data: String) {
+            description("data ends with the wrong check digit")
+            display("\"{}\" ends with the wrong check digit", data)
+        }
+    }
+}
+
+/// The barcode symbologies that can be rendered
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BarcodeKind {
+    /// Any printable ASCII text. Data made up of an even number of digits and nothing else is encoded compactly, two
+    /// digits per symbol.
+    Code128,
+    /// 12 digits (the check digit is added) or 13 digits (the check digit is verified)
+    Ean13,
+}
+
+/// Width in dots of a single module when using `render_barcode()`. At 300dpi this is 0.25mm.
+pub const DEFAULT_MODULE_WIDTH: u32 = 3;
+
+/// Renders `data` as a barcode `height` dots tall (across the tape) with quiet zones on both sides. Each module is
+/// `DEFAULT_MODULE_WIDTH` dots wide.
+pub fn render_barcode(kind: BarcodeKind, data: &str, height: u32) -> Result<GrayImage> {
+    render_barcode_with_module_width(kind, data, height, DEFAULT_MODULE_WIDTH)
+}
+
+/// Same as `render_barcode()` but with each module `module_width` dots wide
+pub fn render_barcode_with_module_width(
+    kind: BarcodeKind,
+    data: &str,
+    height: u32,
+    module_width: u32,
+) -> Result<GrayImage> {
+    let (modules, quiet_zone) = match kind {
+        BarcodeKind::Code128 => (code128_modules(data)?, (10, 10)),
+        BarcodeKind::Ean13 => (ean13_modules(data)?, (11, 7)),
+    };
+    let module_width = module_width.max(1);
+    let total_modules = quiet_zone.0 + modules.len() as u32 + quiet_zone.1;
+    let mut image = GrayImage::from_pixel(total_modules * module_width, height, Luma([255]));
+    for (i, _) in modules.iter().enumerate().filter(|(_, &bar)| bar) {
+        let start = (quiet_zone.0 + i as u32) * module_width;
+        for x in start..start + module_width {
+            for y in 0..height {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// How much of a QR code can be damaged while still being readable. Higher levels make the code larger.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QrEcLevel {
+    /// About 7% of the code can be restored
+    Low,
+    /// About 15% of the code can be restored
+    Medium,
+    /// About 25% of the code can be restored
+    Quartile,
+    /// About 30% of the code can be restored
+    High,
+}
+impl QrEcLevel {
+    fn to_qrcode(self) -> qrcode::EcLevel {
+        match self {
+            QrEcLevel::Low => qrcode::EcLevel::L,
+            QrEcLevel::Medium => qrcode::EcLevel::M,
+            QrEcLevel::Quartile => qrcode::EcLevel::Q,
+            QrEcLevel::High => qrcode::EcLevel::H,
+        }
+    }
+}
+
+/// Modules of clear space a QR code needs on every side
+const QR_QUIET_ZONE: u32 = 4;
+
+/// Renders `data` as a QR code with each module `module_px` dots square, surrounded by a quiet zone. Fails with
+/// `InvalidLength` if the data doesn't fit in a QR code at the chosen error correction level.
+pub fn render_qr(data: &str, ec_level: QrEcLevel, module_px: u32) -> Result<GrayImage> {
+    Ok(QrModules::encode(data, ec_level)?.render(module_px))
+}
+
+/// The dark and light modules of an encoded QR code, so it can be drawn at whichever size fits the label
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct QrModules {
+    /// Number of modules along each side, not counting the quiet zone
+    size: u32,
+    dark: Vec<bool>,
+}
+impl QrModules {
+    pub(crate) fn encode(data: &str, ec_level: QrEcLevel) -> Result<Self> {
+        let code = qrcode::QrCode::with_error_correction_level(data, ec_level.to_qrcode())
+            .map_err(|_| BarcodeErrorKind::InvalidLength(data.to_string()))?;
+        Ok(Self {
+            size: code.width() as u32,
+            dark: code
+                .to_colors()
+                .into_iter()
+                .map(|color| color == qrcode::Color::Dark)
+                .collect(),
+        })
+    }
+
+    /// Number of modules along each side including the quiet zone
+    pub(crate) fn total_size(&self) -> u32 {
+        self.size + 2 * QR_QUIET_ZONE
+    }
+
+    pub(crate) fn render(&self, module_px: u32) -> GrayImage {
+        let module_px = module_px.max(1);
+        let side = self.total_size() * module_px;
+        GrayImage::from_fn(side, side, |x, y| {
+            let module = |position: u32| (position / module_px).checked_sub(QR_QUIET_ZONE);
+            match (module(x), module(y)) {
+                (Some(x), Some(y)) if x < self.size && y < self.size => {
+                    if self.dark[(y * self.size + x) as usize] {
+                        Luma([0])
+                    } else {
+                        Luma([255])
+                    }
+                }
+                _ => Luma([255]),
+            }
+        })
+    }
+}
+
+/// Expands bar and space widths (starting with a bar) into one entry per module, `true` for bars
+fn push_widths(modules: &mut Vec<bool>, widths: &[u8]) {
+    for (i, &width) in widths.iter().enumerate() {
+        let bar = i % 2 == 0;
+        modules.extend(std::iter::repeat_n(bar, width as usize));
+    }
+}
+
+/// Bar and space widths of every Code 128 symbol, indexed by value. 103 to 105 are the start codes for code sets
+/// A, B and C.
+const CODE128_PATTERNS: [[u8; 6]; 106] = [
+    [2, 1, 2, 2, 2, 2],
+    [2, 2, 2, 1, 2, 2],
+    [2, 2, 2, 2, 2, 1],
+    [1, 2, 1, 2, 2, 3],
+    [1, 2, 1, 3, 2, 2],
+    [1, 3, 1, 2, 2, 2],
+    [1, 2, 2, 2, 1, 3],
+    [1, 2, 2, 3, 1, 2],
+    [1, 3, 2, 2, 1, 2],
+    [2, 2, 1, 2, 1, 3],
+    [2, 2, 1, 3, 1, 2],
+    [2, 3, 1, 2, 1, 2],
+    [1, 1, 2, 2, 3, 2],
+    [1, 2, 2, 1, 3, 2],
+    [1, 2, 2, 2, 3, 1],
+    [1, 1, 3, 2, 2, 2],
+    [1, 2, 3, 1, 2, 2],
+    [1, 2, 3, 2, 2, 1],
+    [2, 2, 3, 2, 1, 1],
+    [2, 2, 1, 1, 3, 2],
+    [2, 2, 1, 2, 3, 1],
+    [2, 1, 3, 2, 1, 2],
+    [2, 2, 3, 1, 1, 2],
+    [3, 1, 2, 1, 3, 1],
+    [3, 1, 1, 2, 2, 2],
+    [3, 2, 1, 1, 2, 2],
+    [3, 2, 1, 2, 2, 1],
+    [3, 1, 2, 2, 1, 2],
+    [3, 2, 2, 1, 1, 2],
+    [3, 2, 2, 2, 1, 1],
+    [2, 1, 2, 1, 2, 3],
+    [2, 1, 2, 3, 2, 1],
+    [2, 3, 2, 1, 2, 1],
+    [1, 1, 1, 3, 2, 3],
+    [1, 3, 1, 1, 2, 3],
+    [1, 3, 1, 3, 2, 1],
+    [1, 1, 2, 3, 1, 3],
+    [1, 3, 2, 1, 1, 3],
+    [1, 3, 2, 3, 1, 1],
+    [2, 1, 1, 3, 1, 3],
+    [2, 3, 1, 1, 1, 3],
+    [2, 3, 1, 3, 1, 1],
+    [1, 1, 2, 1, 3, 3],
+    [1, 1, 2, 3, 3, 1],
+    [1, 3, 2, 1, 3, 1],
+    [1, 1, 3, 1, 2, 3],
+    [1, 1, 3, 3, 2, 1],
+    [1, 3, 3, 1, 2, 1],
+    [3, 1, 3, 1, 2, 1],
+    [2, 1, 1, 3, 3, 1],
+    [2, 3, 1, 1, 3, 1],
+    [2, 1, 3, 1, 1, 3],
+    [2, 1, 3, 3, 1, 1],
+    [2, 1, 3, 1, 3, 1],
+    [3, 1, 1, 1, 2, 3],
+    [3, 1, 1, 3, 2, 1],
+    [3, 3, 1, 1, 2, 1],
+    [3, 1, 2, 1, 1, 3],
+    [3, 1, 2, 3, 1, 1],
+    [3, 3, 2, 1, 1, 1],
+    [3, 1, 4, 1, 1, 1],
+    [2, 2, 1, 4, 1, 1],
+    [4, 3, 1, 1, 1, 1],
+    [1, 1, 1, 2, 2, 4],
+    [1, 1, 1, 4, 2, 2],
+    [1, 2, 1, 1, 2, 4],
+    [1, 2, 1, 4, 2, 1],
+    [1, 4, 1, 1, 2, 2],
+    [1, 4, 1, 2, 2, 1],
+    [1, 1, 2, 2, 1, 4],
+    [1, 1, 2, 4, 1, 2],
+    [1, 2, 2, 1, 1, 4],
+    [1, 2, 2, 4, 1, 1],
+    [1, 4, 2, 1, 1, 2],
+    [1, 4, 2, 2, 1, 1],
+    [2, 4, 1, 2, 1, 1],
+    [2, 2, 1, 1, 1, 4],
+    [4, 1, 3, 1, 1, 1],
+    [2, 4, 1, 1, 1, 2],
+    [1, 3, 4, 1, 1, 1],
+    [1, 1, 1, 2, 4, 2],
+    [1, 2, 1, 1, 4, 2],
+    [1, 2, 1, 2, 4, 1],
+    [1, 1, 4, 2, 1, 2],
+    [1, 2, 4, 1, 1, 2],
+    [1, 2, 4, 2, 1, 1],
+    [4, 1, 1, 2, 1, 2],
+    [4, 2, 1, 1, 1, 2],
+    [4, 2, 1, 2, 1, 1],
+    [2, 1, 2, 1, 4, 1],
+    [2, 1, 4, 1, 2, 1],
+    [4, 1, 2, 1, 2, 1],
+    [1, 1, 1, 1, 4, 3],
+    [1, 1, 1, 3, 4, 1],
+    [1, 3, 1, 1, 4, 1],
+    [1, 1, 4, 1, 1, 3],
+    [1, 1, 4, 3, 1, 1],
+    [4, 1, 1, 1, 1, 3],
+    [4, 1, 1, 3, 1, 1],
+    [1, 1, 3, 1, 4, 1],
+    [1, 1, 4, 1, 3, 1],
+    [3, 1, 1, 1, 4, 1],
+    [4, 1, 1, 1, 3, 1],
+    [2, 1, 1, 4, 1, 2],
+    [2, 1, 1, 2, 1, 4],
+    [2, 1, 1, 2, 3, 2],
+];
+const CODE128_STOP: [u8; 7] = [2, 3, 3, 1, 1, 1, 2];
+const CODE128_START_B: u16 = 104;
+const CODE128_START_C: u16 = 105;
+
+/// Symbol values for `data`, including the start code and check symbol but not the stop pattern
+fn code128_values(data: &str) -> Result<Vec<u16>> {
+    if data.is_empty() {
+        bail!(BarcodeErrorKind::InvalidLength(data.to_string()));
+    }
+    let mut values =
+        if data.len().is_multiple_of(2) && data.bytes().all(|byte| byte.is_ascii_digit()) {
+            // Code set C packs two digits into every symbol
+            let mut values = vec![CODE128_START_C];
+            for pair in data.as_bytes().chunks(2) {
+                values.push(((pair[0] - b'0') * 10 + (pair[1] - b'0')) as u16);
+            }
+            values
+        } else {
+            // Code set B covers all printable ASCII
+            let mut values = vec![CODE128_START_B];
+            for character in data.chars() {
+                match character {
+                    ' '..='\x7F' => values.push(character as u16 - 32),
+                    _ => bail!(BarcodeErrorKind::InvalidCharacter(
+                        data.to_string(),
+                        character
+                    )),
+                }
+            }
+            values
+        };
+    let checksum = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| i.max(1) as u32 * value as u32)
+        .sum::<u32>()
+        % 103;
+    values.push(checksum as u16);
+    Ok(values)
+}
+
+fn code128_modules(data: &str) -> Result<Vec<bool>> {
+    let mut modules = Vec::new();
+    for value in code128_values(data)? {
+        push_widths(&mut modules, &CODE128_PATTERNS[value as usize]);
+    }
+    push_widths(&mut modules, &CODE128_STOP);
+    Ok(modules)
+}
+
+/// Left-hand odd parity ("L") encodings of each digit. Right-hand digits are the complement of these and even
+/// parity ("G") digits are the right-hand encoding reversed.
+const EAN_L_CODES: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011,
+    0b0110111, 0b0001011,
+];
+/// Which of the left-hand digits use even parity, from the most significant bit, for each first digit
+const EAN_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+
+fn ean13_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, &digit)| digit as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+fn ean13_modules(data: &str) -> Result<Vec<bool>> {
+    if let Some(character) = data.chars().find(|c| !c.is_ascii_digit()) {
+        bail!(BarcodeErrorKind::InvalidCharacter(
+            data.to_string(),
+            character
+        ));
+    }
+    let mut digits: Vec<u8> = data.bytes().map(|byte| byte - b'0').collect();
+    match digits.len() {
+        12 => digits.push(ean13_check_digit(&digits)),
+        13 if ean13_check_digit(&digits[..12]) == digits[12] => {}
+        13 => bail!(BarcodeErrorKind::InvalidCheckDigit(data.to_string())),
+        _ => bail!(BarcodeErrorKind::InvalidLength(data.to_string())),
+    }
+
+    let push_bits = |modules: &mut Vec<bool>, bits: u8, count: u32| {
+        for bit in (0..count).rev() {
+            modules.push(bits & (1 << bit) != 0);
+        }
+    };
+    let mut modules = Vec::with_capacity(95);
+    push_bits(&mut modules, 0b101, 3);
+    // The first digit isn't drawn, it's encoded in the parity of the next six
+    let parity = EAN_PARITY[digits[0] as usize];
+    for (i, &digit) in digits[1..7].iter().enumerate() {
+        let code = EAN_L_CODES[digit as usize];
+        if parity & (1 << (5 - i)) != 0 {
+            let right = !code & 0x7F;
+            push_bits(&mut modules, right.reverse_bits() >> 1, 7);
+        } else {
+            push_bits(&mut modules, code, 7);
+        }
+    }
+    push_bits(&mut modules, 0b01010, 5);
+    for &digit in &digits[7..] {
+        push_bits(&mut modules, !EAN_L_CODES[digit as usize] & 0x7F, 7);
+    }
+    push_bits(&mut modules, 0b101, 3);
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code128_symbols() {
+        assert!(CODE128_PATTERNS
+            .iter()
+            .all(|pattern| pattern.iter().sum::<u8>() == 11));
+        assert_eq!(
+            code128_values("Hello").unwrap(),
+            vec![104, 40, 69, 76, 76, 79, 76]
+        );
+        assert_eq!(code128_values("123456").unwrap(), vec![105, 12, 34, 56, 44]);
+        assert!(code128_values("Ünïcode").is_err());
+        // Start, 5 characters and check symbol at 11 modules each, the stop pattern and both quiet zones
+        let image = render_barcode_with_module_width(BarcodeKind::Code128, "Hello", 50, 1).unwrap();
+        assert_eq!(image.dimensions(), (10 + 7 * 11 + 13 + 10, 50));
+    }
+
+    #[test]
+    fn ean13_symbols() {
+        assert_eq!(ean13_check_digit(&[4, 0, 0, 6, 3, 8, 1, 3, 3, 3, 9, 3]), 1);
+        let modules = ean13_modules("400638133393").unwrap();
+        assert_eq!(modules, ean13_modules("4006381333931").unwrap());
+        assert_eq!(modules.len(), 95);
+        assert!(ean13_modules("4006381333932").is_err());
+        assert!(ean13_modules("40063813339").is_err());
+
+        let image = render_barcode(BarcodeKind::Ean13, "400638133393", 80).unwrap();
+        assert_eq!(image.width(), (11 + 95 + 7) * DEFAULT_MODULE_WIDTH);
+    }
+
+    #[test]
+    fn qr_codes() {
+        // "Hello" fits in a version 1 code, which is 21 modules square
+        let image = render_qr("Hello", QrEcLevel::Medium, 3).unwrap();
+        assert_eq!(image.dimensions(), ((21 + 8) * 3, (21 + 8) * 3));
+        // The quiet zone is blank and the top left finder pattern starts right after it
+        assert!((0..12).all(|i| image.get_pixel(i, i)[0] == 255));
+        assert!((12..15).all(|i| image.get_pixel(i, i)[0] == 0));
+        assert!(image.pixels().all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+
+        let low = QrModules::encode(&"A".repeat(100), QrEcLevel::Low).unwrap();
+        let high = QrModules::encode(&"A".repeat(100), QrEcLevel::High).unwrap();
+        assert!(high.total_size() > low.total_size());
+        assert!(render_qr(&"A".repeat(5000), QrEcLevel::High, 1).is_err());
+    }
+}
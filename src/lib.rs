@@ -3,3 +3,4 @@ extern crate error_chain;
 
 pub mod text;
 pub mod printer;
+pub mod barcode;
@@ -2,14 +2,56 @@
 //!
 //! Based on the published [Brother QL Series Command Reference](https://download.brother.com/welcome/docp000678/cv_qlseries_eng_raster_600.pdf)
 
+use self::backend::{PrinterBackend, UsbBackend};
+use crate::text::{Dithering, FontSizing, ImageFit, RasterizeWarning, TextRasterizer};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod backend;
+pub mod commands;
 pub mod constants;
+pub mod pool;
+
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::AsyncThermalPrinter;
+pub use self::pool::PrinterPool;
 
 error_chain! {
+    links {
+        Rasterize(crate::text::RasterizeError, crate::text::RasterizeErrorKind);
+    }
     foreign_links {
         USB(rusb::Error);
+        Io(std::io::Error);
+    }
+    errors {
+        Printer(error: status::PrinterError) {
+            description("the printer reported an error")
+            display("Printer error: {}", error)
+        }
+        Timeout(timeout: Duration) {
+            description("the printer didn't respond in time")
+            display("Printer didn't respond within {:?}", timeout)
+        }
+        MediaMismatch(expected: &'static str, found: &'static str) {
+            description("the raster lines are for a different label than the one loaded")
+            display("Raster lines are for {} labels but {} labels are loaded", expected, found)
+        }
+        MissingEndpoint(interface: u8, direction: rusb::Direction, address: Option<u8>) {
+            description("the printer's USB interface doesn't have the bulk endpoint needed")
+            display(
+                "No bulk {:?} endpoint{} on USB interface {}",
+                direction,
+                address.map_or(String::new(), |address| format!(" 0x{:02X}", address)),
+                interface
+            )
+        }
     }
 }
 
@@ -47,7 +89,7 @@ pub mod status {
         }
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum StatusType {
         ReplyToStatusRequest,
         PrintingCompleted,
@@ -56,6 +98,15 @@ pub mod status {
         PhaseChange,
     }
 
+    /// What the printer is doing, as reported with every status. A `StatusType::PhaseChange` status is sent whenever
+    /// this changes.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub enum Phase {
+        /// Waiting for (or receiving) a print job
+        Receiving,
+        Printing,
+    }
+
     #[derive(Debug)]
     pub struct Response {
         pub model: &'static str,
@@ -63,6 +114,160 @@ pub mod status {
         pub errors: Vec<&'static str>,
         pub media: Media,
     }
+
+    /// An error reported by the printer in its status response
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub enum PrinterError {
+        NoMedia,
+        EndOfMedia,
+        CutterJam,
+        InUse,
+        FanFailure,
+        /// The loaded media doesn't match the print job and has to be replaced
+        WrongMedia,
+        TransmissionError,
+        CoverOpen,
+        CannotFeed,
+        SystemError,
+        /// The print head is cooling down and printing is paused until it has
+        Overheated,
+    }
+    impl PrinterError {
+        pub fn message(self) -> &'static str {
+            match self {
+                PrinterError::NoMedia => "No media when printing",
+                PrinterError::EndOfMedia => "End of media",
+                PrinterError::CutterJam => "Tape cutter jam",
+                PrinterError::InUse => "Main unit in use",
+                PrinterError::FanFailure => "Fan doesn't work",
+                PrinterError::WrongMedia => "Replace media",
+                PrinterError::TransmissionError => "Transmission error",
+                PrinterError::CoverOpen => "Cover open",
+                PrinterError::CannotFeed => "Cannot feed",
+                PrinterError::SystemError => "System error",
+                PrinterError::Overheated => "Print head cooling down",
+            }
+        }
+    }
+    impl std::fmt::Display for PrinterError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.message())
+        }
+    }
+
+    /// Position of each error flag in the status response as `(byte, bit, error)`
+    const ERROR_FLAGS: [(usize, u8, PrinterError); 10] = [
+        (8, 0x01, PrinterError::NoMedia),
+        (8, 0x02, PrinterError::EndOfMedia),
+        (8, 0x04, PrinterError::CutterJam),
+        (8, 0x10, PrinterError::InUse),
+        (8, 0x80, PrinterError::FanFailure),
+        (9, 0x01, PrinterError::WrongMedia),
+        (9, 0x04, PrinterError::TransmissionError),
+        (9, 0x10, PrinterError::CoverOpen),
+        (9, 0x40, PrinterError::CannotFeed),
+        (9, 0x80, PrinterError::SystemError),
+    ];
+
+    /// Everything the printer reports in its 32 byte status response
+    #[derive(Debug)]
+    pub struct PrinterStatus {
+        pub model: &'static str,
+        pub status_type: StatusType,
+        pub errors: Vec<PrinterError>,
+        pub media_type: MediaType,
+        pub media_width_mm: u8,
+        /// `0` for continuous tape
+        pub media_length_mm: u8,
+        pub phase: Phase,
+    }
+    impl PrinterStatus {
+        /// Parses a status response read from the printer. Returns `None` if it isn't a valid response.
+        pub fn parse(response: &[u8]) -> Option<Self> {
+            if response.len() != 32 || response[0] != 0x80 {
+                return None;
+            }
+
+            let model = match response[4] {
+                0x4F => "QL-500/550",
+                0x31 => "QL-560",
+                0x32 => "QL-570",
+                0x33 => "QL-580N",
+                0x51 => "QL-650TD",
+                0x35 => "QL-700",
+                0x38 => "QL-800",
+                0x39 => "QL-810W",
+                0x41 => "QL-820NWB",
+                0x50 => "QL-1050",
+                0x34 => "QL-1060N",
+                0x43 => "QL-1100",
+                0x44 => "QL-1110NWB",
+                0x45 => "QL-1115NWB",
+                _ => "Unknown",
+            };
+
+            let mut errors: Vec<_> = ERROR_FLAGS
+                .iter()
+                .filter(|(byte, flag, _)| response[*byte] & flag != 0)
+                .map(|(_, _, error)| *error)
+                .collect();
+            // Cooling is reported as a notification rather than an error bit
+            if response[22] == 0x03 {
+                errors.push(PrinterError::Overheated);
+            }
+
+            let media_type = match response[11] {
+                0x0A => MediaType::ContinuousTape,
+                0x0B => MediaType::DieCutLabels,
+                _ => MediaType::None,
+            };
+
+            let status_type = match response[18] {
+                0x00 => StatusType::ReplyToStatusRequest,
+                0x01 => StatusType::PrintingCompleted,
+                0x02 => StatusType::ErrorOccurred,
+                0x05 => StatusType::Notification,
+                0x06 => StatusType::PhaseChange,
+                // Will never occur
+                _ => StatusType::Notification,
+            };
+
+            Some(PrinterStatus {
+                model,
+                status_type,
+                errors,
+                media_type,
+                media_width_mm: response[10],
+                media_length_mm: response[17],
+                phase: match response[19] {
+                    0x01 => Phase::Printing,
+                    _ => Phase::Receiving,
+                },
+            })
+        }
+        /// The entry in the label table matching the loaded media, if there is one
+        pub fn label(&self) -> Option<Label> {
+            let length = match self.media_length_mm {
+                0 => None,
+                length => Some(length),
+            };
+            label_data(self.media_width_mm, length)
+        }
+    }
+    impl From<PrinterStatus> for Response {
+        fn from(status: PrinterStatus) -> Self {
+            Response {
+                model: status.model,
+                status_type: status.status_type,
+                errors: status.errors.iter().map(|error| error.message()).collect(),
+                media: Media {
+                    media_type: status.media_type,
+                    width: status.media_width_mm,
+                    length: status.media_length_mm,
+                },
+            }
+        }
+    }
 }
 
 fn printer_filter<T: rusb::UsbContext>(device: &rusb::Device<T>) -> bool {
@@ -83,18 +288,516 @@ pub fn printers() -> Vec<rusb::Device<rusb::GlobalContext>> {
         .collect()
 }
 
-const RASTER_LINE_LENGTH: u8 = 90;
+/// A connected printer found by `ThermalPrinter::enumerate()`
+#[derive(Debug, Clone)]
+pub struct PrinterInfo {
+    pub model: &'static str,
+    /// `None` if the serial number couldn't be read, e.g. because another program has the printer open
+    pub serial_number: Option<String>,
+    pub bus_number: u8,
+    pub address: u8,
+    pub device: rusb::Device<rusb::GlobalContext>,
+}
+impl PrinterInfo {
+    /// Connect to this printer
+    pub fn open(&self) -> Result<ThermalPrinter> {
+        ThermalPrinter::new(self.device.clone())
+    }
+}
+
+/// Settings for printing a line of text with `ThermalPrinter::print_text()`
+#[derive(Debug, Clone)]
+pub struct TextPrintOptions {
+    pub font_path: PathBuf,
+    /// Smaller text printed below the main text
+    pub secondary_text: Option<String>,
+    /// Scales the largest font size the text starts at before it's shrunk to fit
+    pub font_scale: f32,
+    /// Print white text on a black background
+    pub invert: bool,
+    /// The label to lay the text out for, or `None` to use the label loaded in the printer
+    pub label: Option<constants::Label>,
+    /// Changes the printer's cut behavior (see `ThermalPrinter::set_cut_behavior()`) if set
+    pub cut_behavior: Option<CutBehavior>,
+    /// Warn about text shrunk below this size in dots (see `TextRasterizer::set_min_readable_font_size()`). The
+    /// warnings are listed by `ThermalPrinter::validate_text()`.
+    pub min_readable_font_size: Option<f32>,
+}
+impl TextPrintOptions {
+    /// Options to print with the font at `font_path` at normal size on the loaded label
+    pub fn new(font_path: PathBuf) -> Self {
+        Self {
+            font_path,
+            secondary_text: None,
+            font_scale: 1.0,
+            invert: false,
+            label: None,
+            cut_behavior: None,
+            min_readable_font_size: None,
+        }
+    }
+
+    /// A rasterizer for `label` set up with these options
+    fn rasterizer(&self, label: constants::Label) -> TextRasterizer {
+        let mut rasterizer = TextRasterizer::new(label, self.font_path.clone());
+        if let Some(size) = self.min_readable_font_size {
+            rasterizer.set_min_readable_font_size(size);
+        }
+        rasterizer
+    }
+
+    /// The size the primary text starts at before it's shrunk to fit, the same size `TextRasterizer::rasterize()`
+    /// starts at
+    fn max_font_size(&self) -> f32 {
+        let size = match self.secondary_text {
+            Some(_) => 90.0,
+            None => 125.0,
+        };
+        size * self.font_scale
+    }
+}
+
+/// What printing a batch of text labels would do, from `ThermalPrinter::validate_text()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobReport {
+    /// The label the text was laid out for
+    pub label: constants::Label,
+    /// One entry for each text, in order
+    pub labels: Vec<LabelReport>,
+    /// Size in bytes of the print job that would have been sent
+    pub job_size: usize,
+}
+
+/// How a print job sent with `ThermalPrinter::print_and_wait()` went
+#[derive(Debug)]
+pub struct PrintOutcome {
+    /// Every phase the printer reported changing to while the job was printed, in order
+    pub phases: Vec<status::Phase>,
+    /// The status reporting that printing has completed
+    pub status: status::PrinterStatus,
+}
+
+/// How one label of a `JobReport` would be printed
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelReport {
+    pub text: String,
+    /// The size in dots the primary text was shrunk to
+    pub font_size: f32,
+    /// Number of raster lines along the tape
+    pub line_count: usize,
+    /// Anything that didn't stop the label from being rasterized but might be worth a look
+    pub warnings: Vec<RasterizeWarning>,
+}
+
+/// Replaces the `{n}`, `{n:0<width>}` and `{n:<width>}` counters in `template` with `n` (see
+/// `ThermalPrinter::print_series()`). Other text, including other braces, is left as it is.
+fn format_counter(template: &str, n: u64) -> Result<String> {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(index) = rest.find("{n") {
+        text.push_str(&rest[..index]);
+        rest = &rest[index..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        match &rest[2..end] {
+            "" => text.push_str(&n.to_string()),
+            spec if spec.starts_with(':') => {
+                let width = &spec[1..];
+                let padded = match width.parse::<usize>() {
+                    Ok(digits) if width.starts_with('0') => format!("{:0digits$}", n),
+                    Ok(digits) => format!("{:digits$}", n),
+                    Err(_) => bail!("Invalid counter {:?} in {:?}", &rest[..=end], template),
+                };
+                text.push_str(&padded);
+            }
+            // Something else in braces that starts with an n, like "{name}"
+            _ => text.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+/// Settings for printing an image file with `ThermalPrinter::print_image_file()`
+#[derive(Debug, Copy, Clone)]
+pub struct ImagePrintOptions {
+    /// How gray pixels are turned into dots. Defaults to `Dithering::FloydSteinberg`, which suits photos and logos.
+    pub dithering: Dithering,
+    /// Whether the image fits inside a die-cut label (the default) or covers all of it. Images on continuous tape
+    /// always span the printable width.
+    pub fit: ImageFit,
+    /// The label to scale the image for, or `None` to use the label loaded in the printer
+    pub label: Option<constants::Label>,
+    /// Changes the printer's cut behavior (see `ThermalPrinter::set_cut_behavior()`) if set
+    pub cut_behavior: Option<CutBehavior>,
+}
+impl Default for ImagePrintOptions {
+    fn default() -> Self {
+        Self {
+            dithering: Dithering::FloydSteinberg,
+            fit: ImageFit::Contain,
+            label: None,
+            cut_behavior: None,
+        }
+    }
+}
+
+/// Settings for writing a print job with `ThermalPrinter::print_to_writer()`
+#[derive(Debug, Copy, Clone)]
+pub struct PrintOptions {
+    /// The label the job is for, which is sent in the media information command
+    pub label: constants::Label,
+}
+impl PrintOptions {
+    pub fn new(label: constants::Label) -> Self {
+        Self { label }
+    }
+}
+
+/// When the printer cuts the tape during a print job
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CutBehavior {
+    /// Never cut, not even at the end of the job
+    None,
+    /// Cut after every label (default on models with a cutter)
+    EachLabel,
+    /// Cut after every `n` labels and at the end of the job, so a 5 label job with `Every(2)` is cut after labels
+    /// 2 and 4 and after the last label. Printers accept 1 to 255, other values are clamped to that range.
+    Every(u16),
+    /// Only cut once after the last label
+    EndOnly,
+}
+impl CutBehavior {
+    /// Returns whether auto-cut is enabled, the number of labels between cuts and whether to cut at the end
+    fn settings(self) -> (bool, u8, bool) {
+        match self {
+            CutBehavior::None => (false, 1, false),
+            CutBehavior::EachLabel => (true, 1, true),
+            CutBehavior::Every(n) => (true, n.clamp(1, 255) as u8, true),
+            CutBehavior::EndOnly => (false, 1, true),
+        }
+    }
+}
+
+/// The ESC i d command setting the feed margin, either `feed_margin` or the label's own margin
+fn margin_command(label: &constants::Label, feed_margin: Option<u16>) -> [u8; 5] {
+    let dots = match feed_margin {
+        _ if label.tape_size.1 != 0 => 0,
+        Some(dots) => dots,
+        None => label.feed_margin as u16,
+    };
+    let [low, high] = dots.to_le_bytes();
+    [0x1B, 0x69, 0x64, low, high]
+}
+
+/// The media a print information command describes: its type and its width and length in millimeters
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct MediaInfo {
+    media_type: u8,
+    width_mm: u8,
+    length_mm: u8,
+}
+impl MediaInfo {
+    /// The media exactly as the printer reported it, which is what it checks the command against
+    fn reported(status: &status::PrinterStatus) -> Self {
+        MediaInfo {
+            media_type: match status.media_type {
+                status::MediaType::DieCutLabels => commands::MEDIA_DIE_CUT,
+                _ => commands::MEDIA_CONTINUOUS,
+            },
+            width_mm: status.media_width_mm,
+            length_mm: status.media_length_mm,
+        }
+    }
+    /// The media for `label` when there's no printer to ask
+    fn of_label(label: &constants::Label) -> Self {
+        MediaInfo {
+            media_type: if label.tape_size.1 == 0 {
+                commands::MEDIA_CONTINUOUS
+            } else {
+                commands::MEDIA_DIE_CUT
+            },
+            width_mm: label.tape_size.0 as u8,
+            length_mm: label.tape_size.1 as u8,
+        }
+    }
+}
+
+/// The ESC i z print information command for a page of `line_count` raster lines on `media`. The printer checks the
+/// media type, width and length against the loaded media before printing and recovers from errors on its own.
+fn print_information_command(
+    media: MediaInfo,
+    line_count: u32,
+    first_page: bool,
+    quality_priority: bool,
+) -> [u8; 13] {
+    use self::commands::{PI_KIND, PI_LENGTH, PI_QUALITY, PI_RECOVER, PI_WIDTH};
+    let mut valid_flags = PI_RECOVER | PI_KIND | PI_WIDTH | PI_LENGTH;
+    if quality_priority {
+        valid_flags |= PI_QUALITY;
+    }
+    let [lines0, lines1, lines2, lines3] = line_count.to_le_bytes();
+    [
+        0x1B,
+        0x69,
+        0x7A,
+        valid_flags,
+        media.media_type,
+        media.width_mm,
+        media.length_mm,
+        lines0,
+        lines1,
+        lines2,
+        lines3,
+        if first_page { 0x00 } else { 0x01 },
+        0,
+    ]
+}
+
+/// Appends the command for a single raster line to `out`. The line is padded to the printer's line length in
+/// `line_data` first. Lines of two-color images are sent with the number of their plane.
+fn encode_line(
+    line: &[u8],
+    plane: Option<u8>,
+    compression: bool,
+    line_data: &mut [u8],
+    out: &mut Vec<u8>,
+) {
+    line_data[..line.len()].copy_from_slice(line);
+    line_data[line.len()..].fill(0);
+    if !compression {
+        frame_raster_line(line_data, plane, out);
+    } else if plane.is_none() && line_data.iter().all(|&byte| byte == 0) {
+        out.push(0x5A); // Blank line
+    } else {
+        frame_raster_line(&compress_packbits(line_data), plane, out);
+    }
+}
+
+/// Appends `data` to `out` as a raster line command: `g 0x00` for a single color line or `w` and the number of the
+/// plane for a line of a two-color image, then the length of `data` and the data itself.
+///
+/// `data` is sent as is, so it must already be the exact payload the printer expects: the packed dots of
+/// `text::pack_raster_line()` padded to the printer's line length, or those compressed with `compress_packbits()` in
+/// compression mode. The packed lines carry no command bytes of their own.
+pub fn frame_raster_line(data: &[u8], plane: Option<u8>, out: &mut Vec<u8>) {
+    match plane {
+        Some(plane) => out.extend_from_slice(&[0x77, plane]),
+        None => out.extend_from_slice(&[0x67, 0x00]),
+    }
+    out.push(data.len() as u8);
+    out.extend_from_slice(data);
+}
+
+/// Compresses a raster line with the TIFF PackBits run-length encoding that printers accept in compression mode
+/// (see `ThermalPrinter::set_compression()`)
+///
+/// Runs of repeated bytes are stored as a count byte of `1 - length` (as an `i8`) followed by the byte while other
+/// bytes are copied in blocks with a count byte of `length - 1` in front.
+pub fn compress_packbits(line: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 128;
+    let mut compressed = Vec::with_capacity(line.len() + line.len() / MAX_BLOCK + 1);
+    let mut i = 0;
+    while i < line.len() {
+        let mut run = 1;
+        while i + run < line.len() && run < MAX_BLOCK && line[i + run] == line[i] {
+            run += 1;
+        }
+        if run > 1 {
+            compressed.push((257 - run) as u8);
+            compressed.push(line[i]);
+            i += run;
+        } else {
+            // Copy bytes literally until the next repeated pair of bytes, which will start a run
+            let start = i;
+            while i < line.len()
+                && i - start < MAX_BLOCK
+                && !(i + 1 < line.len() && line[i] == line[i + 1])
+            {
+                i += 1;
+            }
+            compressed.push((i - start - 1) as u8);
+            compressed.extend_from_slice(&line[start..i]);
+        }
+    }
+    compressed
+}
+
+/// Print resolution along the length of the tape. The resolution across the tape is always 300dpi.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Resolution {
+    /// 300dpi, one raster line per dot (default)
+    Standard,
+    /// 600dpi "high quality" printing, which takes twice as many raster lines for the same length of tape. Not
+    /// supported when printing in two colors.
+    High,
+}
+impl Resolution {
+    /// Number of raster lines printed per 300dpi dot along the tape
+    pub fn lines_per_dot(self) -> u32 {
+        match self {
+            Resolution::Standard => 1,
+            Resolution::High => 2,
+        }
+    }
+    /// Raster lines per inch along the tape
+    pub fn dpi(self) -> u32 {
+        constants::DPI * self.lines_per_dot()
+    }
+    /// Number of raster lines that take up `mm` millimeters of tape, rounded to the nearest line
+    pub fn mm_to_lines(self, mm: f32) -> u32 {
+        (mm * self.dpi() as f32 / 25.4).round().max(0.0) as u32
+    }
+}
+
+/// Monochrome image data in the format sent to the printer: one line of bits per row of dots along the tape
+///
+/// Every line has the same length in bytes, which must not be longer than the raster line of the printer it is
+/// sent to. Shorter lines are padded with blank dots when printing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterLines {
+    line_length: usize,
+    data: Vec<u8>,
+    resolution: Resolution,
+    label: Option<constants::Label>,
+}
+impl RasterLines {
+    /// Creates an empty set of raster lines that are each `line_length` bytes long
+    pub fn new(line_length: usize) -> Self {
+        RasterLines {
+            line_length,
+            data: Vec::new(),
+            resolution: Resolution::Standard,
+            label: None,
+        }
+    }
+    /// Takes lines that are already packed (see `text::pack_raster_line()`), e.g. by another imaging pipeline, one
+    /// after the other in `data`. Panics if `line_length` is 0 or `data` isn't a whole number of lines.
+    pub fn from_packed(line_length: usize, data: Vec<u8>) -> Self {
+        assert!(
+            line_length > 0 && data.len().is_multiple_of(line_length),
+            "Raster data isn't made of whole lines"
+        );
+        RasterLines {
+            data,
+            ..RasterLines::new(line_length)
+        }
+    }
+    /// Length of each line in bytes
+    pub fn line_length(&self) -> usize {
+        self.line_length
+    }
+    /// The resolution these lines should be printed at
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+    /// Mark these lines as being for the given resolution. `Resolution::High` lines take up half as much tape each,
+    /// so an image needs twice as many of them to print at the same length.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+    /// The label these lines were laid out for, if known
+    pub fn label(&self) -> Option<&constants::Label> {
+        self.label.as_ref()
+    }
+    /// Mark these lines as laid out for `label`. Printing them on any other size of label then fails with
+    /// `ErrorKind::MediaMismatch` (see `ThermalPrinter::set_check_media()`).
+    pub fn set_label(&mut self, label: constants::Label) {
+        self.label = Some(label);
+    }
+    /// Number of lines
+    pub fn len(&self) -> usize {
+        self.data.len() / self.line_length.max(1)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// The `(length, width)` in dots of the image these lines print, the same as `dimensions()` of the image they
+    /// were made from. The length is the number of lines (twice the 300dpi length for `Resolution::High`). The width
+    /// is the label's `raster_width()` if the lines were laid out for a label, otherwise every dot of the lines.
+    pub fn dimensions(&self) -> (u32, u32) {
+        let width = match self.label {
+            Some(label) => label.raster_width(),
+            None => self.line_length as u32 * 8,
+        };
+        (self.len() as u32, width)
+    }
+    /// Appends a line to the end of the image. Panics if `line` isn't exactly `line_length()` bytes long.
+    pub fn push(&mut self, line: &[u8]) {
+        assert_eq!(
+            line.len(),
+            self.line_length,
+            "Raster line has the wrong length"
+        );
+        self.data.extend_from_slice(line);
+    }
+    /// Returns the line at `index`, if there is one
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let start = index.checked_mul(self.line_length)?;
+        self.data.get(start..start + self.line_length)
+    }
+    /// Iterates over the lines in the order they will be printed
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks(self.line_length.max(1))
+    }
+}
+/// Raster lines for the black and red planes of an image printed on two-color media
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoColorRasterLines {
+    pub black: RasterLines,
+    pub red: RasterLines,
+}
+
+impl From<Vec<[u8; constants::RASTER_LINE_LENGTH]>> for RasterLines {
+    fn from(lines: Vec<[u8; constants::RASTER_LINE_LENGTH]>) -> Self {
+        RasterLines {
+            line_length: constants::RASTER_LINE_LENGTH,
+            data: lines.concat(),
+            resolution: Resolution::Standard,
+            label: None,
+        }
+    }
+}
+impl From<&[[u8; constants::RASTER_LINE_LENGTH]]> for RasterLines {
+    fn from(lines: &[[u8; constants::RASTER_LINE_LENGTH]]) -> Self {
+        RasterLines {
+            line_length: constants::RASTER_LINE_LENGTH,
+            data: lines.concat(),
+            resolution: Resolution::Standard,
+            label: None,
+        }
+    }
+}
+
+/// Raster data is sent to the printer in USB writes of at most this many bytes by default
+const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
 
-/// The primary interface for dealing with Brother QL printers. Handles all USB communication with the printer.
-pub struct ThermalPrinter<T: rusb::UsbContext> {
+/// How long `ThermalPrinter::print_blocking()` waits for the printer to report that it has finished printing
+const PRINT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The primary interface for dealing with Brother QL printers. Sends commands to the printer over USB, or over any
+/// other `PrinterBackend`.
+pub struct ThermalPrinter<B: PrinterBackend = UsbBackend<rusb::GlobalContext>> {
     pub manufacturer: String,
     pub model: String,
     pub serial_number: String,
-    handle: rusb::DeviceHandle<T>,
-    in_endpoint: u8,
-    out_endpoint: u8,
+    backend: B,
+    product_id: u16,
+    raster_line_length: usize,
+    compression: bool,
+    cut_behavior: CutBehavior,
+    feed_margin: Option<u16>,
+    chunk_size: usize,
+    check_media: bool,
+    safe_margins: (u32, u32),
+    quality_priority: bool,
+    chain_printing: bool,
+    mode_command: Option<commands::ModeCommand>,
 }
-impl<T: rusb::UsbContext> std::fmt::Debug for ThermalPrinter<T> {
+impl<B: PrinterBackend> std::fmt::Debug for ThermalPrinter<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -103,264 +806,1913 @@ impl<T: rusb::UsbContext> std::fmt::Debug for ThermalPrinter<T> {
         )
     }
 }
-impl<T: rusb::UsbContext> ThermalPrinter<T> {
+impl ThermalPrinter {
+    /// List every attached and supported Brother QL printer along with its model, serial number, and USB location.
+    pub fn enumerate() -> Vec<PrinterInfo> {
+        printers()
+            .into_iter()
+            .filter_map(|device| {
+                let descriptor = device.device_descriptor().ok()?;
+                let serial_number = device
+                    .open()
+                    .ok()
+                    .and_then(|handle| handle.read_serial_number_string_ascii(&descriptor).ok());
+                Some(PrinterInfo {
+                    model: constants::printer_name_from_id(descriptor.product_id())?,
+                    serial_number,
+                    bus_number: device.bus_number(),
+                    address: device.address(),
+                    device,
+                })
+            })
+            .collect()
+    }
+
+    /// Connect to the attached printer with the given serial number.
+    pub fn open_by_serial(serial_number: &str) -> Result<Self> {
+        Self::enumerate()
+            .into_iter()
+            .find(|printer| printer.serial_number.as_deref() == Some(serial_number))
+            .ok_or_else(|| format!("No printer found with serial number {}", serial_number))?
+            .open()
+    }
+}
+impl<T: rusb::UsbContext> ThermalPrinter<UsbBackend<T>> {
     /// Create a new `ThermalPrinter` instance using a `rusb` USB device handle.
     ///
-    /// Obtain list of connected device handles by calling `printers()`.
+    /// Obtain list of connected device handles by calling `printers()` or `ThermalPrinter::enumerate()`.
     pub fn new(device: rusb::Device<T>) -> Result<Self> {
-        let mut handle = device.open()?;
-        let mut in_endpoint: Option<u8> = None;
-        let mut out_endpoint: Option<u8> = None;
-
-        let config = device.active_config_descriptor()?;
-        let interface = config
-            .interfaces()
-            .next()
-            .chain_err(|| "Brother QL printers should have exactly one interface")?;
-        let interface_descriptor = interface
-            .descriptors()
-            .next()
-            .chain_err(|| "Brother QL printers should have exactly one interface descriptor")?;
-        for endpoint in interface_descriptor.endpoint_descriptors() {
-            if endpoint.transfer_type() != rusb::TransferType::Bulk {
-                bail!("Brother QL printers are defined as using only bulk endpoint communication");
-            }
-            match endpoint.direction() {
-                rusb::Direction::In => in_endpoint = Some(endpoint.address()),
-                rusb::Direction::Out => out_endpoint = Some(endpoint.address()),
-            }
-        }
-        if in_endpoint.is_none() || out_endpoint.is_none() {
-            bail!("Input or output endpoint not found");
-        }
-
-        if let Ok(kd_active) = handle.kernel_driver_active(interface.number()) {
-            if kd_active {
-                handle.detach_kernel_driver(interface.number())?;
-            }
-        }
-        handle.claim_interface(interface.number())?;
+        Self::with_usb_settings(device, &backend::UsbSettings::default())
+    }
 
+    /// Same as `new()` but opens the device with the USB interface and endpoints chosen in `settings` (see
+    /// `UsbBackend::open_with()`)
+    pub fn with_usb_settings(
+        device: rusb::Device<T>,
+        settings: &backend::UsbSettings,
+    ) -> Result<Self> {
+        let backend = UsbBackend::open_with(&device, settings)?;
         let device_descriptor = device.device_descriptor()?;
+        let handle = backend.handle();
+        let manufacturer = handle.read_manufacturer_string_ascii(&device_descriptor)?;
+        let model = handle.read_product_string_ascii(&device_descriptor)?;
+        let serial_number = handle.read_serial_number_string_ascii(&device_descriptor)?;
+
+        let mut printer = ThermalPrinter::with_backend(backend, device_descriptor.product_id())?;
+        printer.manufacturer = manufacturer;
+        printer.model = model;
+        printer.serial_number = serial_number;
+        Ok(printer)
+    }
 
+    /// How long a single USB read or write may take before failing with `ErrorKind::Timeout`. Defaults to 500ms.
+    pub fn set_usb_timeout(&mut self, timeout: Duration) {
+        self.backend.set_timeout(timeout);
+    }
+
+    /// How many times a USB read or write is retried after the endpoint stalls (`LIBUSB_ERROR_PIPE`). The stall is
+    /// cleared before each retry. Defaults to 0.
+    pub fn set_usb_retries(&mut self, retries: u8) {
+        self.backend.set_retries(retries);
+    }
+}
+impl<B: PrinterBackend> ThermalPrinter<B> {
+    /// Create a `ThermalPrinter` that sends its commands to `backend`, e.g. a `MemoryBackend` in tests. `product_id`
+    /// is the USB product ID of the model to act as (see `constants::printer_name_from_id()`).
+    ///
+    /// The printer is reset and its status is read, just like `new()` does.
+    pub fn with_backend(backend: B, product_id: u16) -> Result<Self> {
+        let model = constants::Model::from_product_id(product_id);
+        let capabilities = model.capabilities();
         let printer = ThermalPrinter {
-            manufacturer: handle.read_manufacturer_string_ascii(&device_descriptor)?,
-            model: handle.read_product_string_ascii(&device_descriptor)?,
-            serial_number: handle.read_serial_number_string_ascii(&device_descriptor)?,
-            handle,
-            in_endpoint: in_endpoint.unwrap(),
-            out_endpoint: out_endpoint.unwrap(),
+            manufacturer: "Brother".to_string(),
+            model: model.name().to_string(),
+            serial_number: String::new(),
+            backend,
+            product_id,
+            raster_line_length: model.raster_line_length(),
+            compression: false,
+            cut_behavior: if capabilities.auto_cut {
+                CutBehavior::EachLabel
+            } else {
+                CutBehavior::None
+            },
+            feed_margin: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            check_media: true,
+            safe_margins: (0, 0),
+            quality_priority: true,
+            chain_printing: false,
+            mode_command: None,
         };
 
-        // Reset printer
+        printer.reset()?;
+        Ok(printer)
+    }
+
+    /// Clears out any half received command with 200 `0x00` bytes (the invalidate command) and sends `ESC @`, which
+    /// resets the printer's settings to their defaults. Every job sent by this crate starts out in this state.
+    pub fn initialize(&self) -> Result<()> {
         let clear_command = [0x00; 200];
-        ThermalPrinter::write(&printer, &clear_command)?;
+        self.write(&clear_command)?;
         let initialize_command = [0x1B, 0x40];
-        ThermalPrinter::write(&printer, &initialize_command)?;
+        self.write(&initialize_command)
+    }
 
-        ThermalPrinter::get_status(&printer)?;
-        Ok(printer)
+    /// Initializes the printer (see `initialize()`) and reads its status to check that it responds, e.g. to recover
+    /// after a job stalled halfway or as a health check. This is what `new()` does when connecting.
+    pub fn reset(&self) -> Result<status::Response> {
+        self.initialize()?;
+        self.get_status()
+    }
+
+    /// The backend commands are sent to
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
     /// Sends raster lines to the USB printer, begins printing, and immediately returns
     ///
     /// Images on the label tape are comprised of bits representing either black (`1`) or white (`0`). They are
-    /// arranged in lines of a static width that corresponds to the width of the printer's thermal print head.
+    /// arranged in lines of a static width that corresponds to the width of the printer's thermal print head
+    /// (see `raster_line_length()`). Lines shorter than that are padded with white at the far edge, so 90 byte
+    /// lines meant for a QL-700 also print on wide format printers.
     ///
     /// **Note:** the raster line width does not change for label media of different sizes. This means the
     /// printer can print out-of-bounds and even print on parts of the label not originally intended to
     /// contain content. Your rasterizer will have to figure out, given a media type, which parts of the
     /// image will appear on the media and resize or shift margins and content accordingly.
-    pub fn print(
+    ///
+    /// The printer's status is checked first. If it reports an error (e.g. the cover is open or no media is loaded)
+    /// nothing is sent and `ErrorKind::Printer` is returned instead. See `wait_until_ready()` to wait for the
+    /// problem to be fixed.
+    pub fn print<L: Into<RasterLines>>(&self, raster_lines: L) -> Result<status::Response> {
+        self.print_planes(&[&raster_lines.into()], &mut |_, _| {})
+            .map(Into::into)
+    }
+    /// Same as `print()` but calls `on_progress(lines_sent, total_lines)` whenever a chunk of raster data (see
+    /// `set_chunk_size()`) has been sent, e.g. to update a progress bar while a long label is transmitted. The last
+    /// call reports all lines as sent.
+    pub fn print_with_progress<L, F>(
         &self,
-        raster_lines: Vec<[u8; RASTER_LINE_LENGTH as usize]>,
-    ) -> Result<status::Response> {
-        let status = self.get_status()?;
-
-        let mode_command = [0x1B, 0x69, 0x61, 1];
-        self.write(&mode_command)?;
-
-        const VALID_FLAGS: u8 = 0x80 | 0x02 | 0x04 | 0x08 | 0x40; // Everything enabled
-        let media_type: u8 = match status.media.media_type {
-            status::MediaType::ContinuousTape => 0x0A,
-            status::MediaType::DieCutLabels => 0x0B,
-            _ => return Err("No media loaded into printer".into()),
+        raster_lines: L,
+        mut on_progress: F,
+    ) -> Result<status::Response>
+    where
+        L: Into<RasterLines>,
+        F: FnMut(usize, usize),
+    {
+        self.print_planes(&[&raster_lines.into()], &mut on_progress)
+            .map(Into::into)
+    }
+    /// Prints `text` on the loaded label in one go: the label is detected (unless `opts` names one), the text is
+    /// rasterized with a `TextRasterizer` and the result is printed. Use `TextRasterizer` directly for more control
+    /// over the layout.
+    pub fn print_text(&mut self, text: &str, opts: &TextPrintOptions) -> Result<()> {
+        let label = match opts.label {
+            Some(label) => label,
+            None => self.detect_label()?,
         };
+        if let Some(cut_behavior) = opts.cut_behavior {
+            self.set_cut_behavior(cut_behavior);
+        }
+        let rasterizer = opts.rasterizer(label);
+        let lines = rasterizer.try_rasterize(
+            text,
+            opts.secondary_text.as_deref(),
+            opts.font_scale,
+            opts.invert,
+        )?;
+        self.print(lines)?;
+        Ok(())
+    }
+    /// Prints a numbered label for every counter value from `start` to `start + count - 1` in one job, e.g.
+    /// `"ASSET-{n:04}"` for labels `ASSET-0001`, `ASSET-0002` and so on. `{n}` in `template` is replaced with the
+    /// counter value, `{n:05}` pads it with zeros to 5 digits and `{n:5}` with spaces.
+    ///
+    /// Every label is measured first and all of them are printed at the size of the one that had to shrink the most,
+    /// so the numbers line up across the series. The secondary text, if any, is printed unchanged on every label.
+    pub fn print_series(
+        &mut self,
+        template: &str,
+        start: u64,
+        count: u64,
+        opts: &TextPrintOptions,
+    ) -> Result<()> {
+        let end = start
+            .checked_add(count)
+            .chain_err(|| "The counter of the series doesn't fit in a u64")?;
+        let texts = (start..end)
+            .map(|n| format_counter(template, n))
+            .collect::<Result<Vec<_>>>()?;
+        let label = match opts.label {
+            Some(label) => label,
+            None => self.detect_label()?,
+        };
+        if let Some(cut_behavior) = opts.cut_behavior {
+            self.set_cut_behavior(cut_behavior);
+        }
 
-        let mut media_command = [
-            0x1B,
-            0x69,
-            0x7A,
-            VALID_FLAGS,
-            media_type,
-            status.media.width,
-            status.media.length,
-            0,
-            0,
-            0,
-            0,
-            0x01,
-            0,
-        ];
-        let line_count = (raster_lines.len() as u32).to_le_bytes();
-        media_command[7..7 + 4].copy_from_slice(&line_count);
-        self.write(&media_command)?;
-
-        self.write(&[0x1B, 0x69, 0x4D, 1 << 6])?; // Enable auto-cut
-        self.write(&[0x1B, 0x69, 0x4B, 1 << 3 | 0 << 6])?; // Enable cut-at-end and disable high res printing
-
-        let label = self.current_label()?;
-
-        let margins_command = [0x1B, 0x69, 0x64, label.feed_margin, 0];
-        self.write(&margins_command)?;
-
-        for line in raster_lines.iter() {
-            let mut raster_command = vec![0x67, 0x00, RASTER_LINE_LENGTH];
-            raster_command.extend_from_slice(line);
-            self.write(&raster_command)?;
+        let mut rasterizer = opts.rasterizer(label);
+        let max_font_size = opts.max_font_size();
+        let mut font_size = max_font_size;
+        for text in &texts {
+            font_size = font_size.min(rasterizer.measure(text, max_font_size)?);
         }
+        rasterizer.set_font_sizing(FontSizing::AutoShrink { max: font_size });
+        let labels = texts
+            .iter()
+            .map(|text| {
+                rasterizer.try_rasterize(
+                    text,
+                    opts.secondary_text.as_deref(),
+                    opts.font_scale,
+                    opts.invert,
+                )
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        self.print_labels(labels)?;
+        Ok(())
+    }
+    /// Checks that `texts` could be printed with `opts` as one job the way `print_labels()` prints them, without
+    /// printing anything. The printer's status is checked once as it would be before the job, then every label is
+    /// rasterized, checked against the loaded media and encoded into print commands. This catches errors like fonts
+    /// that can't be loaded, text that doesn't fit, a printer that's out of tape and the wrong media for
+    /// `opts.label`.
+    ///
+    /// Nothing but a single status request is sent to the printer. The report lists the font size each label ended
+    /// up at along with any warnings, e.g. text smaller than `opts.min_readable_font_size`.
+    pub fn validate_text(&self, texts: &[&str], opts: &TextPrintOptions) -> Result<JobReport> {
+        let (loaded, media) = self.check_job(opts.label.as_ref(), false, false)?;
+        let label = opts.label.unwrap_or(loaded);
+        let mut rasterizer = opts.rasterizer(label);
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let handler_warnings = Arc::clone(&warnings);
+        rasterizer.set_warning_handler(move |warning| {
+            handler_warnings.lock().unwrap().push(warning.clone());
+        });
 
-        let print_command = [0x1A];
-        self.write(&print_command)?;
+        let mut report = JobReport {
+            label,
+            labels: Vec::new(),
+            job_size: 0,
+        };
+        let mut line_data = vec![0; self.raster_line_length];
+        let mut job = Vec::new();
+        for (index, text) in texts.iter().enumerate() {
+            let (lines, font_size) = rasterizer.try_rasterize_measured(
+                text,
+                opts.secondary_text.as_deref(),
+                opts.font_scale,
+                opts.invert,
+            )?;
+            self.check_line_length(lines.line_length())?;
+            let high_resolution = lines.resolution() == Resolution::High;
+            self.check_capabilities(false, high_resolution)?;
+            self.check_media(lines.label(), &loaded)?;
+            job.extend(self.job_commands(
+                &loaded,
+                media,
+                lines.len() as u32,
+                false,
+                high_resolution,
+                index == 0,
+            ));
+            for (line_index, line) in lines.iter().enumerate() {
+                let line = self.safe_line(line, line_index, lines.len());
+                encode_line(line, None, self.compression, &mut line_data, &mut job);
+            }
+            job.push(if index + 1 < texts.len() { 0x0C } else { 0x1A });
+            report.job_size += job.len();
+            job.clear();
 
-        self.read()
+            let label_warnings: Vec<RasterizeWarning> =
+                warnings.lock().unwrap().drain(..).collect();
+            report.labels.push(LabelReport {
+                text: text.to_string(),
+                font_size,
+                line_count: lines.len(),
+                warnings: label_warnings,
+            });
+        }
+        Ok(report)
     }
-    /// Same as `print()` but will not return until the printer reports that it has finished printing.
-    pub fn print_blocking(
-        &self,
-        raster_lines: Vec<[u8; RASTER_LINE_LENGTH as usize]>,
-    ) -> Result<()> {
-        self.print(raster_lines)?;
-        loop {
-            match self.read() {
-                Ok(ref response)
-                    if response.status_type == status::StatusType::PrintingCompleted =>
-                {
-                    break
-                }
-                _ => thread::sleep(Duration::from_millis(50)),
-            }
+    /// Prints the image file at `path` (any format the `image` crate can open) on its own label. The image is
+    /// converted to grayscale, scaled to the printable width keeping its aspect ratio and dithered. Its x axis runs
+    /// along the tape, see `text::raster_from_image_file()`.
+    pub fn print_image_file(&mut self, path: &Path, opts: &ImagePrintOptions) -> Result<()> {
+        let label = match opts.label {
+            Some(label) => label,
+            None => self.detect_label()?,
+        };
+        if let Some(cut_behavior) = opts.cut_behavior {
+            self.set_cut_behavior(cut_behavior);
         }
+        let lines = crate::text::raster_from_image_file(path, &label, opts.fit, opts.dithering)?;
+        self.print(lines)?;
         Ok(())
     }
-
-    /// Get the currently loaded label size.
-    pub fn current_label(&self) -> Result<constants::Label> {
-        let media = self.get_status()?.media;
-        constants::label_data(
-            media.width,
-            match media.length {
-                0 => None,
-                _ => Some(media.length),
-            },
-        )
-        .ok_or("Unknown media loaded in printer".into())
+    /// Prints a label designed as an SVG on its own label, scaled to the printable width the way
+    /// `print_image_file()` scales images (see `text::svg::raster_from_svg()`). Needs the `svg` feature.
+    #[cfg(feature = "svg")]
+    pub fn print_svg(&mut self, svg_data: &str, opts: &ImagePrintOptions) -> Result<()> {
+        let label = match opts.label {
+            Some(label) => label,
+            None => self.detect_label()?,
+        };
+        if let Some(cut_behavior) = opts.cut_behavior {
+            self.set_cut_behavior(cut_behavior);
+        }
+        let lines = crate::text::svg::raster_from_svg(svg_data, &label, opts.fit, opts.dithering)?;
+        self.print(lines)?;
+        Ok(())
     }
-
-    /// Get the current status of the printer including possible errors, media type, and model name.
-    pub fn get_status(&self) -> Result<status::Response> {
-        let status_command = [0x1B, 0x69, 0x53];
-        self.write(&status_command)?;
-        self.read()
+    /// Same as `print()` but prints in black and red on DK-22251 two-color tape loaded in a QL-800 series printer.
+    /// Both sets of raster lines must have the same number of lines. Fails with `MediaMismatch` if the loaded media
+    /// isn't the size of DK-22251 tape.
+    pub fn print_two_color(&self, raster_lines: TwoColorRasterLines) -> Result<status::Response> {
+        if raster_lines.black.len() != raster_lines.red.len() {
+            bail!(
+                "Two-color images need the same number of black ({}) and red ({}) raster lines",
+                raster_lines.black.len(),
+                raster_lines.red.len()
+            );
+        }
+        self.print_planes(&[&raster_lines.black, &raster_lines.red], &mut |_, _| {})
+            .map(Into::into)
     }
 
-    fn read(&self) -> Result<status::Response> {
-        const RECEIVE_SIZE: usize = 32;
-        let mut response = [0; RECEIVE_SIZE];
-        let bytes_read =
-            self.handle
-                .read_bulk(self.in_endpoint, &mut response, Duration::from_millis(500))?;
-
-        if bytes_read != RECEIVE_SIZE || response[0] != 0x80 {
-            return Err("Invalid response received from printer".into());
-        }
-
-        let model = match response[4] {
-            0x4F => "QL-500/550",
-            0x31 => "QL-560",
-            0x32 => "QL-570",
-            0x33 => "QL-580N",
-            0x51 => "QL-650TD",
-            0x35 => "QL-700",
-            0x50 => "QL-1050",
-            0x34 => "QL-1060N",
-            _ => "Unknown",
+    /// Same as `print()` but takes the raster lines from an iterator and sends each line to the printer as soon as
+    /// it's produced instead of holding the whole image in memory. Lines may be any length up to
+    /// `raster_line_length()` and are printed at standard resolution.
+    ///
+    /// The stream is bracketed the same way as every other print job. Before the first line the printer is switched
+    /// to raster mode and sent the media information, auto-cut, expanded mode (cut at end), feed margin and, if
+    /// enabled, compression commands. After the last line the "print with feeding" command (`0x1A`) feeds and cuts
+    /// the label. The media information includes the number of lines if the iterator reports an exact
+    /// `size_hint()`, otherwise it is sent as 0.
+    ///
+    /// A first line that's too long fails the job before anything is sent. Since earlier lines may already have been
+    /// sent by the time a later one turns out to be too long, the printer is initialized (see `initialize()`) before
+    /// returning that error so it isn't left waiting for the rest of the job.
+    pub fn print_iter<I>(&self, raster_lines: I) -> Result<status::Response>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut raster_lines = raster_lines.into_iter().peekable();
+        if let Some(line) = raster_lines.peek() {
+            self.check_line_length(line.as_ref().len())?;
+        }
+        let line_count = match raster_lines.size_hint() {
+            (lower, Some(upper)) if lower == upper => lower as u32,
+            _ => 0,
         };
+        self.start_job(None, line_count, false, false, true)?;
+        // Without a length only the start of the page can be kept blank
+        let known_count = match line_count {
+            0 => usize::MAX,
+            count => count as usize,
+        };
+        let mut line_data = vec![0; self.raster_line_length];
+        let mut raster_data = Vec::with_capacity(self.chunk_size);
+        for (index, line) in raster_lines.enumerate() {
+            let line = line.as_ref();
+            if let Err(error) = self.check_line_length(line.len()) {
+                self.initialize()?;
+                return Err(error);
+            }
+            encode_line(
+                self.safe_line(line, index, known_count),
+                None,
+                self.compression,
+                &mut line_data,
+                &mut raster_data,
+            );
+            self.write_chunks(&mut raster_data, false)?;
+        }
+        self.write_chunks(&mut raster_data, true)?;
+        self.finish_job().map(Into::into)
+    }
 
-        let mut errors = Vec::new();
+    /// Fails if raster lines of `line_length` bytes don't fit this printer's print head
+    fn check_line_length(&self, line_length: usize) -> Result<()> {
+        if line_length > self.raster_line_length {
+            bail!(
+                "Raster lines are {} bytes long but the {} only accepts {} bytes",
+                line_length,
+                self.model,
+                self.raster_line_length
+            );
+        }
+        Ok(())
+    }
 
-        fn error_if(byte: u8, flag: u8, message: &'static str, errors: &mut Vec<&'static str>) {
-            if byte & flag != 0 {
-                errors.push(message);
-            }
+    /// Prints one (black) or two (black and red) planes of raster lines, reporting progress to `on_progress`
+    fn print_planes(
+        &self,
+        planes: &[&RasterLines],
+        on_progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<status::PrinterStatus> {
+        for plane in planes {
+            self.check_line_length(plane.line_length())?;
         }
-        error_if(response[8], 0x01, "No media when printing", &mut errors);
-        error_if(response[8], 0x02, "End of media", &mut errors);
-        error_if(response[8], 0x04, "Tape cutter jam", &mut errors);
-        error_if(response[8], 0x10, "Main unit in use", &mut errors);
-        error_if(response[8], 0x80, "Fan doesn't work", &mut errors);
-        error_if(response[9], 0x04, "Transmission error", &mut errors);
-        error_if(response[9], 0x10, "Cover open", &mut errors);
-        error_if(response[9], 0x40, "Cannot feed", &mut errors);
-        error_if(response[9], 0x80, "System error", &mut errors);
-
-        let width = response[10];
-        let length = response[17];
-
-        let media_type = match response[11] {
-            0x0A => status::MediaType::ContinuousTape,
-            0x0B => status::MediaType::DieCutLabels,
-            _ => status::MediaType::None,
-        };
+        let two_color = planes.len() > 1;
+        let high_resolution = planes[0].resolution() == Resolution::High;
+        if two_color && high_resolution {
+            bail!("High resolution printing isn't supported in two-color mode");
+        }
+        self.start_job(
+            planes[0].label(),
+            planes[0].len() as u32,
+            two_color,
+            high_resolution,
+            true,
+        )?;
 
-        let status_type = match response[18] {
-            0x00 => status::StatusType::ReplyToStatusRequest,
-            0x01 => status::StatusType::PrintingCompleted,
-            0x02 => status::StatusType::ErrorOccurred,
-            0x05 => status::StatusType::Notification,
-            0x06 => status::StatusType::PhaseChange,
-            // Will never occur
-            _ => status::StatusType::Notification,
+        let line_count = planes[0].len();
+        let mut line_data = vec![0; self.raster_line_length];
+        let mut raster_data = Vec::with_capacity(self.chunk_size);
+        // Where each line not yet known to be sent ends in the stream of raster data
+        let mut line_ends = VecDeque::new();
+        let (mut bytes_sent, mut lines_sent) = (0, 0);
+        // Called after each line is encoded and once more to flush what's left
+        let mut send = |raster_data: &mut Vec<u8>, flush: bool| -> Result<()> {
+            if !flush {
+                line_ends.push_back(bytes_sent + raster_data.len());
+            }
+            let sent = self.write_chunks(raster_data, flush)?;
+            if sent > 0 {
+                bytes_sent += sent;
+                while line_ends.front().is_some_and(|&end| end <= bytes_sent) {
+                    line_ends.pop_front();
+                    lines_sent += 1;
+                }
+                on_progress(lines_sent, line_count);
+            }
+            Ok(())
         };
+        for index in 0..line_count {
+            for (plane_index, plane) in planes.iter().enumerate() {
+                let line = self.safe_line(plane.get(index).unwrap_or(&[]), index, line_count);
+                // Two-color lines are sent as a black (0x01) followed by a red (0x02) plane
+                let plane_number = if two_color {
+                    Some(plane_index as u8 + 1)
+                } else {
+                    None
+                };
+                encode_line(
+                    line,
+                    plane_number,
+                    self.compression,
+                    &mut line_data,
+                    &mut raster_data,
+                );
+            }
+            send(&mut raster_data, false)?;
+        }
+        send(&mut raster_data, true)?;
 
-        Ok(status::Response {
-            model,
-            status_type,
-            errors,
-            media: status::Media {
-                media_type,
-                width,
-                length,
-            },
-        })
+        self.finish_job()
     }
 
-    fn write(&self, data: &[u8]) -> Result<()> {
-        self.handle
-            .write_bulk(self.out_endpoint, data, Duration::from_millis(500))?;
-        Ok(())
+    /// Sends the commands that set up a page of `line_count` raster lines. Fails without sending anything if the
+    /// printer reports an error or, unless disabled, the lines were laid out for a different `expected` label.
+    fn start_job(
+        &self,
+        expected: Option<&constants::Label>,
+        line_count: u32,
+        two_color: bool,
+        high_resolution: bool,
+        first_page: bool,
+    ) -> Result<()> {
+        let (label, media) = self.check_job(expected, two_color, high_resolution)?;
+        self.write(&self.job_commands(
+            &label,
+            media,
+            line_count,
+            two_color,
+            high_resolution,
+            first_page,
+        ))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::printer::{printers, ThermalPrinter};
-    #[test]
-    fn connect() {
-        let printer_list = printers();
-        assert!(printer_list.len() > 0, "No printers found");
-        let mut printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
-        printer.init().unwrap();
+    /// Checks that a page with these settings can be printed, as described in `start_job()`, and returns the label
+    /// that's loaded along with the media as the printer reported it
+    fn check_job(
+        &self,
+        expected: Option<&constants::Label>,
+        two_color: bool,
+        high_resolution: bool,
+    ) -> Result<(constants::Label, MediaInfo)> {
+        self.check_capabilities(two_color, high_resolution)?;
+        let status = self.status()?;
+        if let Some(&error) = status.errors.first() {
+            bail!(ErrorKind::Printer(error));
+        }
+        if let status::MediaType::None = status.media_type {
+            bail!(ErrorKind::Printer(status::PrinterError::NoMedia));
+        }
+        let label = Self::loaded_label(&status)?;
+        self.check_media(expected, &label)?;
+        if two_color {
+            let two_color_tape =
+                constants::label_by_name("DK-22251").expect("DK-22251 is a known label");
+            if label.tape_size != two_color_tape.tape_size {
+                bail!(ErrorKind::MediaMismatch(two_color_tape.name, label.name));
+            }
+        }
+        Ok((label, MediaInfo::reported(&status)))
+    }
+
+    /// Fails with `MediaMismatch` if lines laid out for `expected` would be printed on the `loaded` label, unless
+    /// disabled with `set_check_media()`
+    fn check_media(
+        &self,
+        expected: Option<&constants::Label>,
+        loaded: &constants::Label,
+    ) -> Result<()> {
+        if let Some(&expected) = expected {
+            if self.check_media && expected.tape_size != loaded.tape_size {
+                bail!(ErrorKind::MediaMismatch(expected.name, loaded.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a job with these settings and the printer's compression and cut behavior needs a feature the model
+    /// doesn't have (see `capabilities()`) instead of sending commands it doesn't understand
+    fn check_capabilities(&self, two_color: bool, high_resolution: bool) -> Result<()> {
+        let capabilities = self.capabilities();
+        let (auto_cut, _, cut_at_end) = self.cut_behavior.settings();
+        let unsupported = [
+            (two_color && !capabilities.two_color, "two-color printing"),
+            (
+                high_resolution && !capabilities.high_resolution,
+                "high resolution printing",
+            ),
+            (self.compression && !capabilities.compression, "compression"),
+            (
+                (auto_cut || cut_at_end) && !capabilities.auto_cut,
+                "cutting the tape (use CutBehavior::None)",
+            ),
+        ];
+        if let Some((_, feature)) = unsupported.iter().find(|(unsupported, _)| *unsupported) {
+            bail!("The {} doesn't support {}", self.model, feature);
+        }
+        Ok(())
+    }
+
+    /// The commands that set up a page of `line_count` raster lines on `label`: raster mode, media information,
+    /// auto-cut, expanded mode, feed margin and compression
+    fn job_commands(
+        &self,
+        label: &constants::Label,
+        media: MediaInfo,
+        line_count: u32,
+        two_color: bool,
+        high_resolution: bool,
+        first_page: bool,
+    ) -> Vec<u8> {
+        let mut commands = vec![0x1B, 0x69, 0x61, 1]; // Raster mode
+
+        let (auto_cut, cut_every, cut_at_end) = self.cut_behavior.settings();
+        let mode = self.mode_command.unwrap_or_else(|| {
+            commands::ModeCommand::new()
+                .auto_cut(auto_cut)
+                // Cutting at the end of the job is what turns chain printing off
+                .cut_at_end(cut_at_end && !self.chain_printing)
+                .high_resolution(high_resolution)
+                .two_color(two_color)
+                .quality_priority(self.quality_priority)
+        });
+        commands.extend_from_slice(&print_information_command(
+            media,
+            line_count,
+            first_page,
+            mode.is_quality_priority(),
+        ));
+
+        commands.extend_from_slice(&[0x1B, 0x69, 0x4D, mode.various_mode()]);
+        if mode.is_auto_cut() {
+            commands.extend_from_slice(&[0x1B, 0x69, 0x41, cut_every]);
+        }
+        commands.extend_from_slice(&[0x1B, 0x69, 0x4B, mode.expanded_mode()]);
+
+        commands.extend_from_slice(&margin_command(label, self.feed_margin));
+
+        if self.compression {
+            commands.extend_from_slice(&[0x4D, 0x02]); // Enable TIFF (PackBits) compression
+        }
+        commands
+    }
+
+    /// Writes the complete job that would print `lines` on `opts.label` to `writer` instead of sending it to the
+    /// printer: the reset and initialize commands, a status request, the page setup (see `print_iter()`), the raster
+    /// lines and the "print with feeding" command that feeds and cuts the label. The printer's settings (compression,
+    /// cut behavior, feed margin) are used but nothing is sent to it.
+    ///
+    /// The result can be sent to a printer by other means, e.g. `cat job.bin > /dev/usb/lp0`, or compared against
+    /// a job captured from another driver.
+    pub fn print_to_writer<W: Write, L: Into<RasterLines>>(
+        &self,
+        writer: &mut W,
+        lines: L,
+        opts: &PrintOptions,
+    ) -> Result<()> {
+        let lines = lines.into();
+        self.check_line_length(lines.line_length())?;
+        let high_resolution = lines.resolution() == Resolution::High;
+        self.check_capabilities(false, high_resolution)?;
+        let mut job = vec![0x00; 200]; // Reset
+        job.extend_from_slice(&[0x1B, 0x40]); // Initialize
+        job.extend_from_slice(&[0x1B, 0x69, 0x53]); // Status request
+        job.extend(self.job_commands(
+            &opts.label,
+            MediaInfo::of_label(&opts.label),
+            lines.len() as u32,
+            false,
+            high_resolution,
+            true,
+        ));
+        let mut line_data = vec![0; self.raster_line_length];
+        for (index, line) in lines.iter().enumerate() {
+            let line = self.safe_line(line, index, lines.len());
+            encode_line(line, None, self.compression, &mut line_data, &mut job);
+        }
+        job.push(0x1A); // Print with feeding
+        writer.write_all(&job)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Prints a batch of labels over the open connection, cutting them according to `cut`, which also stays the cut
+    /// behavior for later jobs. The labels are sent as one job (see `print_labels()`) so the printer doesn't pause
+    /// between them, which is a lot faster than printing them one at a time.
+    pub fn print_batch<I, L>(&mut self, jobs: I, cut: CutBehavior) -> Result<status::Response>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<RasterLines>,
+    {
+        self.set_cut_behavior(cut);
+        self.print_labels(jobs)
+    }
+
+    /// Prints several labels in one job. Each label is a page of its own and the tape is cut between them according
+    /// to `set_cut_behavior()`. The printer's status is only asked for once, before anything is sent, and every page
+    /// is checked against the media it reports.
+    pub fn print_labels<I, L>(&self, labels: I) -> Result<status::Response>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<RasterLines>,
+    {
+        let labels: Vec<RasterLines> = labels.into_iter().map(Into::into).collect();
+        if labels.is_empty() {
+            bail!("No labels to print");
+        }
+        for label in &labels {
+            self.check_line_length(label.line_length())?;
+            self.check_capabilities(false, label.resolution() == Resolution::High)?;
+        }
+        // The status is only asked for before the first page. Once a page is printing the printer sends status
+        // updates of its own, which would be read as the answer to a status request sent between pages.
+        let (loaded, media) = self.check_job(
+            labels[0].label(),
+            false,
+            labels[0].resolution() == Resolution::High,
+        )?;
+        for label in &labels[1..] {
+            self.check_media(label.label(), &loaded)?;
+        }
+        for (index, label) in labels.iter().enumerate() {
+            let high_resolution = label.resolution() == Resolution::High;
+            self.write(&self.job_commands(
+                &loaded,
+                media,
+                label.len() as u32,
+                false,
+                high_resolution,
+                index == 0,
+            ))?;
+            let mut line_data = vec![0; self.raster_line_length];
+            let mut raster_data = Vec::with_capacity(self.chunk_size);
+            for (line_index, line) in label.iter().enumerate() {
+                encode_line(
+                    self.safe_line(line, line_index, label.len()),
+                    None,
+                    self.compression,
+                    &mut line_data,
+                    &mut raster_data,
+                );
+                self.write_chunks(&mut raster_data, false)?;
+            }
+            self.write_chunks(&mut raster_data, true)?;
+            if index + 1 < labels.len() {
+                self.write(&[0x0C])?; // Print this page and continue with the next
+            }
+        }
+        self.finish_job().map(Into::into)
+    }
+
+    /// Ends the print job, which prints, feeds and cuts the label
+    fn finish_job(&self) -> Result<status::PrinterStatus> {
+        let print_command = [0x1A];
+        self.write(&print_command)?;
+
+        Self::check_errors(self.read_status()?)
+    }
+
+    /// Turns a status reporting that an error occurred into an `ErrorKind::Printer`
+    fn check_errors(status: status::PrinterStatus) -> Result<status::PrinterStatus> {
+        if status.status_type == status::StatusType::ErrorOccurred {
+            if let Some(&error) = status.errors.first() {
+                bail!(ErrorKind::Printer(error));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Same as `print()` but will not return until the printer reports that it has finished printing. Reads that
+    /// time out while the printer is busy are retried, other errors (e.g. the printer being unplugged or sending an
+    /// invalid response) are returned. Fails with `ErrorKind::Timeout` if printing hasn't completed within a minute.
+    pub fn print_blocking<L: Into<RasterLines>>(&self, raster_lines: L) -> Result<()> {
+        self.print_and_wait(raster_lines, PRINT_TIMEOUT, |_| {})
+            .map(|_| ())
+    }
+
+    /// Same as `print_blocking()` but waits for up to `timeout` and calls `on_status` with every status the printer
+    /// sends while the job is printed (e.g. to tell the user when the printer starts printing). Returns the phases it
+    /// went through along with the status reporting that printing has completed.
+    pub fn print_and_wait<L, F>(
+        &self,
+        raster_lines: L,
+        timeout: Duration,
+        mut on_status: F,
+    ) -> Result<PrintOutcome>
+    where
+        L: Into<RasterLines>,
+        F: FnMut(&status::PrinterStatus),
+    {
+        let mut phases = Vec::new();
+        let mut status = self.print_planes(&[&raster_lines.into()], &mut |_, _| {})?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            on_status(&status);
+            match status.status_type {
+                status::StatusType::PrintingCompleted => {
+                    return Ok(PrintOutcome { phases, status })
+                }
+                status::StatusType::PhaseChange => phases.push(status.phase),
+                _ => {}
+            }
+            status = loop {
+                if Instant::now() >= deadline {
+                    bail!(ErrorKind::Timeout(timeout));
+                }
+                match self.read_status() {
+                    Ok(status) => break Self::check_errors(status)?,
+                    Err(Error(ErrorKind::Timeout(_), _)) => {
+                        thread::sleep(Duration::from_millis(50))
+                    }
+                    Err(error) => return Err(error),
+                }
+            };
+        }
+    }
+
+    /// Compress raster lines before sending them to the printer, which greatly reduces the amount of data sent for
+    /// mostly blank labels. Disabled by default. Printing fails if the model doesn't support compression (see
+    /// `capabilities()`).
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    /// Choose when the tape is cut. Use with `print_labels()` to cut a batch of labels into strips. Defaults to
+    /// `CutBehavior::EachLabel` on models with a cutter and `CutBehavior::None` on those without one (see
+    /// `Capabilities::auto_cut`).
+    pub fn set_cut_behavior(&mut self, cut_behavior: CutBehavior) {
+        self.cut_behavior = cut_behavior;
+    }
+
+    /// Feed `dots` of blank tape before and after each label on continuous tape, e.g. to leave room before the cut.
+    /// The printer prints at 300dpi, so a dot is 25.4 / 300 ≈ 0.085mm and 1mm is about 11.8 dots (see
+    /// `Label::mm_to_dots()`). Defaults to the label's `feed_margin` (35 dots, or 3mm, on continuous tape). Die-cut
+    /// labels are already separated so they always use 0.
+    pub fn set_feed_margin(&mut self, dots: u16) {
+        self.feed_margin = Some(dots);
+    }
+
+    /// The largest USB write raster data is sent in, in bytes. Long labels are split into writes of this size, which
+    /// some USB controllers need to avoid stalling. Defaults to 16KiB, values below 1 are treated as 1.
+    pub fn set_chunk_size(&mut self, bytes: usize) {
+        self.chunk_size = bytes.max(1);
+    }
+
+    /// Check that raster lines marked with `RasterLines::set_label()` (as `TextRasterizer` does) were laid out for
+    /// the size of label that's loaded before printing them. Enabled by default. Disable it to print on a different
+    /// label on purpose, e.g. a 29mm design on 62mm tape.
+    pub fn set_check_media(&mut self, enabled: bool) {
+        self.check_media = enabled;
+    }
+
+    /// Send the mode bits of `mode` with every job instead of the ones worked out from this printer's settings (cut
+    /// behavior, chain printing, quality priority) and the resolution and colors of the lines, e.g. to experiment
+    /// with undocumented bits. Nothing is checked: the bits of `mode` have to suit the lines printed. With auto-cut the
+    /// number of labels between cuts still comes from `set_cut_behavior()`. `None` goes back to the printer's
+    /// settings (default).
+    pub fn set_mode_command(&mut self, mode: Option<commands::ModeCommand>) {
+        self.mode_command = mode;
+    }
+
+    /// Keep the labels of consecutive jobs chained together (`true`) or feed out and cut the last label of every job
+    /// (`false`) with the cut at end bit of the expanded mode command. Chain printing suits roll output: the last
+    /// label stays in the printer and the next job carries on right after it without wasting tape, but it can only be
+    /// torn off once more labels have been printed. Disable it to have each job ejected for dispensing. Defaults to
+    /// `false`.
+    ///
+    /// Only the cut at the end of a job is affected. Auto-cut between the labels of a job still follows
+    /// `set_cut_behavior()`, and `CutBehavior::None` never cuts at the end anyway.
+    pub fn set_chain_printing(&mut self, enabled: bool) {
+        self.chain_printing = enabled;
+    }
+
+    /// Give priority to print quality (`true`) or to print speed (`false`) with the quality bit of the media
+    /// information command. Speed suits long labels where a little fading doesn't matter, quality small text.
+    /// Defaults to `true`, the printers' factory setting.
+    ///
+    /// This is separate from the resolution: lines marked `Resolution::High` are still printed at 600dpi along the
+    /// tape with speed priority, which printers always do at their slower high resolution speed.
+    pub fn set_quality_priority(&mut self, enabled: bool) {
+        self.quality_priority = enabled;
+    }
+
+    /// Leave the first `start` and the last `end` raster lines of every page white, whatever they contain, so
+    /// nothing is printed across the perforation or cut between labels on continuous tape. Lines at the end can only
+    /// be blanked in `print_iter()` if the iterator knows its exact length. Defaults to 0 lines at both ends.
+    pub fn set_safe_margins(&mut self, start: u32, end: u32) {
+        self.safe_margins = (start, end);
+    }
+
+    /// The line to send instead of line `index` of a page of `line_count` lines, which is blank in the safe margins
+    fn safe_line<'a>(&self, line: &'a [u8], index: usize, line_count: usize) -> &'a [u8] {
+        let (start, end) = self.safe_margins;
+        if index < start as usize || index + end as usize >= line_count {
+            &[]
+        } else {
+            line
+        }
+    }
+
+    /// The printer's model, from its USB product ID. Unlike the `model` field, which holds whatever name the printer
+    /// reports over USB, this can be matched on to find out what the printer can do.
+    pub fn model(&self) -> constants::Model {
+        constants::Model::from_product_id(self.product_id)
+    }
+
+    /// The features this printer's model supports. Jobs that need anything else fail before anything is sent.
+    pub fn capabilities(&self) -> constants::Capabilities {
+        self.model().capabilities()
+    }
+
+    /// Length in bytes of each raster line this printer's print head accepts. `RASTER_LINE_LENGTH` for most
+    /// models and `WIDE_RASTER_LINE_LENGTH` for wide format printers like the QL-1100.
+    pub fn raster_line_length(&self) -> usize {
+        self.raster_line_length
+    }
+
+    /// Get the currently loaded label size.
+    pub fn current_label(&self) -> Result<constants::Label> {
+        self.detect_label()
+    }
+
+    /// Read the printer's status and look up the loaded media in the label table so that it can be passed to
+    /// `TextRasterizer::new()`. Fails if no media is loaded or the printer reports media that isn't in the table.
+    pub fn detect_label(&self) -> Result<constants::Label> {
+        Self::loaded_label(&self.status()?)
+    }
+
+    /// Looks up the media reported in `status` in the label table
+    fn loaded_label(status: &status::PrinterStatus) -> Result<constants::Label> {
+        if let status::MediaType::None = status.media_type {
+            bail!("No media loaded into printer");
+        }
+        status.label().ok_or_else(|| {
+            match status.media_length_mm {
+                0 => format!(
+                    "Unknown {}mm continuous tape loaded in printer",
+                    status.media_width_mm
+                ),
+                length => format!(
+                    "Unknown {}mm x {}mm die cut labels loaded in printer",
+                    status.media_width_mm, length
+                ),
+            }
+            .into()
+        })
+    }
+
+    /// Request the printer's status and return everything it reports, including the loaded media and any errors.
+    pub fn status(&self) -> Result<status::PrinterStatus> {
+        let status_command = [0x1B, 0x69, 0x53];
+        self.write(&status_command)?;
+        self.read_status()
+    }
+
+    /// Poll the printer's status until it reports no errors (e.g. once the cover has been closed or the print head
+    /// has cooled down) and return that status. Fails with the last reported error if the printer still isn't ready
+    /// after `timeout`.
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<status::PrinterStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.status()?;
+            match status.errors.first() {
+                None => return Ok(status),
+                Some(&error) if Instant::now() >= deadline => bail!(ErrorKind::Printer(error)),
+                Some(_) => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    /// Get the current status of the printer including possible errors, media type, and model name.
+    pub fn get_status(&self) -> Result<status::Response> {
+        let status_command = [0x1B, 0x69, 0x53];
+        self.write(&status_command)?;
+        self.read()
+    }
+
+    fn read(&self) -> Result<status::Response> {
+        Ok(self.read_status()?.into())
+    }
+
+    fn read_status(&self) -> Result<status::PrinterStatus> {
+        const RECEIVE_SIZE: usize = 32;
+        let mut response = [0; RECEIVE_SIZE];
+        let bytes_read = self.backend.read(&mut response)?;
+
+        status::PrinterStatus::parse(&response[..bytes_read])
+            .ok_or_else(|| "Invalid response received from printer".into())
+    }
+
+    /// Sends full `chunk_size` chunks of `data` and removes them from it. The rest is sent too if `flush` is set.
+    /// Returns the number of bytes sent.
+    fn write_chunks(&self, data: &mut Vec<u8>, flush: bool) -> Result<usize> {
+        let mut sent = 0;
+        for chunk in data.chunks(self.chunk_size) {
+            if chunk.len() < self.chunk_size && !flush {
+                break;
+            }
+            self.write(chunk)?;
+            sent += chunk.len();
+        }
+        data.drain(..sent);
+        Ok(sent)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<()> {
+        self.backend.write(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::printer::backend::{MemoryBackend, PrinterBackend};
+    use crate::printer::commands;
+    use crate::printer::status::{MediaType, Phase, PrinterError, PrinterStatus, StatusType};
+    use crate::printer::{
+        compress_packbits, constants, encode_line, format_counter, frame_raster_line,
+        margin_command, print_information_command, printers, CutBehavior, ErrorKind, MediaInfo,
+        PrintOptions, RasterLines, Resolution, Result, TextPrintOptions, ThermalPrinter,
+        TwoColorRasterLines,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn parse_status() {
+        let mut response = [0; 32];
+        response[0] = 0x80;
+        response[4] = 0x35;
+        response[9] = 0x10;
+        response[10] = 62;
+        response[11] = 0x0A;
+        let status = PrinterStatus::parse(&response).unwrap();
+        assert_eq!(status.model, "QL-700");
+        assert_eq!(status.status_type, StatusType::ReplyToStatusRequest);
+        assert_eq!(status.errors, vec![PrinterError::CoverOpen]);
+        assert!(matches!(status.media_type, MediaType::ContinuousTape));
+        assert_eq!((status.media_width_mm, status.media_length_mm), (62, 0));
+        assert_eq!(status.label().unwrap().dots_printable.0, 696);
+        assert_eq!(status.phase, Phase::Receiving);
+
+        response[18] = 0x05;
+        response[19] = 0x01;
+        response[22] = 0x03;
+        let status = PrinterStatus::parse(&response).unwrap();
+        assert_eq!(status.status_type, StatusType::Notification);
+        assert_eq!(status.phase, Phase::Printing);
+        assert_eq!(
+            status.errors,
+            vec![PrinterError::CoverOpen, PrinterError::Overheated]
+        );
+
+        response[4] = 0x41;
+        assert_eq!(PrinterStatus::parse(&response).unwrap().model, "QL-820NWB");
+        response[4] = 0x45;
+        assert_eq!(PrinterStatus::parse(&response).unwrap().model, "QL-1115NWB");
+
+        assert!(PrinterStatus::parse(&response[..31]).is_none());
+        response[0] = 0;
+        assert!(PrinterStatus::parse(&response).is_none());
+    }
+
+    fn decompress_packbits(mut data: &[u8]) -> Vec<u8> {
+        let mut line = Vec::new();
+        while let Some((&count, rest)) = data.split_first() {
+            if count < 128 {
+                let (literal, rest) = rest.split_at(count as usize + 1);
+                line.extend_from_slice(literal);
+                data = rest;
+            } else {
+                line.extend(std::iter::repeat_n(rest[0], 257 - count as usize));
+                data = &rest[1..];
+            }
+        }
+        line
+    }
+
+    /// A QL-700 that records what it's sent, with `label` loaded
+    fn memory_printer(label: constants::Label) -> ThermalPrinter<MemoryBackend> {
+        ThermalPrinter::with_backend(MemoryBackend::new(label), 0x2042).unwrap()
+    }
+
+    /// Returns what `matcher` picks out of the commands sent so far, clearing them for the next check.
+    fn take_sent<T>(
+        printer: &ThermalPrinter<MemoryBackend>,
+        matcher: impl Fn(&commands::QlCommand) -> Option<T>,
+    ) -> Vec<T> {
+        let sent = commands::parse(&printer.backend().sent()).unwrap();
+        printer.backend().clear();
+        sent.iter().filter_map(matcher).collect()
+    }
+
+    #[test]
+    fn cut_settings() {
+        assert_eq!(CutBehavior::EachLabel.settings(), (true, 1, true));
+        // Cut after every second label and after the last one
+        assert_eq!(CutBehavior::Every(2).settings(), (true, 2, true));
+        assert_eq!(CutBehavior::Every(0).settings(), (true, 1, true));
+        assert_eq!(CutBehavior::Every(1000).settings(), (true, 255, true));
+        assert_eq!(CutBehavior::EndOnly.settings(), (false, 1, true));
+        assert_eq!(CutBehavior::None.settings(), (false, 1, false));
+    }
+
+    #[test]
+    fn command_stream() {
+        let label = constants::label_data(62, None).unwrap();
+        let printer = memory_printer(label);
+        assert_eq!(printer.model, "QL-700");
+        let mut expected = vec![0; 200];
+        expected.extend_from_slice(&[0x1B, 0x40, 0x1B, 0x69, 0x53]);
+        assert_eq!(&*printer.backend().sent(), &expected[..]);
+        printer.backend().clear();
+
+        let mut lines = RasterLines::new(90);
+        lines.push(&[0xFF; 90]);
+        lines.push(&[0x00; 90]);
+        let response = printer.print(lines).unwrap();
+        assert_eq!(response.status_type, StatusType::PrintingCompleted);
+
+        let mut expected = vec![
+            0x1B, 0x69, 0x53, // Status
+            0x1B, 0x69, 0x61, 1, // Raster mode
+            0x1B, 0x69, 0x7A, 0xCE, 0x0A, 62, 0, 2, 0, 0, 0, 0, 0, // Media and line count
+            0x1B, 0x69, 0x4D, 0x40, // Auto-cut
+            0x1B, 0x69, 0x41, 1, // Cut every label
+            0x1B, 0x69, 0x4B, 0x08, // Cut at end
+            0x1B, 0x69, 0x64, 35, 0, // Feed margin
+            0x67, 0x00, 90,
+        ];
+        expected.extend_from_slice(&[0xFF; 90]);
+        expected.extend_from_slice(&[0x67, 0x00, 90]);
+        expected.extend_from_slice(&[0x00; 90]);
+        expected.push(0x1A);
+        assert_eq!(&*printer.backend().sent(), &expected[..]);
+
+        let mut job = Vec::new();
+        printer
+            .print_to_writer(
+                &mut job,
+                vec![[0xFF; 90], [0x00; 90]],
+                &PrintOptions::new(label),
+            )
+            .unwrap();
+        assert_eq!(&job[..200], &[0; 200][..]);
+        assert_eq!(&job[200..205], [0x1B, 0x40, 0x1B, 0x69, 0x53]);
+        assert_eq!(&job[205..], &expected[3..]);
+        assert_eq!(commands::encode(&commands::parse(&job).unwrap()), job);
+    }
+
+    #[test]
+    fn encoding_lines() {
+        let mut line_data = [0; 4];
+        let mut out = Vec::new();
+        encode_line(&[0xFF, 0x01], None, false, &mut line_data, &mut out);
+        encode_line(&[0xFF], Some(2), false, &mut line_data, &mut out);
+        assert_eq!(
+            out,
+            [0x67, 0x00, 4, 0xFF, 0x01, 0, 0, 0x77, 0x02, 4, 0xFF, 0, 0, 0]
+        );
+
+        out.clear();
+        encode_line(&[], None, true, &mut line_data, &mut out);
+        encode_line(&[0xAA; 4], None, true, &mut line_data, &mut out);
+        assert_eq!(out, [0x5A, 0x67, 0x00, 2, 0xFD, 0xAA]);
+
+        // Packed lines are only the dots, the command is added around them
+        let packed = crate::text::pack_raster_line([true, false, true], 2);
+        assert_eq!(packed, [0xA0, 0x00]);
+        out.clear();
+        frame_raster_line(&packed, None, &mut out);
+        frame_raster_line(&packed, Some(1), &mut out);
+        assert_eq!(out, [0x67, 0x00, 2, 0xA0, 0x00, 0x77, 0x01, 2, 0xA0, 0x00]);
+    }
+
+    #[test]
+    fn print_information() {
+        // Flags, media type, width, length, the line count in little endian and the page, as in the command reference
+        let die_cut = constants::label_data(29, Some(90)).unwrap();
+        assert_eq!(
+            print_information_command(MediaInfo::of_label(&die_cut), 991, true, true),
+            [0x1B, 0x69, 0x7A, 0xCE, 0x0B, 0x1D, 0x5A, 0xDF, 0x03, 0x00, 0x00, 0x00, 0x00]
+        );
+        let continuous = constants::label_data(62, None).unwrap();
+        assert_eq!(
+            print_information_command(MediaInfo::of_label(&continuous), 750, false, false),
+            [0x1B, 0x69, 0x7A, 0x8E, 0x0A, 0x3E, 0x00, 0xEE, 0x02, 0x00, 0x00, 0x01, 0x00]
+        );
+        assert_eq!(
+            commands::parse(&print_information_command(
+                MediaInfo::of_label(&die_cut),
+                991,
+                true,
+                true
+            ))
+            .unwrap(),
+            [commands::QlCommand::MediaInfo {
+                valid_flags: 0xCE,
+                media_type: commands::MEDIA_DIE_CUT,
+                width_mm: 29,
+                length_mm: 90,
+                line_count: 991,
+                page: 0,
+            }]
+        );
+
+        // Jobs describe the media the way the printer reported it, e.g. 38x90mm labels as 39x90mm
+        let label = constants::label_data(38, Some(90)).unwrap();
+        let reported = constants::Label {
+            tape_size: constants::WidthLength(39, 90),
+            ..label
+        };
+        let printer = memory_printer(reported);
+        let mut lines = RasterLines::from(vec![[0xFF; 90]; 2]);
+        lines.set_label(label);
+        printer.print(lines).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(sent.iter().any(|command| matches!(
+            command,
+            commands::QlCommand::MediaInfo {
+                width_mm: 39,
+                length_mm: 90,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn feed_margins() {
+        let continuous = constants::label_data(62, None).unwrap();
+        assert_eq!(margin_command(&continuous, None), [0x1B, 0x69, 0x64, 35, 0]);
+        // 25mm of blank tape
+        assert_eq!(
+            margin_command(&continuous, Some(295)),
+            [0x1B, 0x69, 0x64, 0x27, 0x01]
+        );
+        let die_cut = constants::label_data(62, Some(29)).unwrap();
+        assert_eq!(
+            margin_command(&die_cut, Some(295)),
+            [0x1B, 0x69, 0x64, 0, 0]
+        );
+    }
+
+    #[test]
+    fn packbits_round_trip() {
+        let mut mixed = vec![0; 90];
+        mixed[10..20].copy_from_slice(&[1, 2, 3, 4, 4, 4, 5, 6, 6, 7]);
+        mixed[60..].fill(0xFF);
+        let counting: Vec<u8> = (0..=255).collect();
+        let lines = [
+            vec![],
+            vec![0; 90],
+            vec![0; 162],
+            mixed,
+            counting,
+            vec![0x0F, 0xF0],
+        ];
+        for line in lines.iter() {
+            let compressed = compress_packbits(line);
+            assert_eq!(&decompress_packbits(&compressed), line);
+            assert!(compressed.len() <= line.len() + line.len().div_ceil(128));
+        }
+        assert_eq!(compress_packbits(&[0; 90]), vec![(257 - 90) as u8, 0]);
+    }
+
+    #[test]
+    fn streaming_too_long_lines() {
+        let label = constants::label_data(62, None).unwrap();
+        let printer = memory_printer(label);
+        printer.backend().clear();
+        // A first line that's too long is caught before anything is sent
+        assert!(printer.print_iter(vec![vec![0xFF; 91]]).is_err());
+        assert!(printer.backend().sent().is_empty());
+
+        // Later ones abort the job and clear what was already sent out of the printer
+        let lines = vec![vec![0xFF; 90], vec![0xFF; 90], vec![0xFF; 91]];
+        assert!(printer.print_iter(lines).is_err());
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(!sent.contains(&commands::QlCommand::PrintWithFeeding));
+        assert_eq!(
+            sent[sent.len() - 2..],
+            [
+                commands::QlCommand::Invalidate(200),
+                commands::QlCommand::Initialize
+            ]
+        );
+    }
+
+    #[test]
+    fn several_labels_ask_for_status_once() {
+        let label = constants::label_data(62, None).unwrap();
+        let printer = memory_printer(label);
+        printer.backend().clear();
+        printer.print_labels(vec![vec![[0xFF; 90]; 2]; 3]).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        let count = |wanted: &commands::QlCommand| sent.iter().filter(|c| *c == wanted).count();
+        assert_eq!(count(&commands::QlCommand::StatusRequest), 1);
+        assert_eq!(sent[0], commands::QlCommand::StatusRequest);
+        assert_eq!(count(&commands::QlCommand::Print), 2);
+
+        // A page laid out for other media stops the job before any of it is sent
+        let mut other = RasterLines::from(vec![[0xFF; 90]; 2]);
+        other.set_label(constants::label_data(29, None).unwrap());
+        let error = printer
+            .print_labels(vec![RasterLines::from(vec![[0xFF; 90]; 2]), other])
+            .unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::MediaMismatch(..)));
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert_eq!(sent, [commands::QlCommand::StatusRequest]);
+    }
+
+    #[test]
+    fn printing_two_colors() {
+        let planes = TwoColorRasterLines {
+            black: vec![[0xFF; 90]; 2].into(),
+            red: vec![[0x0F; 90]; 2].into(),
+        };
+        let label = constants::label_data(62, None).unwrap();
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x209B).unwrap();
+        printer.backend().clear();
+        printer.print_two_color(planes.clone()).unwrap();
+        let status_requests = take_sent(&printer, |command| match command {
+            commands::QlCommand::StatusRequest => Some(()),
+            _ => None,
+        });
+        assert_eq!(status_requests.len(), 1);
+
+        // Only 62mm tape comes in black and red
+        let label = constants::label_data(29, Some(90)).unwrap();
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x209B).unwrap();
+        let error = printer.print_two_color(planes).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::MediaMismatch("62", "29x90")
+        ));
+    }
+
+    #[test]
+    fn printing_batches() {
+        let label = constants::label_data(62, Some(29)).unwrap();
+        let mut printer = memory_printer(label);
+        printer.backend().clear();
+        let first = [[0xFF; 90]; 2];
+        let second = [[0x0F; 90]; 3];
+        let jobs: [&[[u8; 90]]; 2] = [&first, &second];
+        printer.print_batch(jobs, CutBehavior::Every(2)).unwrap();
+
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        let pages: Vec<_> = sent
+            .iter()
+            .filter_map(|command| match *command {
+                commands::QlCommand::MediaInfo {
+                    line_count, page, ..
+                } => Some((line_count, page)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pages, [(2, 0), (3, 1)]);
+        let count =
+            |wanted: commands::QlCommand| sent.iter().filter(|&command| *command == wanted).count();
+        assert_eq!(count(commands::QlCommand::CutEvery(2)), 2);
+        assert_eq!(count(commands::QlCommand::Print), 1);
+        assert_eq!(count(commands::QlCommand::PrintWithFeeding), 1);
+        assert_eq!(sent.last(), Some(&commands::QlCommand::PrintWithFeeding));
+    }
+
+    #[test]
+    fn printing_series() {
+        assert_eq!(format_counter("ASSET-{n:04}", 7).unwrap(), "ASSET-0007");
+        assert_eq!(format_counter("{n}/{n:3}", 12).unwrap(), "12/ 12");
+        assert_eq!(format_counter("{name} {n", 1).unwrap(), "{name} {n");
+        assert_eq!(format_counter("#{n:02}", 123).unwrap(), "#123");
+        assert!(format_counter("{n:x}", 1).is_err());
+
+        let label = constants::label_data(29, Some(90)).unwrap();
+        let mut printer = memory_printer(label);
+        printer.backend().clear();
+        let opts = TextPrintOptions::new("test-fonts/DejaVuSansMono.ttf".into());
+        printer
+            .print_series("SERIAL {n}", 9_999_999_998, 3, &opts)
+            .unwrap();
+
+        // One job with a page per label, the last one of which is too long for the largest size
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        let mut pages = vec![Vec::new()];
+        for command in &sent {
+            match command {
+                commands::QlCommand::RasterLine(data) => pages.last_mut().unwrap().push(data),
+                commands::QlCommand::Print => pages.push(Vec::new()),
+                _ => {}
+            }
+        }
+        assert_eq!(pages.len(), 3);
+        let jobs = sent
+            .iter()
+            .filter(|&command| *command == commands::QlCommand::PrintWithFeeding)
+            .count();
+        assert_eq!(jobs, 1);
+
+        // All labels are printed at the same size, so their text is equally tall
+        let ink_height = |page: &Vec<&Vec<u8>>| {
+            (0..90 * 8)
+                .filter(|dot| {
+                    page.iter()
+                        .any(|line| line[dot / 8] & (0x80 >> (dot % 8)) != 0)
+                })
+                .count()
+        };
+        let rasterizer = crate::text::TextRasterizer::new(label, opts.font_path.clone());
+        assert!(rasterizer.measure("SERIAL 10000000000", 125.0).unwrap() < 125.0);
+        assert!(ink_height(&pages[0]) > 0);
+        assert_eq!(ink_height(&pages[0]), ink_height(&pages[2]));
+        assert!(printer.print_series("{n:x}", 1, 1, &opts).is_err());
     }
 
-    use std::path::PathBuf;
+    #[test]
+    fn detecting_models() {
+        for id in [0x2015, 0x2042, 0x209B, 0x20A7, 0x20AB] {
+            let model = constants::Model::from_product_id(id);
+            assert_eq!(model.product_id(), id);
+            assert_eq!(Some(model.name()), constants::printer_name_from_id(id));
+        }
+        assert_eq!(constants::printer_name_from_id(0x1234), None);
+
+        let label = constants::label_data(62, None).unwrap();
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x20A7).unwrap();
+        assert_eq!(printer.model(), constants::Model::QL1100);
+        assert_eq!(printer.model, "QL-1100");
+        assert_eq!(printer.model().max_width_dots(), 1296);
+        assert_eq!(printer.model().dpi(), 300);
+        assert_eq!(
+            printer.raster_line_length(),
+            printer.model().raster_line_length()
+        );
+
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x209C).unwrap();
+        assert_eq!(printer.model(), constants::Model::QL810W);
+        assert!(printer.capabilities().two_color && !printer.capabilities().compression);
+
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x1234).unwrap();
+        assert_eq!(printer.model(), constants::Model::Unknown(0x1234));
+        assert_eq!(printer.model, "Unknown");
+        assert_eq!(printer.model().max_width_dots(), 720);
+    }
+
+    #[test]
+    #[cfg(feature = "svg")]
+    fn printing_svgs() {
+        let label = constants::label_data(29, Some(90)).unwrap();
+        let mut printer = memory_printer(label);
+        printer.backend().clear();
+        let svg = "<svg xmlns='http://www.w3.org/2000/svg' width='90mm' height='29mm'>\
+                   <rect width='100%' height='100%'/></svg>";
+        printer.print_svg(svg, &Default::default()).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(sent.contains(&commands::QlCommand::MediaInfo {
+            valid_flags: 0xCE,
+            media_type: 0x0B,
+            width_mm: 29,
+            length_mm: 90,
+            line_count: 991,
+            page: 0,
+        }));
+    }
+
+    #[test]
+    fn validating_jobs() {
+        let label = constants::label_data(29, Some(90)).unwrap();
+        let printer = memory_printer(label);
+        printer.backend().clear();
+        let mut opts = TextPrintOptions::new("test-fonts/DejaVuSansMono.ttf".into());
+        opts.min_readable_font_size = Some(60.0);
+        let texts = ["Hi", "A much longer line of text than fits"];
+        let report = printer.validate_text(&texts, &opts).unwrap();
+        assert_eq!(report.label, label);
+        assert_eq!(report.labels.len(), 2);
+        assert_eq!(report.labels[0].font_size, 125.0);
+        assert!(report.labels[0].warnings.is_empty());
+        assert_eq!(report.labels[1].line_count, 991);
+        let small = report.labels[1].font_size;
+        assert!(small < 60.0);
+        assert_eq!(
+            report.labels[1].warnings,
+            [crate::text::RasterizeWarning::TextTooSmall {
+                text: texts[1].to_string(),
+                font_size: small,
+                min_readable: 60.0,
+            }]
+        );
+        assert!(report.job_size > 2 * 991);
+
+        // Only the status was read, once for the whole job
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert_eq!(sent, [commands::QlCommand::StatusRequest]);
+
+        opts.label = constants::label_data(62, Some(29));
+        let error = printer.validate_text(&texts, &opts).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            crate::printer::ErrorKind::MediaMismatch(..)
+        ));
+        opts.label = None;
+        opts.font_path = "does-not-exist.ttf".into();
+        assert!(printer.validate_text(&texts, &opts).is_err());
+    }
+
+    #[test]
+    fn safe_margins_are_blank() {
+        let label = constants::label_data(62, None).unwrap();
+        let mut printer = memory_printer(label);
+        let sent_lines = |printer: &ThermalPrinter<MemoryBackend>| {
+            take_sent(printer, |command| match command {
+                commands::QlCommand::RasterLine(data) => Some(data.iter().any(|&byte| byte != 0)),
+                _ => None,
+            })
+        };
+        printer.backend().clear();
+        printer.print(vec![[0xFF; 90]; 6]).unwrap();
+        assert_eq!(sent_lines(&printer), [true; 6]);
+
+        printer.set_safe_margins(2, 1);
+        printer.print(vec![[0xFF; 90]; 6]).unwrap();
+        assert_eq!(
+            sent_lines(&printer),
+            [false, false, true, true, true, false]
+        );
+        printer.print_labels(vec![vec![[0xFF; 90]; 4]; 2]).unwrap();
+        assert_eq!(
+            sent_lines(&printer),
+            [false, false, true, false, false, false, true, false]
+        );
+        printer.print_iter(vec![[0xFF; 90]; 4]).unwrap();
+        assert_eq!(sent_lines(&printer), [false, false, true, false]);
+        // Margins bigger than the page leave it all blank
+        printer.set_safe_margins(5, 5);
+        printer.print(vec![[0xFF; 90]; 3]).unwrap();
+        assert_eq!(sent_lines(&printer), [false; 3]);
+    }
+
+    #[test]
+    fn sending_raw_mode_commands() {
+        let label = constants::label_data(62, None).unwrap();
+        let mut printer = memory_printer(label);
+        printer.set_quality_priority(false);
+        printer.set_mode_command(Some(
+            commands::ModeCommand::new()
+                .quality_priority(true)
+                .extra_bits(0x10),
+        ));
+        printer.backend().clear();
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(sent.contains(&commands::QlCommand::VariousMode(0)));
+        assert!(sent.contains(&commands::QlCommand::ExpandedMode(0x10)));
+        assert!(!sent
+            .iter()
+            .any(|command| matches!(command, commands::QlCommand::CutEvery(_))));
+        assert!(sent.iter().any(|command| matches!(
+            command,
+            commands::QlCommand::MediaInfo {
+                valid_flags: 0xCE,
+                ..
+            }
+        )));
+
+        // Without it the printer's own settings are used again
+        printer.set_mode_command(None);
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(sent.contains(&commands::QlCommand::VariousMode(0x40)));
+        assert!(sent.contains(&commands::QlCommand::ExpandedMode(0x08)));
+    }
+
+    #[test]
+    fn chain_printing() {
+        let label = constants::label_data(62, None).unwrap();
+        let mut printer = memory_printer(label);
+        let expanded_modes = |printer: &ThermalPrinter<MemoryBackend>| {
+            take_sent(printer, |command| match command {
+                commands::QlCommand::ExpandedMode(flags) => Some(*flags),
+                _ => None,
+            })
+        };
+        printer.backend().clear();
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        assert_eq!(expanded_modes(&printer), [0x08]);
+
+        printer.set_chain_printing(true);
+        printer.print_labels(vec![vec![[0xFF; 90]; 2]; 2]).unwrap();
+        assert_eq!(expanded_modes(&printer), [0x00, 0x00]);
+        // Auto-cut between labels is still on
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        let sent = take_sent(&printer, |command| Some(command.clone()));
+        assert!(sent.contains(&commands::QlCommand::VariousMode(0x40)));
+
+        printer.set_chain_printing(false);
+        printer.set_cut_behavior(CutBehavior::EndOnly);
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        assert_eq!(expanded_modes(&printer), [0x08]);
+        printer.set_cut_behavior(CutBehavior::None);
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        assert_eq!(expanded_modes(&printer), [0x00]);
+    }
+
+    /// A `MemoryBackend` that answers with `responses` (`None` being a timed out read) once a job has been printed,
+    /// then answers as usual
+    struct ScriptedBackend {
+        memory: MemoryBackend,
+        responses: std::cell::RefCell<std::collections::VecDeque<Option<[u8; 32]>>>,
+    }
+    impl PrinterBackend for ScriptedBackend {
+        fn write(&self, data: &[u8]) -> Result<()> {
+            self.memory.write(data)
+        }
+        fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+            if self.memory.sent().last() != Some(&0x1A) {
+                return self.memory.read(buffer);
+            }
+            match self.responses.borrow_mut().pop_front() {
+                Some(Some(response)) => {
+                    buffer[..32].copy_from_slice(&response);
+                    Ok(32)
+                }
+                Some(None) => bail!(ErrorKind::Timeout(std::time::Duration::from_millis(500))),
+                None => self.memory.read(buffer),
+            }
+        }
+    }
+
+    #[test]
+    fn waiting_for_phase_changes() {
+        let label = constants::label_data(62, None).unwrap();
+        let memory = MemoryBackend::new(label);
+        let mut phase_change = [0; 32];
+        memory.read(&mut phase_change).unwrap();
+        phase_change[18] = 0x06;
+        let mut printing = phase_change;
+        printing[19] = 0x01;
+        let backend = ScriptedBackend {
+            memory,
+            responses: Default::default(),
+        };
+        let printer = ThermalPrinter::with_backend(backend, 0x2042).unwrap();
+        printer.backend().responses.borrow_mut().extend(vec![
+            Some(printing),
+            None,
+            Some(phase_change),
+        ]);
+
+        let mut seen = Vec::new();
+        let outcome = printer
+            .print_and_wait(vec![[0xFF; 90]; 2], Duration::from_secs(1), |status| {
+                seen.push((status.status_type.clone(), status.phase))
+            })
+            .unwrap();
+        assert_eq!(outcome.phases, [Phase::Printing, Phase::Receiving]);
+        assert_eq!(outcome.status.status_type, StatusType::PrintingCompleted);
+        assert_eq!(
+            seen,
+            [
+                (StatusType::PhaseChange, Phase::Printing),
+                (StatusType::PhaseChange, Phase::Receiving),
+                (StatusType::PrintingCompleted, Phase::Receiving),
+            ]
+        );
+
+        // Errors reported while printing end the wait
+        let mut error = phase_change;
+        error[8] = 0x04;
+        error[18] = 0x02;
+        printer
+            .backend()
+            .responses
+            .borrow_mut()
+            .extend(vec![Some(printing), Some(error)]);
+        let error = printer
+            .print_and_wait(vec![[0xFF; 90]; 2], Duration::from_secs(1), |_| {})
+            .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Printer(PrinterError::CutterJam)
+        ));
+
+        // Invalid responses aren't retried
+        printer
+            .backend()
+            .responses
+            .borrow_mut()
+            .extend(vec![Some(printing), Some([0; 32])]);
+        let error = printer
+            .print_and_wait(vec![[0xFF; 90]; 2], Duration::from_secs(1), |_| {})
+            .unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Msg(_)));
+
+        // A printer that stops answering is given up on
+        printer
+            .backend()
+            .responses
+            .borrow_mut()
+            .extend(vec![Some(printing), None, None, None]);
+        let error = printer
+            .print_and_wait(vec![[0xFF; 90]; 2], Duration::from_millis(20), |_| {})
+            .unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Timeout(_)));
+    }
+
+    #[test]
+    fn choosing_quality_or_speed() {
+        let label = constants::label_data(62, None).unwrap();
+        let mut printer = memory_printer(label);
+        let valid_flags = |printer: &ThermalPrinter<MemoryBackend>| {
+            take_sent(printer, |command| match command {
+                commands::QlCommand::MediaInfo { valid_flags, .. } => Some(*valid_flags),
+                _ => None,
+            })
+        };
+        printer.backend().clear();
+        printer.print(vec![[0xFF; 90]; 2]).unwrap();
+        assert_eq!(valid_flags(&printer), [0xCE]);
+
+        printer.set_quality_priority(false);
+        printer.print_labels(vec![vec![[0xFF; 90]; 2]; 2]).unwrap();
+        assert_eq!(valid_flags(&printer), [0x8E, 0x8E]);
+    }
+
+    #[test]
+    fn reporting_progress() {
+        let label = constants::label_data(62, None).unwrap();
+        let mut printer = memory_printer(label);
+        printer.set_chunk_size(1000);
+        let lines = vec![[0xAA; 90]; 100];
+        let mut calls = Vec::new();
+        printer
+            .print_with_progress(lines, |sent, total| calls.push((sent, total)))
+            .unwrap();
+
+        // 100 lines of 93 bytes are sent in 10 chunks, the last one partly filled
+        assert_eq!(calls.len(), 10);
+        assert_eq!(calls[0], (10, 100));
+        assert!(calls.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(calls.last(), Some(&(100, 100)));
+    }
+
+    #[test]
+    fn resetting() {
+        let label = constants::label_data(29, None).unwrap();
+        let printer = memory_printer(label);
+        let mut expected = vec![0x00; 200];
+        expected.extend_from_slice(&[0x1B, 0x40, 0x1B, 0x69, 0x53]);
+        assert_eq!(&*printer.backend().sent(), &expected[..]);
+
+        printer.backend().clear();
+        printer.initialize().unwrap();
+        assert_eq!(&*printer.backend().sent(), &expected[..202]);
+        printer.backend().clear();
+        assert_eq!(printer.reset().unwrap().media.width, 29);
+        assert_eq!(&*printer.backend().sent(), &expected[..]);
+    }
+
+    #[test]
+    fn unsupported_features() {
+        let label = constants::label_data(62, None).unwrap();
+        // The QL-500 has no cutter and doesn't support compression
+        let mut printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x2015).unwrap();
+        assert!(!printer.capabilities().compression);
+        assert_eq!(printer.capabilities().max_width_dots, 720);
+        printer.print([[0xFF; 90]; 2].to_vec()).unwrap();
+
+        printer.set_compression(true);
+        printer.backend().clear();
+        let error = printer.print([[0xFF; 90]; 2].to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "The QL-500 doesn't support compression");
+        assert!(printer.backend().sent().is_empty());
+
+        printer.set_compression(false);
+        printer.set_cut_behavior(CutBehavior::EachLabel);
+        assert!(printer.print([[0xFF; 90]; 2].to_vec()).is_err());
+
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x209B).unwrap();
+        let capabilities = printer.capabilities();
+        assert!(capabilities.two_color && !capabilities.compression && capabilities.auto_cut);
+    }
+
+    #[test]
+    fn mismatched_media() {
+        let loaded = constants::label_data(29, None).unwrap();
+        let mut printer = memory_printer(loaded);
+        printer.backend().clear();
+        let mut lines = RasterLines::new(90);
+        lines.push(&[0xFF; 90]);
+        lines.set_label(constants::label_data(62, None).unwrap());
+        let error = printer.print(lines.clone()).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            crate::printer::ErrorKind::MediaMismatch(expected, found) if *expected == "62" && *found == "29"
+        ));
+        // Only the status request was sent
+        assert_eq!(&*printer.backend().sent(), [0x1B, 0x69, 0x53]);
+
+        printer.set_check_media(false);
+        printer.print(lines.clone()).unwrap();
+        lines.set_label(loaded);
+        printer.set_check_media(true);
+        printer.print(lines).unwrap();
+    }
+
+    #[test]
+    fn wide_labels() {
+        let label = constants::label_by_name("DK-1247").unwrap();
+        assert_eq!(
+            label.raster_line_length(),
+            constants::WIDE_RASTER_LINE_LENGTH
+        );
+        let mut lines = RasterLines::new(label.raster_line_length());
+        lines.push(&[0xFF; constants::WIDE_RASTER_LINE_LENGTH]);
+        let opts = PrintOptions::new(label);
+
+        let narrow = memory_printer(label);
+        let mut job = Vec::new();
+        assert!(narrow
+            .print_to_writer(&mut job, lines.clone(), &opts)
+            .is_err());
+
+        let wide = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x20A7).unwrap();
+        wide.print_to_writer(&mut job, lines, &opts).unwrap();
+        let raster_line = commands::parse(&job)
+            .unwrap()
+            .into_iter()
+            .find_map(|command| match command {
+                commands::QlCommand::RasterLine(data) => Some(data),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(raster_line, vec![0xFF; constants::WIDE_RASTER_LINE_LENGTH]);
+    }
+
+    #[test]
+    fn looking_up_labels() {
+        let labels = constants::all_labels();
+        assert!(labels
+            .iter()
+            .any(|label| label.part_number == Some("DK-22251")));
+
+        let label = constants::label_by_name("DK-1201").unwrap();
+        assert_eq!((label.tape_size.0, label.tape_size.1), (29, 90));
+        let label = constants::label_by_name("dk-22205").unwrap();
+        assert_eq!(label.name, "62");
+        let label = constants::label_by_name("62x29").unwrap();
+        assert_eq!(label.part_number, Some("DK-11209"));
+        assert!(constants::label_by_name("DK-9999").is_none());
+
+        let label = constants::label_by_size(38, 90).unwrap();
+        assert_eq!(label.part_number, Some("DK-11208"));
+        // The size printers report for the same labels
+        assert_eq!(constants::label_data(39, Some(90)), Some(label));
+        assert_eq!(constants::label_data(39, Some(48)).unwrap().name, "39x48");
+        assert_eq!(
+            constants::label_by_size(62, 0).unwrap().part_number,
+            Some("DK-22205")
+        );
+        assert!(constants::label_by_size(62, 30).is_none());
+    }
+
+    #[test]
+    fn converting_units() {
+        let label = constants::label_data(29, Some(90)).unwrap();
+        assert_eq!(label.dpi(), 300);
+        assert_eq!(label.mm_to_dots(25.4), 300);
+        assert_eq!(label.mm_to_dots(3.0), 35);
+        assert_eq!(label.mm_to_dots(-1.0), 0);
+        assert!((label.dots_to_mm(label.dots_printable.0) - 25.9).abs() < 0.1);
+        assert_eq!(Resolution::Standard.mm_to_lines(10.0), 118);
+        assert_eq!(Resolution::High.mm_to_lines(10.0), 236);
+        assert_eq!(Resolution::High.dpi(), 600);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serializing_labels() {
+        for label in constants::all_labels() {
+            let json = serde_json::to_string(label).unwrap();
+            let parsed: constants::Label = serde_json::from_str(&json).unwrap();
+            assert_eq!(&parsed, label);
+            // Strings from the table are reused rather than leaked
+            assert!(std::ptr::eq(parsed.name, label.name));
+        }
+
+        let custom = constants::Label {
+            name: "40x20",
+            part_number: None,
+            ..constants::label_data(29, Some(90)).unwrap()
+        };
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(
+            serde_json::from_str::<constants::Label>(&json).unwrap(),
+            custom
+        );
+        let capabilities = constants::capabilities_from_id(0x209B);
+        let json = serde_json::to_string(&capabilities).unwrap();
+        assert_eq!(
+            serde_json::from_str::<constants::Capabilities>(&json).unwrap(),
+            capabilities
+        );
+    }
+
+    #[test]
+    fn raster_lines_from_arrays() {
+        let mut first = [0; 90];
+        first[1] = 0xFF;
+        let lines = RasterLines::from(vec![first, [0; 90]]);
+        assert_eq!(lines.line_length(), 90);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines.iter().next().unwrap(), &first[..]);
+
+        let mut wide = RasterLines::new(162);
+        wide.push(&[0; 162]);
+        assert_eq!(wide.len(), 1);
+
+        // Packed rows from elsewhere are split into lines as they are
+        let packed = RasterLines::from_packed(90, [first, [0xAA; 90], [0; 90]].concat());
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed.get(1).unwrap(), [0xAA; 90]);
+        assert_eq!(packed, RasterLines::from(vec![first, [0xAA; 90], [0; 90]]));
+    }
+
+    #[test]
+    #[ignore]
+    fn connect() {
+        let printer_list = printers();
+        assert!(!printer_list.is_empty(), "No printers found");
+        let printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
+        printer.get_status().unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn open_by_serial() {
+        let printer_list = ThermalPrinter::enumerate();
+        assert!(!printer_list.is_empty(), "No printers found");
+        let serial_number = printer_list[0].serial_number.as_ref().unwrap();
+        let printer = ThermalPrinter::open_by_serial(serial_number).unwrap();
+        assert_eq!(&printer.serial_number, serial_number);
+    }
+
+    use std::path::{Path, PathBuf};
     #[test]
     #[ignore]
     fn print() {
         let printer_list = printers();
-        assert!(printer_list.len() > 0, "No printers found");
-        let mut printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
-        let label = printer.init().unwrap().media.to_label();
+        assert!(!printer_list.is_empty(), "No printers found");
+        let printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
+        let label = printer.get_status().unwrap().media.to_label();
 
         let mut rasterizer =
             crate::text::TextRasterizer::new(label, PathBuf::from("./Space Mono Bold.ttf"));
@@ -369,4 +2721,28 @@ mod tests {
 
         dbg!(printer.print(lines).unwrap());
     }
+
+    #[test]
+    #[ignore]
+    fn print_text() {
+        let printer_list = printers();
+        assert!(!printer_list.is_empty(), "No printers found");
+        let mut printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
+        let mut opts =
+            crate::printer::TextPrintOptions::new(PathBuf::from("./Space Mono Bold.ttf"));
+        opts.secondary_text = Some("Computer Science".to_string());
+        printer.print_text("Ryan Petschek", &opts).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn print_image_file() {
+        let printer_list = printers();
+        assert!(!printer_list.is_empty(), "No printers found");
+        let mut printer = ThermalPrinter::new(printer_list.into_iter().next().unwrap()).unwrap();
+        let opts = crate::printer::ImagePrintOptions::default();
+        printer
+            .print_image_file(Path::new("./logos/BuildGT Mono.png"), &opts)
+            .unwrap();
+    }
 }
@@ -0,0 +1,215 @@
+//! A `ThermalPrinter` for async code, enabled with the `tokio` feature
+//!
+//! USB transfers block, so calling `ThermalPrinter` from an async task stalls the executor it runs on.
+//! `AsyncThermalPrinter` runs every call on tokio's blocking thread pool instead.
+
+use super::backend::{PrinterBackend, UsbBackend};
+use super::{
+    constants, status, CutBehavior, ImagePrintOptions, PrintOutcome, RasterLines, Result,
+    TextPrintOptions, ThermalPrinter, TwoColorRasterLines,
+};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Wraps a `ThermalPrinter` so it can be used from async code. Clones share the same printer and calls are run one
+/// at a time.
+///
+/// Must be used from within a tokio runtime.
+pub struct AsyncThermalPrinter<B: PrinterBackend = UsbBackend<rusb::GlobalContext>> {
+    printer: Arc<Mutex<ThermalPrinter<B>>>,
+}
+impl<B: PrinterBackend> Clone for AsyncThermalPrinter<B> {
+    fn clone(&self) -> Self {
+        AsyncThermalPrinter {
+            printer: Arc::clone(&self.printer),
+        }
+    }
+}
+impl<B: PrinterBackend + Send + 'static> AsyncThermalPrinter<B> {
+    pub fn new(printer: ThermalPrinter<B>) -> Self {
+        AsyncThermalPrinter {
+            printer: Arc::new(Mutex::new(printer)),
+        }
+    }
+
+    /// Runs `f` with the printer on the blocking thread pool, e.g. to change settings or call a method that has no
+    /// async version here. Panics in `f` are passed on to the caller.
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut ThermalPrinter<B>) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let printer = Arc::clone(&self.printer);
+        let task = tokio::task::spawn_blocking(move || {
+            // A call that panicked can't have left the printer in a state that's unsafe to use
+            let mut printer = printer
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&mut printer)
+        });
+        match task.await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => bail!("The printer task was cancelled"),
+        }
+    }
+
+    /// See `ThermalPrinter::print()`
+    pub async fn print<L: Into<RasterLines>>(&self, raster_lines: L) -> Result<status::Response> {
+        let raster_lines = raster_lines.into();
+        self.run(move |printer| printer.print(raster_lines)).await
+    }
+    /// See `ThermalPrinter::print_with_progress()`. `on_progress` is called on the blocking thread.
+    pub async fn print_with_progress<L, F>(
+        &self,
+        raster_lines: L,
+        on_progress: F,
+    ) -> Result<status::Response>
+    where
+        L: Into<RasterLines>,
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        let raster_lines = raster_lines.into();
+        self.run(move |printer| printer.print_with_progress(raster_lines, on_progress))
+            .await
+    }
+    /// See `ThermalPrinter::print_iter()`. The iterator is run on the blocking thread.
+    pub async fn print_iter<I>(&self, raster_lines: I) -> Result<status::Response>
+    where
+        I: IntoIterator + Send + 'static,
+        I::Item: AsRef<[u8]>,
+    {
+        self.run(move |printer| printer.print_iter(raster_lines))
+            .await
+    }
+    /// See `ThermalPrinter::print_text()`
+    pub async fn print_text(&self, text: &str, opts: &TextPrintOptions) -> Result<()> {
+        let (text, opts) = (text.to_string(), opts.clone());
+        self.run(move |printer| printer.print_text(&text, &opts))
+            .await
+    }
+    /// See `ThermalPrinter::print_series()`
+    pub async fn print_series(
+        &self,
+        template: &str,
+        start: u64,
+        count: u64,
+        opts: &TextPrintOptions,
+    ) -> Result<()> {
+        let (template, opts) = (template.to_string(), opts.clone());
+        self.run(move |printer| printer.print_series(&template, start, count, &opts))
+            .await
+    }
+    /// See `ThermalPrinter::print_image_file()`
+    pub async fn print_image_file(&self, path: &Path, opts: &ImagePrintOptions) -> Result<()> {
+        let (path, opts) = (path.to_path_buf(), *opts);
+        self.run(move |printer| printer.print_image_file(&path, &opts))
+            .await
+    }
+    /// See `ThermalPrinter::print_blocking()`. Only the blocking thread waits for the printer to finish.
+    pub async fn print_blocking<L: Into<RasterLines>>(&self, raster_lines: L) -> Result<()> {
+        let raster_lines = raster_lines.into();
+        self.run(move |printer| printer.print_blocking(raster_lines))
+            .await
+    }
+    /// See `ThermalPrinter::print_and_wait()`. `on_status` is called on the blocking thread.
+    pub async fn print_and_wait<L, F>(
+        &self,
+        raster_lines: L,
+        timeout: Duration,
+        on_status: F,
+    ) -> Result<PrintOutcome>
+    where
+        L: Into<RasterLines>,
+        F: FnMut(&status::PrinterStatus) + Send + 'static,
+    {
+        let raster_lines = raster_lines.into();
+        self.run(move |printer| printer.print_and_wait(raster_lines, timeout, on_status))
+            .await
+    }
+    /// See `ThermalPrinter::print_labels()`
+    pub async fn print_labels<I, L>(&self, labels: I) -> Result<status::Response>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<RasterLines>,
+    {
+        let labels: Vec<RasterLines> = labels.into_iter().map(Into::into).collect();
+        self.run(move |printer| printer.print_labels(labels)).await
+    }
+    /// See `ThermalPrinter::print_batch()`
+    pub async fn print_batch<I, L>(&self, jobs: I, cut: CutBehavior) -> Result<status::Response>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<RasterLines>,
+    {
+        let jobs: Vec<RasterLines> = jobs.into_iter().map(Into::into).collect();
+        self.run(move |printer| printer.print_batch(jobs, cut))
+            .await
+    }
+    /// See `ThermalPrinter::print_svg()`. Needs the `svg` feature.
+    #[cfg(feature = "svg")]
+    pub async fn print_svg(&self, svg_data: &str, opts: &ImagePrintOptions) -> Result<()> {
+        let (svg_data, opts) = (svg_data.to_string(), *opts);
+        self.run(move |printer| printer.print_svg(&svg_data, &opts))
+            .await
+    }
+    /// See `ThermalPrinter::print_two_color()`
+    pub async fn print_two_color(
+        &self,
+        raster_lines: TwoColorRasterLines,
+    ) -> Result<status::Response> {
+        self.run(move |printer| printer.print_two_color(raster_lines))
+            .await
+    }
+    /// See `ThermalPrinter::status()`
+    pub async fn status(&self) -> Result<status::PrinterStatus> {
+        self.run(|printer| printer.status()).await
+    }
+    /// See `ThermalPrinter::get_status()`
+    pub async fn get_status(&self) -> Result<status::Response> {
+        self.run(|printer| printer.get_status()).await
+    }
+    /// See `ThermalPrinter::detect_label()`
+    pub async fn detect_label(&self) -> Result<constants::Label> {
+        self.run(|printer| printer.detect_label()).await
+    }
+    /// See `ThermalPrinter::wait_until_ready()`
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<status::PrinterStatus> {
+        self.run(move |printer| printer.wait_until_ready(timeout))
+            .await
+    }
+}
+impl<B: PrinterBackend + Send + 'static> From<ThermalPrinter<B>> for AsyncThermalPrinter<B> {
+    fn from(printer: ThermalPrinter<B>) -> Self {
+        Self::new(printer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncThermalPrinter;
+    use crate::printer::backend::MemoryBackend;
+    use crate::printer::{constants, ThermalPrinter};
+
+    #[test]
+    fn printing_from_async_code() {
+        let label = constants::label_data(62, None).unwrap();
+        let printer = ThermalPrinter::with_backend(MemoryBackend::new(label), 0x2042).unwrap();
+        let printer = AsyncThermalPrinter::new(printer);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert_eq!(printer.detect_label().await.unwrap().name, "62");
+            assert_eq!(printer.status().await.unwrap().media_width_mm, 62);
+            printer.print_iter(vec![[0xFF; 90]; 2]).await.unwrap();
+            printer.print(vec![[0xFF; 90]; 3]).await.unwrap();
+            let sent = printer
+                .run(|printer| Ok(printer.backend().sent().to_vec()))
+                .await
+                .unwrap();
+            assert_eq!(sent.last(), Some(&0x1A));
+        });
+    }
+}
@@ -0,0 +1,327 @@
+//! Transports a `ThermalPrinter` sends its commands over
+//!
+//! `UsbBackend` talks to a real printer. `MemoryBackend` records every byte instead, which lets the command stream
+//! for a label be checked without a printer attached.
+
+use super::constants::Label;
+use super::{ErrorKind, Result, ResultExt};
+use std::cell::{Cell, Ref, RefCell};
+use std::time::Duration;
+
+/// Something a `ThermalPrinter` can send commands to and read 32 byte status responses from
+pub trait PrinterBackend {
+    /// Sends all of `data` to the printer
+    fn write(&self, data: &[u8]) -> Result<()>;
+    /// Reads a status response into `buffer` and returns the number of bytes read
+    fn read(&self, buffer: &mut [u8]) -> Result<usize>;
+}
+
+/// Runs a USB `transfer`. If the endpoint stalls it is cleared with `clear_halt` and the transfer is tried again,
+/// up to `retries` more times. A timed out transfer becomes `ErrorKind::Timeout`.
+fn retry_transfer<F, C>(
+    retries: u8,
+    timeout: Duration,
+    mut transfer: F,
+    mut clear_halt: C,
+) -> Result<usize>
+where
+    F: FnMut() -> rusb::Result<usize>,
+    C: FnMut() -> rusb::Result<()>,
+{
+    let mut attempts = 0;
+    loop {
+        match transfer() {
+            Ok(bytes) => return Ok(bytes),
+            Err(rusb::Error::Pipe) if attempts < retries => {
+                attempts += 1;
+                clear_halt()?;
+            }
+            Err(rusb::Error::Timeout) => bail!(ErrorKind::Timeout(timeout)),
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Which USB interface and endpoints `UsbBackend::open_with()` uses. Everything left as `None` is found from the
+/// device's descriptors: the first interface and its bulk in and out endpoints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsbSettings {
+    /// Number of the interface to claim
+    pub interface: Option<u8>,
+    /// Address of the bulk endpoint status responses are read from, e.g. `0x81`
+    pub in_endpoint: Option<u8>,
+    /// Address of the bulk endpoint commands are written to, e.g. `0x02`
+    pub out_endpoint: Option<u8>,
+    /// Detach the kernel driver (e.g. `usblp` on Linux) from the interface if it's attached. Defaults to `true`.
+    pub detach_kernel_driver: bool,
+}
+impl Default for UsbSettings {
+    fn default() -> Self {
+        UsbSettings {
+            interface: None,
+            in_endpoint: None,
+            out_endpoint: None,
+            detach_kernel_driver: true,
+        }
+    }
+}
+
+/// Picks the address of the bulk endpoint going in `direction` from the `(address, transfer type, direction)` of
+/// every endpoint of an interface: `wanted` if the interface has it, otherwise the first. Addresses that aren't
+/// bulk endpoints in the right direction are never picked.
+fn find_endpoint(
+    endpoints: &[(u8, rusb::TransferType, rusb::Direction)],
+    direction: rusb::Direction,
+    wanted: Option<u8>,
+) -> Option<u8> {
+    endpoints
+        .iter()
+        .filter(|&&(_, transfer_type, endpoint_direction)| {
+            transfer_type == rusb::TransferType::Bulk && endpoint_direction == direction
+        })
+        .map(|&(address, _, _)| address)
+        .find(|&address| wanted.is_none_or(|wanted| wanted == address))
+}
+
+/// Communicates with a printer over its USB bulk endpoints
+pub struct UsbBackend<T: rusb::UsbContext> {
+    handle: rusb::DeviceHandle<T>,
+    in_endpoint: u8,
+    out_endpoint: u8,
+    timeout: Duration,
+    retries: u8,
+}
+impl<T: rusb::UsbContext> UsbBackend<T> {
+    /// Opens `device` and claims its interface, detaching the kernel driver if necessary
+    pub fn open(device: &rusb::Device<T>) -> Result<Self> {
+        Self::open_with(device, &UsbSettings::default())
+    }
+    /// Same as `open()` but with the interface and endpoints chosen in `settings`, for printers behind adapters or
+    /// hubs that don't show up as usual. Fails with `MissingEndpoint` if the interface has no bulk endpoint (or not
+    /// the one chosen) in either direction.
+    pub fn open_with(device: &rusb::Device<T>, settings: &UsbSettings) -> Result<Self> {
+        let handle = device.open()?;
+
+        let config = device.active_config_descriptor()?;
+        let interface = match settings.interface {
+            Some(number) => config
+                .interfaces()
+                .find(|interface| interface.number() == number)
+                .chain_err(|| format!("The printer has no USB interface {}", number))?,
+            None => config
+                .interfaces()
+                .next()
+                .chain_err(|| "Brother QL printers should have exactly one interface")?,
+        };
+        let interface_descriptor = interface
+            .descriptors()
+            .next()
+            .chain_err(|| "Brother QL printers should have exactly one interface descriptor")?;
+        let endpoints: Vec<_> = interface_descriptor
+            .endpoint_descriptors()
+            .map(|endpoint| {
+                (
+                    endpoint.address(),
+                    endpoint.transfer_type(),
+                    endpoint.direction(),
+                )
+            })
+            .collect();
+        let endpoint = |direction, wanted| -> Result<u8> {
+            find_endpoint(&endpoints, direction, wanted).ok_or_else(|| {
+                ErrorKind::MissingEndpoint(interface.number(), direction, wanted).into()
+            })
+        };
+        let in_endpoint = endpoint(rusb::Direction::In, settings.in_endpoint)?;
+        let out_endpoint = endpoint(rusb::Direction::Out, settings.out_endpoint)?;
+
+        if settings.detach_kernel_driver {
+            // Only Linux reports (and lets us detach) kernel drivers
+            if let Ok(true) = handle.kernel_driver_active(interface.number()) {
+                handle.detach_kernel_driver(interface.number())?;
+            }
+        }
+        handle.claim_interface(interface.number())?;
+
+        Ok(UsbBackend {
+            handle,
+            in_endpoint,
+            out_endpoint,
+            timeout: Duration::from_millis(500),
+            retries: 0,
+        })
+    }
+    /// The open USB device
+    pub fn handle(&self) -> &rusb::DeviceHandle<T> {
+        &self.handle
+    }
+    /// How long a single read or write may take before failing with `ErrorKind::Timeout`. Defaults to 500ms.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    /// How many times a read or write is retried after the endpoint stalls (`LIBUSB_ERROR_PIPE`). The stall is
+    /// cleared before each retry. Defaults to 0.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+}
+impl<T: rusb::UsbContext> PrinterBackend for UsbBackend<T> {
+    /// Writes all of `data`, continuing after partial writes
+    fn write(&self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let written = retry_transfer(
+                self.retries,
+                self.timeout,
+                || {
+                    self.handle
+                        .write_bulk(self.out_endpoint, data, self.timeout)
+                },
+                || self.handle.clear_halt(self.out_endpoint),
+            )?;
+            if written == 0 {
+                bail!(ErrorKind::Timeout(self.timeout));
+            }
+            data = &data[written..];
+        }
+        Ok(())
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+        retry_transfer(
+            self.retries,
+            self.timeout,
+            || {
+                self.handle
+                    .read_bulk(self.in_endpoint, buffer, self.timeout)
+            },
+            || self.handle.clear_halt(self.in_endpoint),
+        )
+    }
+}
+
+/// Records everything sent to it instead of printing
+///
+/// Status requests are answered the way a ready printer with `label` loaded would answer them. Once the print command
+/// has been sent the printer reports that printing has completed.
+pub struct MemoryBackend {
+    label: Label,
+    sent: RefCell<Vec<u8>>,
+    printed: Cell<bool>,
+}
+impl MemoryBackend {
+    pub fn new(label: Label) -> Self {
+        MemoryBackend {
+            label,
+            sent: RefCell::new(Vec::new()),
+            printed: Cell::new(false),
+        }
+    }
+    /// Every byte sent so far
+    pub fn sent(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.sent.borrow(), Vec::as_slice)
+    }
+    /// Forgets the bytes sent so far, e.g. to skip the reset sent when the printer is created
+    pub fn clear(&self) {
+        self.sent.borrow_mut().clear();
+    }
+}
+impl PrinterBackend for MemoryBackend {
+    fn write(&self, data: &[u8]) -> Result<()> {
+        self.sent.borrow_mut().extend_from_slice(data);
+        self.printed.set(data == [0x1A]);
+        Ok(())
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+        let mut response = [0; 32];
+        response[0] = 0x80;
+        response[1] = 0x20;
+        response[2] = 0x42;
+        response[3] = 0x30;
+        response[10] = self.label.tape_size.0 as u8;
+        response[11] = if self.label.tape_size.1 == 0 {
+            0x0A
+        } else {
+            0x0B
+        };
+        response[17] = self.label.tape_size.1 as u8;
+        response[18] = self.printed.get() as u8;
+        let length = response.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&response[..length]);
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_endpoint, retry_transfer, MemoryBackend, PrinterBackend};
+    use crate::printer::constants::label_data;
+    use crate::printer::status::{MediaType, PrinterStatus, StatusType};
+    use crate::printer::ErrorKind;
+    use std::time::Duration;
+
+    #[test]
+    fn stalled_transfers_are_retried() {
+        let timeout = Duration::from_millis(100);
+        let mut results = vec![Ok(4), Err(rusb::Error::Pipe), Err(rusb::Error::Pipe)];
+        let mut clears = 0;
+        let bytes = retry_transfer(
+            2,
+            timeout,
+            || results.pop().unwrap(),
+            || {
+                clears += 1;
+                Ok(())
+            },
+        );
+        assert_eq!(bytes.unwrap(), 4);
+        assert_eq!(clears, 2);
+
+        let mut results = vec![Ok(4), Err(rusb::Error::Pipe), Err(rusb::Error::Pipe)];
+        let error = retry_transfer(1, timeout, || results.pop().unwrap(), || Ok(())).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::USB(rusb::Error::Pipe)));
+
+        let error =
+            retry_transfer(3, timeout, || Err(rusb::Error::Timeout), || Ok(())).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Timeout(t) if *t == timeout));
+    }
+
+    #[test]
+    fn finding_endpoints() {
+        use rusb::{Direction, TransferType};
+        let endpoints = [
+            (0x83, TransferType::Interrupt, Direction::In),
+            (0x81, TransferType::Bulk, Direction::In),
+            (0x02, TransferType::Bulk, Direction::Out),
+            (0x84, TransferType::Bulk, Direction::In),
+        ];
+        assert_eq!(find_endpoint(&endpoints, Direction::In, None), Some(0x81));
+        assert_eq!(find_endpoint(&endpoints, Direction::Out, None), Some(0x02));
+        assert_eq!(
+            find_endpoint(&endpoints, Direction::In, Some(0x84)),
+            Some(0x84)
+        );
+        // Endpoints that aren't bulk endpoints in the right direction can't be chosen
+        assert_eq!(find_endpoint(&endpoints, Direction::In, Some(0x83)), None);
+        assert_eq!(find_endpoint(&endpoints, Direction::Out, Some(0x81)), None);
+        assert_eq!(find_endpoint(&endpoints[..2], Direction::Out, None), None);
+    }
+
+    #[test]
+    fn memory_backend_reports_its_label() {
+        let backend = MemoryBackend::new(label_data(62, Some(29)).unwrap());
+        let mut response = [0; 32];
+        assert_eq!(backend.read(&mut response).unwrap(), 32);
+        let status = PrinterStatus::parse(&response).unwrap();
+        assert!(matches!(status.media_type, MediaType::DieCutLabels));
+        assert_eq!((status.media_width_mm, status.media_length_mm), (62, 29));
+        assert_eq!(status.status_type, StatusType::ReplyToStatusRequest);
+
+        backend.write(&[0x1B, 0x40]).unwrap();
+        backend.write(&[0x1A]).unwrap();
+        backend.read(&mut response).unwrap();
+        let status = PrinterStatus::parse(&response).unwrap();
+        assert_eq!(status.status_type, StatusType::PrintingCompleted);
+        assert_eq!(&*backend.sent(), [0x1B, 0x40, 0x1A]);
+    }
+}
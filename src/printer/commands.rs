@@ -0,0 +1,387 @@
+//! Decode and encode the raw command stream sent to Brother QL printers
+//!
+//! `parse()` splits a captured print job (e.g. one written by `ThermalPrinter::print_to_writer()` or captured from
+//! Brother's own driver) into `QlCommand`s so jobs can be inspected and compared. `encode()` turns the commands back
+//! into the exact same bytes.
+
+error_chain! {
+    types {
+        ParseError, ParseErrorKind, ResultExt, Result;
+    }
+    errors {
+        UnknownCommand(offset: usize, byte: u8) {
+            description("unknown command in print job")
+            display("Unknown command 0x{:02X} at byte {}", byte, offset)
+        }
+        Truncated(offset: usize) {
+            description("print job ends in the middle of a command")
+            display("Command at byte {} is cut off by the end of the job", offset)
+        }
+    }
+}
+
+/// Valid flag of `QlCommand::MediaInfo`: the printer checks the media type
+pub const PI_KIND: u8 = 0x02;
+/// Valid flag of `QlCommand::MediaInfo`: the printer checks the media width
+pub const PI_WIDTH: u8 = 0x04;
+/// Valid flag of `QlCommand::MediaInfo`: the printer checks the media length
+pub const PI_LENGTH: u8 = 0x08;
+/// Valid flag of `QlCommand::MediaInfo`: print quality takes priority over speed
+pub const PI_QUALITY: u8 = 0x40;
+/// Valid flag of `QlCommand::MediaInfo`: the printer recovers and carries on after errors it can fix itself
+pub const PI_RECOVER: u8 = 0x80;
+/// `media_type` of `QlCommand::MediaInfo` for continuous tape
+pub const MEDIA_CONTINUOUS: u8 = 0x0A;
+/// `media_type` of `QlCommand::MediaInfo` for die-cut labels
+pub const MEDIA_DIE_CUT: u8 = 0x0B;
+
+/// The mode settings of a job: the `ESC i M` various mode and `ESC i K` expanded mode flags along with the print
+/// quality flag of `ESC i z`. Starts with every bit off. Pass one to `ThermalPrinter::set_mode_command()` to send
+/// exactly these bits instead of the ones worked out from the printer's settings.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ModeCommand {
+    various_mode: u8,
+    expanded_mode: u8,
+    quality_priority: bool,
+}
+impl ModeCommand {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn with_bit(flags: u8, bit: u8, enabled: bool) -> u8 {
+        if enabled {
+            flags | bit
+        } else {
+            flags & !bit
+        }
+    }
+    /// Bit 6 of the various mode: cut automatically, every `n` labels as set by `ESC i A`
+    pub fn auto_cut(mut self, enabled: bool) -> Self {
+        self.various_mode = Self::with_bit(self.various_mode, 0x40, enabled);
+        self
+    }
+    /// Bit 3 of the expanded mode: cut after the last label of the job
+    pub fn cut_at_end(mut self, enabled: bool) -> Self {
+        self.expanded_mode = Self::with_bit(self.expanded_mode, 0x08, enabled);
+        self
+    }
+    /// Keeps the labels of consecutive jobs chained, which is the same as not cutting at the end
+    pub fn chain_printing(self, enabled: bool) -> Self {
+        self.cut_at_end(!enabled)
+    }
+    /// Bit 6 of the expanded mode: print at 600dpi along the tape (see `Resolution::High`)
+    pub fn high_resolution(mut self, enabled: bool) -> Self {
+        self.expanded_mode = Self::with_bit(self.expanded_mode, 0x40, enabled);
+        self
+    }
+    /// Bit 0 of the expanded mode: print in black and red on two-color media
+    pub fn two_color(mut self, enabled: bool) -> Self {
+        self.expanded_mode = Self::with_bit(self.expanded_mode, 0x01, enabled);
+        self
+    }
+    /// `PI_QUALITY` in the valid flags of the print information command: give priority to quality over speed
+    pub fn quality_priority(mut self, enabled: bool) -> Self {
+        self.quality_priority = enabled;
+        self
+    }
+    /// Sets further bits of the expanded mode as they are, e.g. to try out bits that aren't documented
+    pub fn extra_bits(mut self, bits: u8) -> Self {
+        self.expanded_mode |= bits;
+        self
+    }
+    /// Sets further bits of the various mode as they are
+    pub fn extra_various_bits(mut self, bits: u8) -> Self {
+        self.various_mode |= bits;
+        self
+    }
+
+    /// The `ESC i M` flags
+    pub fn various_mode(&self) -> u8 {
+        self.various_mode
+    }
+    /// The `ESC i K` flags
+    pub fn expanded_mode(&self) -> u8 {
+        self.expanded_mode
+    }
+    pub fn is_auto_cut(&self) -> bool {
+        self.various_mode & 0x40 != 0
+    }
+    pub fn is_quality_priority(&self) -> bool {
+        self.quality_priority
+    }
+    /// The various and expanded mode commands
+    pub fn commands(&self) -> [QlCommand; 2] {
+        [
+            QlCommand::VariousMode(self.various_mode),
+            QlCommand::ExpandedMode(self.expanded_mode),
+        ]
+    }
+}
+
+/// A single command in a print job
+#[derive(Debug, Clone, PartialEq)]
+pub enum QlCommand {
+    /// A run of this many `0x00` bytes, sent to clear out any half received command before a job
+    Invalidate(usize),
+    /// `ESC @` resets the printer's settings to their defaults
+    Initialize,
+    /// `ESC i S` asks the printer to reply with its 32 byte status
+    StatusRequest,
+    /// `ESC i !` turns automatic status notifications on (`0`) or off (`1`)
+    NotificationMode(u8),
+    /// `ESC i a` switches the command mode, `1` for raster mode
+    SwitchMode(u8),
+    /// `ESC i z` describes the media and the page that follows
+    MediaInfo {
+        /// Which of the following fields the printer should check, made of the `PI_*` flags
+        valid_flags: u8,
+        /// `MEDIA_CONTINUOUS` or `MEDIA_DIE_CUT`
+        media_type: u8,
+        width_mm: u8,
+        /// `0` for continuous tape
+        length_mm: u8,
+        /// Number of raster lines on the page
+        line_count: u32,
+        /// `0` on the first page of a job, `1` on the following pages
+        page: u8,
+    },
+    /// `ESC i M` sets the various mode flags, bit 6 enables auto-cut
+    VariousMode(u8),
+    /// `ESC i A` cuts after every `n` labels when auto-cut is enabled
+    CutEvery(u8),
+    /// `ESC i K` sets the expanded mode flags: bit 0 two-color printing, bit 3 cut at end and bit 6 high resolution
+    ExpandedMode(u8),
+    /// `ESC i d` sets the feed margin in dots
+    Margin(u16),
+    /// `M` selects the raster data compression, `0x02` for TIFF (PackBits)
+    Compression(u8),
+    /// `g` sends one raster line. The data is PackBits compressed if compression is enabled.
+    RasterLine(Vec<u8>),
+    /// `w` sends one raster line of a two-color image's black (`1`) or red (`2`) plane
+    TwoColorRasterLine { plane: u8, data: Vec<u8> },
+    /// `Z` sends a blank raster line in compression mode
+    BlankLine,
+    /// `FF` prints the page and continues with the next one
+    Print,
+    /// `Ctrl-Z` prints the last page of the job, then feeds and cuts the tape
+    PrintWithFeeding,
+}
+
+impl QlCommand {
+    /// Appends the bytes of this command to `out`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            QlCommand::Invalidate(count) => out.resize(out.len() + count, 0x00),
+            QlCommand::Initialize => out.extend_from_slice(&[0x1B, 0x40]),
+            QlCommand::StatusRequest => out.extend_from_slice(&[0x1B, 0x69, 0x53]),
+            QlCommand::NotificationMode(mode) => out.extend_from_slice(&[0x1B, 0x69, 0x21, mode]),
+            QlCommand::SwitchMode(mode) => out.extend_from_slice(&[0x1B, 0x69, 0x61, mode]),
+            QlCommand::MediaInfo {
+                valid_flags,
+                media_type,
+                width_mm,
+                length_mm,
+                line_count,
+                page,
+            } => {
+                out.extend_from_slice(&[
+                    0x1B,
+                    0x69,
+                    0x7A,
+                    valid_flags,
+                    media_type,
+                    width_mm,
+                    length_mm,
+                ]);
+                out.extend_from_slice(&line_count.to_le_bytes());
+                out.extend_from_slice(&[page, 0]);
+            }
+            QlCommand::VariousMode(flags) => out.extend_from_slice(&[0x1B, 0x69, 0x4D, flags]),
+            QlCommand::CutEvery(labels) => out.extend_from_slice(&[0x1B, 0x69, 0x41, labels]),
+            QlCommand::ExpandedMode(flags) => out.extend_from_slice(&[0x1B, 0x69, 0x4B, flags]),
+            QlCommand::Margin(dots) => {
+                out.extend_from_slice(&[0x1B, 0x69, 0x64]);
+                out.extend_from_slice(&dots.to_le_bytes());
+            }
+            QlCommand::Compression(mode) => out.extend_from_slice(&[0x4D, mode]),
+            QlCommand::RasterLine(ref data) => super::frame_raster_line(data, None, out),
+            QlCommand::TwoColorRasterLine { plane, ref data } => {
+                super::frame_raster_line(data, Some(plane), out)
+            }
+            QlCommand::BlankLine => out.push(0x5A),
+            QlCommand::Print => out.push(0x0C),
+            QlCommand::PrintWithFeeding => out.push(0x1A),
+        }
+    }
+}
+
+/// Turns `commands` back into the bytes of a print job
+pub fn encode(commands: &[QlCommand]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for command in commands {
+        command.encode(&mut out);
+    }
+    out
+}
+
+/// Splits the bytes of a print job into commands. Fails on commands this module doesn't know and on jobs that end in
+/// the middle of a command.
+pub fn parse(bytes: &[u8]) -> Result<Vec<QlCommand>> {
+    let mut commands = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        // Takes the `length` bytes of the command (or fails if the job ends first)
+        let take = |length: usize| -> Result<&[u8]> {
+            rest.get(..length)
+                .ok_or_else(|| ParseErrorKind::Truncated(offset).into())
+        };
+        let (command, length) = match rest[0] {
+            0x00 => {
+                let count = rest.iter().take_while(|&&byte| byte == 0x00).count();
+                (QlCommand::Invalidate(count), count)
+            }
+            0x1B => match take(2)?[1] {
+                0x40 => (QlCommand::Initialize, 2),
+                0x69 => {
+                    let command = take(3)?[2];
+                    match command {
+                        0x53 => (QlCommand::StatusRequest, 3),
+                        0x21 => (QlCommand::NotificationMode(take(4)?[3]), 4),
+                        0x61 => (QlCommand::SwitchMode(take(4)?[3]), 4),
+                        0x4D => (QlCommand::VariousMode(take(4)?[3]), 4),
+                        0x41 => (QlCommand::CutEvery(take(4)?[3]), 4),
+                        0x4B => (QlCommand::ExpandedMode(take(4)?[3]), 4),
+                        0x64 => {
+                            let data = take(5)?;
+                            (QlCommand::Margin(u16::from_le_bytes([data[3], data[4]])), 5)
+                        }
+                        0x7A => {
+                            let data = take(13)?;
+                            let mut line_count = [0; 4];
+                            line_count.copy_from_slice(&data[7..11]);
+                            let command = QlCommand::MediaInfo {
+                                valid_flags: data[3],
+                                media_type: data[4],
+                                width_mm: data[5],
+                                length_mm: data[6],
+                                line_count: u32::from_le_bytes(line_count),
+                                page: data[11],
+                            };
+                            (command, 13)
+                        }
+                        _ => bail!(ParseErrorKind::UnknownCommand(offset + 2, command)),
+                    }
+                }
+                byte => bail!(ParseErrorKind::UnknownCommand(offset + 1, byte)),
+            },
+            0x4D => (QlCommand::Compression(take(2)?[1]), 2),
+            0x67 | 0x77 => {
+                let length = take(3)?[2] as usize + 3;
+                let data = take(length)?[3..].to_vec();
+                let command = match rest[0] {
+                    0x67 => QlCommand::RasterLine(data),
+                    _ => QlCommand::TwoColorRasterLine {
+                        plane: rest[1],
+                        data,
+                    },
+                };
+                (command, length)
+            }
+            0x5A => (QlCommand::BlankLine, 1),
+            0x0C => (QlCommand::Print, 1),
+            0x1A => (QlCommand::PrintWithFeeding, 1),
+            byte => bail!(ParseErrorKind::UnknownCommand(offset, byte)),
+        };
+        commands.push(command);
+        offset += length;
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, parse, ModeCommand, ParseErrorKind, QlCommand};
+
+    #[test]
+    fn parsing_jobs() {
+        let mut job = vec![0x00; 200];
+        job.extend_from_slice(&[0x1B, 0x40, 0x1B, 0x69, 0x61, 0x01]);
+        job.extend_from_slice(&[0x1B, 0x69, 0x7A, 0xCE, 0x0B, 62, 29, 0x02, 0x01, 0, 0, 0, 0]);
+        job.extend_from_slice(&[0x1B, 0x69, 0x64, 0x23, 0x00, 0x4D, 0x02]);
+        job.extend_from_slice(&[
+            0x67, 0x00, 0x02, 0xFD, 0xAA, 0x5A, 0x77, 0x02, 0x01, 0xFF, 0x1A,
+        ]);
+
+        let commands = parse(&job).unwrap();
+        assert_eq!(
+            commands,
+            [
+                QlCommand::Invalidate(200),
+                QlCommand::Initialize,
+                QlCommand::SwitchMode(1),
+                QlCommand::MediaInfo {
+                    valid_flags: 0xCE,
+                    media_type: 0x0B,
+                    width_mm: 62,
+                    length_mm: 29,
+                    line_count: 258,
+                    page: 0,
+                },
+                QlCommand::Margin(35),
+                QlCommand::Compression(2),
+                QlCommand::RasterLine(vec![0xFD, 0xAA]),
+                QlCommand::BlankLine,
+                QlCommand::TwoColorRasterLine {
+                    plane: 2,
+                    data: vec![0xFF],
+                },
+                QlCommand::PrintWithFeeding,
+            ]
+        );
+        assert_eq!(encode(&commands), job);
+    }
+
+    #[test]
+    fn building_mode_commands() {
+        let mode = ModeCommand::new()
+            .auto_cut(true)
+            .cut_at_end(true)
+            .high_resolution(true)
+            .quality_priority(true);
+        assert_eq!((mode.various_mode(), mode.expanded_mode()), (0x40, 0x48));
+        assert!(mode.is_auto_cut() && mode.is_quality_priority());
+        assert_eq!(
+            encode(&mode.commands()),
+            [0x1B, 0x69, 0x4D, 0x40, 0x1B, 0x69, 0x4B, 0x48]
+        );
+
+        let mode = mode
+            .chain_printing(true)
+            .high_resolution(false)
+            .two_color(true);
+        assert_eq!(mode.expanded_mode(), 0x01);
+        let mode = mode
+            .extra_bits(0x24)
+            .extra_various_bits(0x01)
+            .auto_cut(false);
+        assert_eq!((mode.various_mode(), mode.expanded_mode()), (0x01, 0x25));
+        assert_eq!(ModeCommand::new().expanded_mode(), 0);
+    }
+
+    #[test]
+    fn invalid_jobs() {
+        let error = parse(&[0x1B, 0x40, 0x42]).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ParseErrorKind::UnknownCommand(2, 0x42)
+        ));
+        let error = parse(&[0x1B, 0x69, 0x7F]).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ParseErrorKind::UnknownCommand(2, 0x7F)
+        ));
+        let error = parse(&[0x1A, 0x67, 0x00, 0x05, 0xFF]).unwrap_err();
+        assert!(matches!(error.kind(), ParseErrorKind::Truncated(1)));
+    }
+}
@@ -1,169 +1,568 @@
 //! Label media and USB ID constants used by Brother QL printers
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WidthLength(pub u32, pub u32);
 
-#[derive(Debug, Copy, Clone)]
+/// The outline of a label
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LabelShape {
+    /// Continuous tape and rectangular die-cut labels
+    Rectangle,
+    /// Round die-cut labels as wide as they are long. `TextRasterizer` keeps content inside the printable circle.
+    Round,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Label {
+    /// The label's size as `"<width>x<length>"` in millimeters, `"d<diameter>"` for round labels or just `"<width>"` for
+    /// continuous tape
+    pub name: &'static str,
+    /// Brother's part number for the roll, e.g. `"DK-11201"`
+    pub part_number: Option<&'static str>,
+    pub shape: LabelShape,
     pub tape_size: WidthLength,
     pub dots: WidthLength,
     pub dots_printable: WidthLength,
     pub right_margin: u8,
     pub feed_margin: u8,
 }
-
-/// Returns a corresponding label type given dimensions returned by the printer
-///
-/// These are predefined label rolls types sold by Brother and defined in the spec
-pub fn label_data(width: u8, length: Option<u8>) -> Option<Label> {
-    if let Some(length) = length {
-        // Die cut label
-        match (width, length) {
-            (17, 54) => Some(Label {
-                tape_size: WidthLength(17, 54),
-                dots: WidthLength(201, 636),
-                dots_printable: WidthLength(165, 566),
-                right_margin: 0,
-                feed_margin: 0,
-            }),
-            (17, 87) => Some(Label {
-                tape_size: WidthLength(17, 87),
-                dots: WidthLength(201, 1026),
-                dots_printable: WidthLength(165, 956),
-                right_margin: 0,
-                feed_margin: 0,
-            }),
-            (23, 23) => Some(Label {
-                tape_size: WidthLength(23, 23),
-                dots: WidthLength(272, 272),
-                dots_printable: WidthLength(202, 202),
-                right_margin: 42,
-                feed_margin: 0,
-            }),
-            (29, 42) => Some(Label {
-                tape_size: WidthLength(29, 42),
-                dots: WidthLength(342, 495),
-                dots_printable: WidthLength(306, 425),
-                right_margin: 6,
-                feed_margin: 0,
-            }),
-            (29, 90) => Some(Label {
-                tape_size: WidthLength(29, 90),
-                dots: WidthLength(342, 1061),
-                dots_printable: WidthLength(306, 991),
-                right_margin: 6,
-                feed_margin: 0,
-            }),
-            (39, 90) => Some(Label {
-                tape_size: WidthLength(38, 90),
-                dots: WidthLength(449, 1061),
-                dots_printable: WidthLength(413, 991),
-                right_margin: 12,
-                feed_margin: 0,
-            }),
-            (39, 48) => Some(Label {
-                tape_size: WidthLength(39, 48),
-                dots: WidthLength(461, 565),
-                dots_printable: WidthLength(425, 495),
-                right_margin: 6,
-                feed_margin: 0,
-            }),
-            (52, 29) => Some(Label {
-                tape_size: WidthLength(52, 29),
-                dots: WidthLength(614, 341),
-                dots_printable: WidthLength(578, 271),
-                right_margin: 0,
-                feed_margin: 0,
-            }),
-            (62, 29) => Some(Label {
-                tape_size: WidthLength(62, 29),
-                dots: WidthLength(732, 341),
-                dots_printable: WidthLength(696, 271),
-                right_margin: 12,
-                feed_margin: 0,
-            }),
-            (62, 100) => Some(Label {
-                tape_size: WidthLength(62, 100),
-                dots: WidthLength(732, 1179),
-                dots_printable: WidthLength(696, 1109),
-                right_margin: 12,
-                feed_margin: 0,
-            }),
-            _ => None,
+impl Label {
+    /// Number of dots at the start of each raster line that this label covers: the unprintable right margin followed
+    /// by the printable width of the tape
+    pub fn raster_width(&self) -> u32 {
+        self.dots_printable.0 + self.right_margin as u32
+    }
+    /// The smallest raster line length (in bytes) able to hold the printable width of this label
+    ///
+    /// Wide media like 102mm tape only fits on printers with a `WIDE_RASTER_LINE_LENGTH` print head.
+    pub fn raster_line_length(&self) -> usize {
+        if self.raster_width() > RASTER_LINE_LENGTH as u32 * 8 {
+            WIDE_RASTER_LINE_LENGTH
+        } else {
+            RASTER_LINE_LENGTH
         }
-    } else {
-        // Continuous label
-        match width {
-            12 => Some(Label {
-                tape_size: WidthLength(12, 0),
-                dots: WidthLength(142, 0),
-                dots_printable: WidthLength(106, 0),
-                right_margin: 29,
-                feed_margin: 35,
-            }),
-            29 => Some(Label {
-                tape_size: WidthLength(29, 0),
-                dots: WidthLength(342, 0),
-                dots_printable: WidthLength(306, 0),
-                right_margin: 6,
-                feed_margin: 35,
-            }),
-            38 => Some(Label {
-                tape_size: WidthLength(38, 0),
-                dots: WidthLength(449, 0),
-                dots_printable: WidthLength(413, 0),
-                right_margin: 12,
-                feed_margin: 35,
-            }),
-            50 => Some(Label {
-                tape_size: WidthLength(50, 0),
-                dots: WidthLength(590, 0),
-                dots_printable: WidthLength(554, 0),
-                right_margin: 12,
-                feed_margin: 35,
-            }),
-            54 => Some(Label {
-                tape_size: WidthLength(54, 0),
-                dots: WidthLength(636, 0),
-                dots_printable: WidthLength(590, 0),
-                right_margin: 0,
-                feed_margin: 35,
-            }),
-            62 => Some(Label {
-                tape_size: WidthLength(62, 0),
-                dots: WidthLength(732, 0),
-                dots_printable: WidthLength(696, 0),
-                right_margin: 12,
-                feed_margin: 35,
-            }),
-            102 => Some(Label {
-                tape_size: WidthLength(102, 0),
-                dots: WidthLength(1200, 0),
-                dots_printable: WidthLength(1164, 0),
-                right_margin: 12,
-                feed_margin: 35,
-            }),
-            _ => None,
+    }
+    /// Dots per inch of the print head across this label, which is also the number of dots per inch along the tape
+    /// at `Resolution::Standard`
+    pub fn dpi(&self) -> u32 {
+        DPI
+    }
+    /// Converts a distance on this label in millimeters to the nearest whole number of dots
+    pub fn mm_to_dots(&self, mm: f32) -> u32 {
+        (mm * self.dpi() as f32 / MM_PER_INCH).round().max(0.0) as u32
+    }
+    /// Converts a distance on this label in dots to millimeters
+    pub fn dots_to_mm(&self, dots: u32) -> f32 {
+        dots as f32 * MM_PER_INCH / self.dpi() as f32
+    }
+}
+
+/// `Label` with owned strings, as read from e.g. a config file
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct OwnedLabel {
+    name: String,
+    part_number: Option<String>,
+    shape: LabelShape,
+    tape_size: WidthLength,
+    dots: WidthLength,
+    dots_printable: WidthLength,
+    right_margin: u8,
+    feed_margin: u8,
+}
+
+/// Names and part numbers from the label table are reused. Any other string (e.g. of a custom label) is leaked so it
+/// lives as long as the table's strings.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Label {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        fn intern(string: String) -> &'static str {
+            LABELS
+                .iter()
+                .flat_map(|label| label.part_number.into_iter().chain(Some(label.name)))
+                .find(|known| *known == string)
+                .unwrap_or_else(|| Box::leak(string.into_boxed_str()))
         }
+        let label = OwnedLabel::deserialize(deserializer)?;
+        Ok(Label {
+            name: intern(label.name),
+            part_number: label.part_number.map(intern),
+            shape: label.shape,
+            tape_size: label.tape_size,
+            dots: label.dots,
+            dots_printable: label.dots_printable,
+            right_margin: label.right_margin,
+            feed_margin: label.feed_margin,
+        })
+    }
+}
+
+/// Resolution of the print head of every QL printer in dots per inch. Printers can print twice as many lines per inch
+/// along the tape with `Resolution::High`.
+pub const DPI: u32 = 300;
+const MM_PER_INCH: f32 = 25.4;
+
+/// Bytes in each raster line sent to regular printers like the QL-700 (720 dots)
+pub const RASTER_LINE_LENGTH: usize = 90;
+/// Bytes in each raster line sent to wide format printers like the QL-1100 (1296 dots)
+pub const WIDE_RASTER_LINE_LENGTH: usize = 162;
+
+/// Every label type in the table, die-cut labels first and then continuous tape
+///
+/// These are predefined label rolls types sold by Brother and defined in the spec. Labels wider than 62mm only fit
+/// wide format printers like the QL-1100 (see `Label::raster_line_length()`).
+static LABELS: [Label; 24] = [
+    Label {
+        name: "17x54",
+        part_number: Some("DK-11204"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(17, 54),
+        dots: WidthLength(201, 636),
+        dots_printable: WidthLength(165, 566),
+        right_margin: 0,
+        feed_margin: 0,
+    },
+    Label {
+        name: "17x87",
+        part_number: Some("DK-11203"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(17, 87),
+        dots: WidthLength(201, 1026),
+        dots_printable: WidthLength(165, 956),
+        right_margin: 0,
+        feed_margin: 0,
+    },
+    Label {
+        name: "23x23",
+        part_number: Some("DK-11221"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(23, 23),
+        dots: WidthLength(272, 272),
+        dots_printable: WidthLength(202, 202),
+        right_margin: 42,
+        feed_margin: 0,
+    },
+    Label {
+        name: "29x42",
+        part_number: None,
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(29, 42),
+        dots: WidthLength(342, 495),
+        dots_printable: WidthLength(306, 425),
+        right_margin: 6,
+        feed_margin: 0,
+    },
+    Label {
+        name: "29x90",
+        part_number: Some("DK-11201"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(29, 90),
+        dots: WidthLength(342, 1061),
+        dots_printable: WidthLength(306, 991),
+        right_margin: 6,
+        feed_margin: 0,
+    },
+    Label {
+        name: "38x90",
+        part_number: Some("DK-11208"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(38, 90),
+        dots: WidthLength(449, 1061),
+        dots_printable: WidthLength(413, 991),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "39x48",
+        part_number: None,
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(39, 48),
+        dots: WidthLength(461, 565),
+        dots_printable: WidthLength(425, 495),
+        right_margin: 6,
+        feed_margin: 0,
+    },
+    Label {
+        name: "52x29",
+        part_number: None,
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(52, 29),
+        dots: WidthLength(614, 341),
+        dots_printable: WidthLength(578, 271),
+        right_margin: 0,
+        feed_margin: 0,
+    },
+    Label {
+        name: "62x29",
+        part_number: Some("DK-11209"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(62, 29),
+        dots: WidthLength(732, 341),
+        dots_printable: WidthLength(696, 271),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "62x100",
+        part_number: Some("DK-11202"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(62, 100),
+        dots: WidthLength(732, 1179),
+        dots_printable: WidthLength(696, 1109),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "102x51",
+        part_number: Some("DK-11240"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(102, 51),
+        dots: WidthLength(1200, 660),
+        dots_printable: WidthLength(1164, 590),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "102x152",
+        part_number: Some("DK-11241"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(102, 152),
+        dots: WidthLength(1200, 1822),
+        dots_printable: WidthLength(1164, 1752),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "103x164",
+        part_number: Some("DK-11247"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(103, 164),
+        dots: WidthLength(1224, 1937),
+        dots_printable: WidthLength(1200, 1867),
+        right_margin: 12,
+        feed_margin: 0,
+    },
+    Label {
+        name: "d12",
+        part_number: Some("DK-11219"),
+        shape: LabelShape::Round,
+        tape_size: WidthLength(12, 12),
+        dots: WidthLength(142, 142),
+        dots_printable: WidthLength(94, 94),
+        right_margin: 113,
+        feed_margin: 0,
+    },
+    Label {
+        name: "d24",
+        part_number: Some("DK-11218"),
+        shape: LabelShape::Round,
+        tape_size: WidthLength(24, 24),
+        dots: WidthLength(284, 284),
+        dots_printable: WidthLength(236, 236),
+        right_margin: 42,
+        feed_margin: 0,
+    },
+    Label {
+        name: "d58",
+        part_number: Some("DK-11207"),
+        shape: LabelShape::Round,
+        tape_size: WidthLength(58, 58),
+        dots: WidthLength(688, 688),
+        dots_printable: WidthLength(618, 618),
+        right_margin: 51,
+        feed_margin: 0,
+    },
+    Label {
+        name: "12",
+        part_number: Some("DK-22214"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(12, 0),
+        dots: WidthLength(142, 0),
+        dots_printable: WidthLength(106, 0),
+        right_margin: 29,
+        feed_margin: 35,
+    },
+    Label {
+        name: "29",
+        part_number: Some("DK-22210"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(29, 0),
+        dots: WidthLength(342, 0),
+        dots_printable: WidthLength(306, 0),
+        right_margin: 6,
+        feed_margin: 35,
+    },
+    Label {
+        name: "38",
+        part_number: Some("DK-22225"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(38, 0),
+        dots: WidthLength(449, 0),
+        dots_printable: WidthLength(413, 0),
+        right_margin: 12,
+        feed_margin: 35,
+    },
+    Label {
+        name: "50",
+        part_number: Some("DK-22223"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(50, 0),
+        dots: WidthLength(590, 0),
+        dots_printable: WidthLength(554, 0),
+        right_margin: 12,
+        feed_margin: 35,
+    },
+    Label {
+        name: "54",
+        part_number: Some("DK-N55224"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(54, 0),
+        dots: WidthLength(636, 0),
+        dots_printable: WidthLength(590, 0),
+        right_margin: 0,
+        feed_margin: 35,
+    },
+    Label {
+        name: "62",
+        part_number: Some("DK-22205"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(62, 0),
+        dots: WidthLength(732, 0),
+        dots_printable: WidthLength(696, 0),
+        right_margin: 12,
+        feed_margin: 35,
+    },
+    Label {
+        name: "62",
+        part_number: Some("DK-22251"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(62, 0),
+        dots: WidthLength(732, 0),
+        dots_printable: WidthLength(696, 0),
+        right_margin: 12,
+        feed_margin: 35,
+    },
+    Label {
+        name: "102",
+        part_number: Some("DK-22243"),
+        shape: LabelShape::Rectangle,
+        tape_size: WidthLength(102, 0),
+        dots: WidthLength(1200, 0),
+        dots_printable: WidthLength(1164, 0),
+        right_margin: 12,
+        feed_margin: 35,
+    },
+];
+
+/// Every supported label type, e.g. to let users pick the loaded media
+pub fn all_labels() -> &'static [Label] {
+    &LABELS
+}
+
+/// Looks up a label by its Brother part number (`"DK-11201"`, or the shorter `"DK-1201"` used in some regions) or by
+/// its size (`"29x90"` for die-cut labels, `"d24"` for round ones and `"62"` for continuous tape). Case is ignored.
+///
+/// Two-color tape (DK-22251) has the same size as regular 62mm tape, so `"62"` finds the regular tape.
+pub fn label_by_name(name: &str) -> Option<Label> {
+    let mut name = name.trim().to_ascii_uppercase();
+    // DK-1201 is DK-11201 and DK-2205 is DK-22205
+    let short_number = name.strip_prefix("DK-").filter(|number| {
+        number.len() == 4
+            && number.starts_with(['1', '2'])
+            && number.bytes().all(|byte| byte.is_ascii_digit())
+    });
+    if let Some(number) = short_number {
+        name = format!("DK-{}{}", &number[..1], number);
     }
+    LABELS
+        .iter()
+        .find(|label| label.part_number == Some(name.as_str()) || label.name == name.to_lowercase())
+        .copied()
+}
+
+/// Looks up a label by the size the printer reports for it. `length_mm` is 0 for continuous tape. 38x90mm labels
+/// (DK-11208) are also found by 39x90mm, the size printers report for them.
+pub fn label_by_size(width_mm: u8, length_mm: u8) -> Option<Label> {
+    let width_mm = match (width_mm, length_mm) {
+        (39, 90) => 38,
+        _ => width_mm,
+    };
+    LABELS
+        .iter()
+        .find(|label| label.tape_size.0 == width_mm as u32 && label.tape_size.1 == length_mm as u32)
+        .copied()
+}
+
+/// Returns a corresponding label type given dimensions returned by the printer
+pub fn label_data(width: u8, length: Option<u8>) -> Option<Label> {
+    label_by_size(width, length.unwrap_or(0))
 }
 
 /// USB Vendor ID for Brother QL printers
 pub const VENDOR_ID: u16 = 0x04F9;
 
+/// A Brother QL printer model, told apart by its USB product ID
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Model {
+    QL500,
+    QL550,
+    QL560,
+    QL570,
+    QL580N,
+    QL650TD,
+    QL700,
+    QL800,
+    QL810W,
+    QL820NWB,
+    QL1050,
+    QL1060N,
+    QL1100,
+    QL1110NWB,
+    QL1115NWB,
+    /// A printer with a product ID this crate doesn't know. It's treated like a QL-700.
+    Unknown(u16),
+}
+impl Model {
+    /// The model with the USB product ID `id`
+    pub fn from_product_id(id: u16) -> Self {
+        match id {
+            0x2015 => Model::QL500,
+            0x2016 => Model::QL550,
+            0x2027 => Model::QL560,
+            0x2028 => Model::QL570,
+            0x2029 => Model::QL580N,
+            0x201B => Model::QL650TD,
+            0x2042 => Model::QL700,
+            0x209B => Model::QL800,
+            0x209C => Model::QL810W,
+            0x209D => Model::QL820NWB,
+            0x2020 => Model::QL1050,
+            0x202A => Model::QL1060N,
+            0x20A7 => Model::QL1100,
+            0x20A8 => Model::QL1110NWB,
+            0x20AB => Model::QL1115NWB,
+            _ => Model::Unknown(id),
+        }
+    }
+    /// The USB product ID the model reports
+    pub fn product_id(self) -> u16 {
+        match self {
+            Model::QL500 => 0x2015,
+            Model::QL550 => 0x2016,
+            Model::QL560 => 0x2027,
+            Model::QL570 => 0x2028,
+            Model::QL580N => 0x2029,
+            Model::QL650TD => 0x201B,
+            Model::QL700 => 0x2042,
+            Model::QL800 => 0x209B,
+            Model::QL810W => 0x209C,
+            Model::QL820NWB => 0x209D,
+            Model::QL1050 => 0x2020,
+            Model::QL1060N => 0x202A,
+            Model::QL1100 => 0x20A7,
+            Model::QL1110NWB => 0x20A8,
+            Model::QL1115NWB => 0x20AB,
+            Model::Unknown(id) => id,
+        }
+    }
+    /// The model's name as Brother writes it, e.g. `"QL-700"`, or `"Unknown"`
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::QL500 => "QL-500",
+            Model::QL550 => "QL-550",
+            Model::QL560 => "QL-560",
+            Model::QL570 => "QL-570",
+            Model::QL580N => "QL-580N",
+            Model::QL650TD => "QL-650TD",
+            Model::QL700 => "QL-700",
+            Model::QL800 => "QL-800",
+            Model::QL810W => "QL-810W",
+            Model::QL820NWB => "QL-820NWB",
+            Model::QL1050 => "QL-1050",
+            Model::QL1060N => "QL-1060N",
+            Model::QL1100 => "QL-1100",
+            Model::QL1110NWB => "QL-1110NWB",
+            Model::QL1115NWB => "QL-1115NWB",
+            Model::Unknown(_) => "Unknown",
+        }
+    }
+    /// The optional features the model supports. Unknown models are assumed to support everything but two-color
+    /// printing.
+    pub fn capabilities(self) -> Capabilities {
+        use Model::*;
+        let old_model = matches!(self, QL500 | QL550 | QL560);
+        let two_color = matches!(self, QL800 | QL810W | QL820NWB);
+        Capabilities {
+            compression: !old_model && !two_color,
+            two_color,
+            high_resolution: !old_model,
+            max_width_dots: self.raster_line_length() as u32 * 8,
+            auto_cut: self != QL500,
+        }
+    }
+    /// Whether the model has a wide print head for labels up to 102mm (see `WIDE_RASTER_LINE_LENGTH`)
+    pub fn is_wide(self) -> bool {
+        use Model::*;
+        matches!(self, QL1050 | QL1060N | QL1100 | QL1110NWB | QL1115NWB)
+    }
+    /// Length in bytes of each raster line the model's print head accepts
+    pub fn raster_line_length(self) -> usize {
+        if self.is_wide() {
+            WIDE_RASTER_LINE_LENGTH
+        } else {
+            RASTER_LINE_LENGTH
+        }
+    }
+    /// Number of dots across the model's print head, the widest image it can print
+    pub fn max_width_dots(self) -> u32 {
+        self.capabilities().max_width_dots
+    }
+    /// Resolution of the model's print head in dots per inch. Every QL printer has a 300dpi head (see `DPI`).
+    pub fn dpi(self) -> u32 {
+        DPI
+    }
+}
+
 /// Get the string representation of a printer's model name from a USB Product ID
 pub fn printer_name_from_id(id: u16) -> Option<&'static str> {
-    match id {
-        0x2015 => Some("QL-500"),
-        0x2016 => Some("QL-550"),
-        0x2027 => Some("QL-560"),
-        0x2028 => Some("QL-570"),
-        0x2029 => Some("QL-580N"),
-        0x201B => Some("QL-650TD"),
-        0x2042 => Some("QL-700"),
-        0x209B => Some("QL-800"),
-        0x2020 => Some("QL-1050"),
-        0x202A => Some("QL-1060N"),
-        _ => None,
+    match Model::from_product_id(id) {
+        Model::Unknown(_) => None,
+        model => Some(model.name()),
     }
 }
+
+/// The optional features a printer model supports
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Raster lines can be sent PackBits compressed. The QL-500, QL-550, QL-560 and QL-800 series don't support it.
+    pub compression: bool,
+    /// Black and red printing on two-color media (QL-800 series only)
+    pub two_color: bool,
+    /// 600dpi printing along the tape (see `Resolution::High`)
+    pub high_resolution: bool,
+    /// Number of dots across the print head
+    pub max_width_dots: u32,
+    /// The printer has a cutter and accepts the auto-cut commands. The QL-500 has to be cut by hand.
+    pub auto_cut: bool,
+}
+
+/// Get the features a printer supports from its USB Product ID. Unknown models are assumed to support everything
+/// but two-color printing.
+pub fn capabilities_from_id(id: u16) -> Capabilities {
+    Model::from_product_id(id).capabilities()
+}
+
+/// Whether a printer can print in black and red on two-color media given its USB Product ID (QL-800 series only)
+pub fn supports_two_color(id: u16) -> bool {
+    capabilities_from_id(id).two_color
+}
+
+/// Get the raster line length (in bytes) a printer expects from its USB Product ID
+pub fn raster_line_length_from_id(id: u16) -> usize {
+    Model::from_product_id(id).raster_line_length()
+}
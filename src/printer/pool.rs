@@ -0,0 +1,196 @@
+//! Spread a batch of labels across several printers
+//!
+//! A `PrinterPool` prints every job of a batch on whichever of its printers is free first, so a bank of printers
+//! works through the batch in parallel.
+
+use super::backend::{PrinterBackend, UsbBackend};
+use super::{Error, ErrorKind, RasterLines, Result, ThermalPrinter};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+
+/// What happened to a batch printed with `PrinterPool::print_all()`
+#[derive(Debug)]
+pub struct PoolReport {
+    /// For every job in the order they were given, the index of the printer that printed it or why it wasn't
+    /// printed
+    pub jobs: Vec<Result<usize>>,
+    /// The index of every printer that dropped out of the batch along with the reason, either an error in its
+    /// status or a failed print
+    pub failed_printers: Vec<(usize, Error)>,
+}
+
+/// Several printers that share the jobs of a batch between them
+pub struct PrinterPool<B: PrinterBackend = UsbBackend<rusb::GlobalContext>> {
+    printers: Vec<ThermalPrinter<B>>,
+}
+impl PrinterPool {
+    /// Opens every attached printer found by `ThermalPrinter::enumerate()`. Fails if any of them can't be opened.
+    pub fn open_all() -> Result<Self> {
+        let printers = ThermalPrinter::enumerate()
+            .iter()
+            .map(|info| info.open())
+            .collect::<Result<_>>()?;
+        Ok(PrinterPool::new(printers))
+    }
+}
+impl<B: PrinterBackend + Send> PrinterPool<B> {
+    pub fn new(printers: Vec<ThermalPrinter<B>>) -> Self {
+        PrinterPool { printers }
+    }
+    /// The printers in the pool. Their settings (e.g. `ThermalPrinter::set_cut_behavior()`) apply to the jobs they
+    /// print.
+    pub fn printers(&self) -> &[ThermalPrinter<B>] {
+        &self.printers
+    }
+    pub fn printers_mut(&mut self) -> &mut [ThermalPrinter<B>] {
+        &mut self.printers
+    }
+    pub fn into_printers(self) -> Vec<ThermalPrinter<B>> {
+        self.printers
+    }
+
+    /// Prints every job with `ThermalPrinter::print()`, each on the first printer to become free. Every printer works
+    /// on its own thread and asks for its status before it takes the next job.
+    ///
+    /// A printer that reports an error (e.g. it ran out of labels) or fails to print a job drops out of the batch and
+    /// the others carry on with the remaining jobs. A job whose print failed isn't tried again, since part of it may
+    /// already have been printed. Jobs left over once every printer has dropped out fail as well.
+    pub fn print_all<L: Into<RasterLines> + Send>(&mut self, jobs: Vec<L>) -> PoolReport {
+        let job_count = jobs.len();
+        let queue = Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>());
+        let results = Mutex::new((0..job_count).map(|_| None).collect::<Vec<_>>());
+        let failed_printers = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for (index, printer) in self.printers.iter_mut().enumerate() {
+                let (queue, results, failed_printers) = (&queue, &results, &failed_printers);
+                scope.spawn(move || loop {
+                    if let Err(error) = ready(printer) {
+                        failed_printers.lock().unwrap().push((index, error));
+                        return;
+                    }
+                    let (job, lines) = match queue.lock().unwrap().pop_front() {
+                        Some(job) => job,
+                        None => return,
+                    };
+                    match printer.print(lines) {
+                        Ok(_) => results.lock().unwrap()[job] = Some(Ok(index)),
+                        Err(error) => {
+                            let dropped_out = format!("Printer {} failed to print the job", index);
+                            results.lock().unwrap()[job] = Some(Err(dropped_out.into()));
+                            failed_printers.lock().unwrap().push((index, error));
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut failed_printers = failed_printers.into_inner().unwrap();
+        failed_printers.sort_by_key(|(index, _)| *index);
+        PoolReport {
+            jobs: results
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|result| {
+                    result.unwrap_or_else(|| Err("Every printer in the pool dropped out".into()))
+                })
+                .collect(),
+            failed_printers,
+        }
+    }
+}
+
+/// Checks that `printer` is ready for the next job
+fn ready<B: PrinterBackend>(printer: &ThermalPrinter<B>) -> Result<()> {
+    match printer.status()?.errors.first() {
+        Some(&error) => bail!(ErrorKind::Printer(error)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrinterPool;
+    use crate::printer::backend::{MemoryBackend, PrinterBackend};
+    use crate::printer::status::PrinterError;
+    use crate::printer::{commands, constants, ErrorKind, Result, ThermalPrinter};
+    use std::cell::Cell;
+
+    /// A `MemoryBackend` that can be made to report running out of labels
+    struct EmptyableBackend {
+        memory: MemoryBackend,
+        empty: Cell<bool>,
+    }
+    impl PrinterBackend for EmptyableBackend {
+        fn write(&self, data: &[u8]) -> Result<()> {
+            self.memory.write(data)
+        }
+        fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+            let length = self.memory.read(buffer)?;
+            if self.empty.get() {
+                buffer[8] |= 0x02;
+            }
+            Ok(length)
+        }
+    }
+
+    fn printer() -> ThermalPrinter<EmptyableBackend> {
+        let backend = EmptyableBackend {
+            memory: MemoryBackend::new(constants::label_data(62, None).unwrap()),
+            empty: Cell::new(false),
+        };
+        ThermalPrinter::with_backend(backend, 0x2042).unwrap()
+    }
+
+    fn printed_jobs(printer: &ThermalPrinter<EmptyableBackend>) -> usize {
+        commands::parse(&printer.backend().memory.sent())
+            .unwrap()
+            .iter()
+            .filter(|command| **command == commands::QlCommand::PrintWithFeeding)
+            .count()
+    }
+
+    #[test]
+    fn jobs_are_shared_between_printers() {
+        let mut pool = PrinterPool::new(vec![printer(), printer(), printer()]);
+        let report = pool.print_all(vec![vec![[0xFF; 90]; 10]; 7]);
+        assert!(report.failed_printers.is_empty());
+        assert!(report.jobs.iter().all(|job| job.is_ok()));
+        let printed: Vec<_> = pool.printers().iter().map(printed_jobs).collect();
+        assert_eq!(printed.iter().sum::<usize>(), 7);
+        for (index, &count) in printed.iter().enumerate() {
+            let assigned = report
+                .jobs
+                .iter()
+                .filter(|job| *job.as_ref().unwrap() == index)
+                .count();
+            assert_eq!(assigned, count);
+        }
+    }
+
+    #[test]
+    fn printers_with_errors_drop_out() {
+        let mut pool = PrinterPool::new(vec![printer(), printer()]);
+        pool.printers()[0].backend().empty.set(true);
+        let report = pool.print_all(vec![vec![[0xFF; 90]; 10]; 3]);
+        assert_eq!(report.failed_printers.len(), 1);
+        let (index, error) = &report.failed_printers[0];
+        assert_eq!(*index, 0);
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Printer(PrinterError::EndOfMedia)
+        ));
+        assert!(report.jobs.iter().all(|job| *job.as_ref().unwrap() == 1));
+        assert_eq!(printed_jobs(&pool.printers()[0]), 0);
+        assert_eq!(printed_jobs(&pool.printers()[1]), 3);
+
+        // Nothing is left to print the jobs on
+        pool.printers()[1].backend().empty.set(true);
+        let report = pool.print_all(vec![vec![[0xFF; 90]; 10]; 2]);
+        assert_eq!(report.failed_printers.len(), 2);
+        assert!(report.jobs.iter().all(|job| job.is_err()));
+    }
+}
@@ -1,252 +1,4249 @@
 //! Easy-to-use text and image compositing and rasterization for use with Brother QL printers
 
-use crate::printer::constants::Label;
-use image::{DynamicImage, Luma};
-use rusttype::{Font, Point, Scale};
+use crate::barcode::{BarcodeError, BarcodeErrorKind, QrEcLevel, QrModules};
+use crate::printer::constants::{Label, LabelShape, WidthLength};
+use crate::printer::{RasterLines, Resolution, TwoColorRasterLines};
+use image::imageops::FilterType;
+use image::{Luma, Pixel};
+use rusttype::{Font, Point, Rect, Scale};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use unicode_bidi::BidiInfo;
+
+#[cfg(feature = "svg")]
+pub mod svg;
+pub mod template;
 
 type XY<T> = Point<T>;
 
-fn calc_text_width(glyphs: &[rusttype::PositionedGlyph]) -> u32 {
-    let min_x = glyphs
+error_chain! {
+    types {
+        RasterizeError, RasterizeErrorKind, ResultExt, Result;
+    }
+    links {
+        Barcode(BarcodeError, BarcodeErrorKind);
+    }
+    foreign_links {
+        Io(std::io::Error);
+        Image(image::ImageError);
+    }
+    errors {
+        InvalidFont(source: String, index: usize) {
+            description("invalid font")
+            display("{} does not contain a valid font at index {}", source, index)
+        }
+        TextDoesNotFit(text: String) {
+            description("text does not fit on the label")
+            display("\"{}\" does not fit on the label", text)
+        }
+        InvalidSecondRowImage(path: PathBuf) {
+            description("second row image could not be loaded")
+            display("could not load second row image {}", path.display())
+        }
+        InvalidImage(path: PathBuf) {
+            description("image could not be loaded")
+            display("could not load image {}", path.display())
+        }
+        InvalidSvg(reason: String) {
+            description("SVG could not be parsed")
+            display("could not parse SVG: {}", reason)
+        }
+        QrCodeDoesNotFit {
+            description("QR code does not fit on the label")
+            display("QR code does not fit across the label")
+        }
+        UnknownLabel(name: String) {
+            description("no label with this name")
+            display("\"{}\" is not a known label size or part number", name)
+        }
+        MissingField(name: String) {
+            description("a template field was not filled in")
+            display("no text given for template field \"{}\"", name)
+        }
+        ImageDoesNotFitLine(dots: u32, max: u32) {
+            description("image is wider than the raster lines")
+            display("image is {} dots across the tape but the raster lines only hold {}", dots, max)
+        }
+        NotBilevel(x: u32, y: u32, luma: u8) {
+            description("image is not black and white")
+            display("pixel ({}, {}) has luma {} but only 0 (black) and 255 (white) are allowed", x, y, luma)
+        }
+    }
+}
+
+/// Number of faces in a font file. Collections list their count in the header, all other fonts have one face.
+fn face_count(font_data: &[u8]) -> u32 {
+    match font_data.get(..12) {
+        Some(header) if header.starts_with(b"ttcf") => {
+            u32::from_be_bytes([header[8], header[9], header[10], header[11]])
+        }
+        _ => 1,
+    }
+}
+
+/// Gives face `index` of `font_data` usable vertical metrics if it reports none (an ascent at or below its descent),
+/// as some converted bitmap fonts do. Glyphs of such fonts would otherwise be scaled by 0/0 and draw nothing.
+///
+/// The `hhea` ascent and descent (and the `OS/2` typographic ones, which some fonts use instead) are set to the top
+/// and bottom of the bounding box of all glyphs from the `head` table, or split 80/20 around the baseline if that is
+/// empty too.
+fn repair_v_metrics(font_data: &mut [u8], index: u32) {
+    let degenerate = match Font::try_from_bytes_and_index(font_data, index) {
+        Some(font) => {
+            let v_metrics = font.v_metrics_unscaled();
+            v_metrics.ascent <= v_metrics.descent
+        }
+        None => false,
+    };
+    if !degenerate {
+        return;
+    }
+    fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+        let bytes = data.get(at..at + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    fn read_u32(data: &[u8], at: usize) -> Option<usize> {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+    let directory = if font_data.starts_with(b"ttcf") {
+        match read_u32(font_data, 12 + 4 * index as usize) {
+            Some(offset) => offset,
+            None => return,
+        }
+    } else {
+        0
+    };
+    // Offset of the face's table with this tag
+    let table = |tag: &[u8]| -> Option<usize> {
+        let count = read_u16(font_data, directory + 4)? as usize;
+        let record = (0..count)
+            .map(|table| directory + 12 + 16 * table)
+            .find(|&record| font_data.get(record..record + 4) == Some(tag))?;
+        read_u32(font_data, record + 8)
+    };
+    let (head, hhea, os2) = (table(b"head"), table(b"hhea"), table(b"OS/2"));
+    let (ascent, descent) = match head.and_then(|head| {
+        Some((
+            read_u16(font_data, head + 18)?,
+            read_u16(font_data, head + 38)? as i16,
+            read_u16(font_data, head + 42)? as i16,
+        ))
+    }) {
+        Some((_, y_min, y_max)) if y_max > y_min => (y_max, y_min),
+        Some((units_per_em, _, _)) => {
+            let units_per_em = units_per_em as f32;
+            ((units_per_em * 0.8) as i16, -(units_per_em * 0.2) as i16)
+        }
+        None => return,
+    };
+    let mut write = |at: usize, value: i16| {
+        if let Some(bytes) = font_data.get_mut(at..at + 2) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+    };
+    if let Some(hhea) = hhea {
+        write(hhea + 4, ascent);
+        write(hhea + 6, descent);
+    }
+    if let Some(os2) = os2 {
+        write(os2 + 68, ascent);
+        write(os2 + 70, descent);
+    }
+}
+
+/// Reorders text from logical to visual order using the Unicode Bidirectional Algorithm so that right-to-left
+/// runs (e.g. Hebrew or Arabic) come out the right way round when laid out left to right. Also returns whether the
+/// base direction of the text is right-to-left.
+///
+/// This only reorders characters. There is no contextual shaping so scripts like Arabic are drawn with the isolated
+/// form of each letter, and mirrored characters such as brackets are not swapped in right-to-left runs.
+fn visual_order(text: &str) -> (Cow<'_, str>, bool) {
+    let bidi = BidiInfo::new(text, None);
+    if !bidi.has_rtl() {
+        return (Cow::Borrowed(text), false);
+    }
+    let rtl = bidi
+        .paragraphs
         .first()
-        .map(|g| g.pixel_bounding_box().unwrap().min.x)
-        .unwrap();
-    let max_x = glyphs
-        .last()
-        .map(|g| g.pixel_bounding_box().unwrap().max.x)
-        .unwrap();
-    (max_x - min_x) as u32
+        .is_some_and(|paragraph| paragraph.level.is_rtl());
+    let visual = bidi
+        .paragraphs
+        .iter()
+        .map(|paragraph| bidi.reorder_line(paragraph, paragraph.range.clone()))
+        .collect();
+    (Cow::Owned(visual), rtl)
+}
+
+/// Index of the first of `fonts` that has a glyph for `c`, or the first font if none of them do
+fn font_for(fonts: &[Font], c: char) -> usize {
+    fonts
+        .iter()
+        .position(|font| font.glyph(c).id().0 != 0)
+        .unwrap_or(0)
+}
+
+/// Vertical metrics covering every font that supplies a character of `text` (and always the first font) so that text
+/// mixing fonts shares one baseline with room for the tallest ascent and the deepest descent
+fn combined_v_metrics(fonts: &[Font], text: &str, scale: Scale) -> rusttype::VMetrics {
+    let mut used = vec![false; fonts.len()];
+    used[0] = true;
+    for c in text.chars() {
+        used[font_for(fonts, c)] = true;
+    }
+    fonts
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .map(|(font, _)| font.v_metrics(scale))
+        .reduce(|a, b| rusttype::VMetrics {
+            ascent: a.ascent.max(b.ascent),
+            descent: a.descent.min(b.descent),
+            line_gap: a.line_gap.max(b.line_gap),
+        })
+        .unwrap()
+}
+
+/// The fonts and spacing that text is laid out with
+#[derive(Copy, Clone)]
+struct TextStyle<'a> {
+    /// The main font followed by the fallback fonts
+    fonts: &'a [Font<'a>],
+    /// Extra advance in dots after every character
+    letter_spacing: f32,
+    /// Extra advance in dots after every space on top of the letter spacing
+    word_spacing: f32,
+}
+impl<'a> TextStyle<'a> {
+    /// Lays text out with the fonts' own spacing
+    fn new(fonts: &'a [Font<'a>]) -> Self {
+        Self {
+            fonts,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+        }
+    }
+}
+
+/// Lays out `text` in a single line with its baseline at `y`. Each character is taken from the first font of `style`
+/// that has a glyph for it, falling back to the first font's missing glyph symbol (usually a box) if none of them do.
+fn layout_glyphs<'a>(
+    style: TextStyle<'a>,
+    text: &str,
+    scale: Scale,
+    y: f32,
+) -> Vec<rusttype::PositionedGlyph<'a>> {
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous: Option<(usize, rusttype::GlyphId)> = None;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let index = font_for(style.fonts, c);
+        let font = &style.fonts[index];
+        let glyph = font.glyph(c).scaled(scale);
+        // Kerning only makes sense between glyphs from the same font
+        if let Some((previous_index, previous_id)) = previous {
+            if previous_index == index {
+                caret += font.pair_kerning(scale, previous_id, glyph.id());
+            }
+        }
+        previous = Some((index, glyph.id()));
+        let mut advance = glyph.h_metrics().advance_width + style.letter_spacing;
+        if c == ' ' {
+            advance += style.word_spacing;
+        }
+        glyphs.push(glyph.positioned(Point { x: caret, y }));
+        caret += advance;
+    }
+    glyphs
+}
+
+/// The leftmost and rightmost pixel x that `glyphs` cover, if any of them has a box
+fn glyph_span(glyphs: &[rusttype::PositionedGlyph]) -> Option<(i32, i32)> {
+    // Whitespace has no bounding box and combining marks can stick out past the glyphs around them, so measure the
+    // span of every glyph that has a box rather than just the first and the last
+    glyphs
+        .iter()
+        .filter_map(|g| g.pixel_bounding_box())
+        .map(|bb| (bb.min.x, bb.max.x))
+        .reduce(|(min, max), (bb_min, bb_max)| (min.min(bb_min), max.max(bb_max)))
+}
+
+fn calc_text_width(glyphs: &[rusttype::PositionedGlyph]) -> u32 {
+    glyph_span(glyphs).map_or(0, |(min, max)| (max - min) as u32)
+}
+
+/// How the rasterizer picks font sizes (see `TextRasterizer::set_font_sizing()`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FontSizing {
+    /// Start at the size each rasterizing method asks for, but no larger than `max`, and shrink the text until it
+    /// fits or reaches the minimum font size (default, with no limit)
+    AutoShrink { max: f32 },
+    /// Draw all text at exactly this size in dots. Text that doesn't fit is then handled by the `TextOverflow`
+    /// setting and never shrunk.
+    Fixed(f32),
+}
+
+/// What to do with text that still doesn't fit once it has been shrunk to the minimum font size
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextOverflow {
+    /// Fail with `RasterizeErrorKind::TextDoesNotFit` (default)
+    Error,
+    /// Cut the text short at the minimum font size and end it with an ellipsis
+    Ellipsis,
+}
+
+/// A problem with a label that doesn't stop it from being rasterized, reported to the handler given to
+/// `TextRasterizer::set_warning_handler()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RasterizeWarning {
+    /// `text` was shrunk to `font_size` dots, below the `min_readable` size set with
+    /// `TextRasterizer::set_min_readable_font_size()`
+    TextTooSmall {
+        text: String,
+        font_size: f32,
+        min_readable: f32,
+    },
+}
+
+/// Called with every `RasterizeWarning` while rasterizing
+pub type WarningHandler = Box<dyn Fn(&RasterizeWarning) + Send + Sync>;
+
+/// How an image placed beside the text (see `TextRasterizer::set_leading_image()`) fills the area it's given
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFit {
+    /// Scale the whole image to fit inside the area, leaving blank space on the sides it doesn't reach
+    Contain,
+    /// Scale the image to cover the whole area, cropping off whatever sticks out
+    Cover,
+}
+
+/// How far an image is turned clockwise before it's drawn, as seen in the label image (x along the tape)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rotation {
+    /// Drawn as it is (default)
+    Deg0,
+    Deg90,
+    /// Upside down, e.g. for a second row label that's peeled off and stuck on the other way round
+    Deg180,
+    Deg270,
+}
+impl Rotation {
+    fn apply(self, image: image::GrayImage) -> image::GrayImage {
+        match self {
+            Rotation::Deg0 => image,
+            Rotation::Deg90 => image::imageops::rotate90(&image),
+            Rotation::Deg180 => image::imageops::rotate180(&image),
+            Rotation::Deg270 => image::imageops::rotate270(&image),
+        }
+    }
 }
 
 struct ResizedText<'a> {
+    text: String,
+    /// Whether the base direction of the text is right-to-left
+    rtl: bool,
     rendered_size: XY<u32>,
+    /// The font size in dots the text was laid out at
+    font_size: f32,
     glyphs: Vec<rusttype::PositionedGlyph<'a>>,
 }
 impl<'a> ResizedText<'a> {
-    pub fn create<'b>(font: &'a Font, text: &'b str, max_width: u32, max_font_size: f32) -> Self {
-        let mut font_size = max_font_size.ceil(); // Max possible font size
-        let rendered_size;
-        // Scale the font size down until it all fits length-wise
-        let glyphs = loop {
-            let scale = Scale::uniform(font_size);
-            let v_metrics = font.v_metrics(scale);
-            let glyphs: Vec<_> = font
-                .layout(
-                    text,
-                    scale,
-                    Point {
-                        x: 0.0,
-                        y: v_metrics.ascent,
-                    },
-                )
-                .collect();
+    pub fn create<'b>(
+        style: TextStyle<'a>,
+        text: &'b str,
+        max_width: u32,
+        max_font_size: f32,
+        min_font_size: f32,
+        overflow: TextOverflow,
+    ) -> Result<Self> {
+        let mut font_size = max_font_size;
+        let min_font_size = min_font_size.max(1.0);
 
-            let width = calc_text_width(&glyphs);
-            if width < max_width {
-                let height = (v_metrics.ascent - v_metrics.descent).ceil() as u32;
-                rendered_size = XY {
-                    x: width,
-                    y: height,
-                };
-                break glyphs;
+        // Scale the font size down until it all fits length-wise
+        while font_size >= min_font_size {
+            let resized = Self::at_size(style, text, font_size);
+            if resized.rendered_size.x < max_width {
+                return Ok(resized);
             }
             font_size -= 1.0;
-        };
+        }
+        match overflow {
+            TextOverflow::Error => bail!(RasterizeErrorKind::TextDoesNotFit(text.to_string())),
+            TextOverflow::Ellipsis => Self::ellipsized(style, text, max_width, min_font_size),
+        }
+    }
 
+    /// Lays out the text at exactly `font_size` without trying to make it fit anywhere
+    pub fn at_size<'b>(style: TextStyle<'a>, text: &'b str, font_size: f32) -> Self {
+        let scale = Scale::uniform(font_size);
+        let v_metrics = combined_v_metrics(style.fonts, text, scale);
+        let (visual, rtl) = visual_order(text);
+        let glyphs = layout_glyphs(style, &visual, scale, v_metrics.ascent);
+
+        let width = calc_text_width(&glyphs);
+        let height = (v_metrics.ascent - v_metrics.descent).ceil() as u32;
         Self {
-            rendered_size,
+            text: text.to_string(),
+            rtl,
+            rendered_size: XY {
+                x: width,
+                y: height,
+            },
+            font_size,
             glyphs,
         }
     }
+
+    /// Drops characters from the end of the text until it fits with an ellipsis appended
+    pub fn ellipsized<'b>(
+        style: TextStyle<'a>,
+        text: &'b str,
+        max_width: u32,
+        font_size: f32,
+    ) -> Result<Self> {
+        // Not every font has a proper ellipsis character
+        let ellipsis = if style.fonts.iter().any(|font| font.glyph('…').id().0 != 0) {
+            "…"
+        } else {
+            "..."
+        };
+        let ends = std::iter::once(text.len()).chain(text.char_indices().rev().map(|(i, _)| i));
+        for end in ends {
+            let candidate = format!("{}{}", text[..end].trim_end(), ellipsis);
+            let resized = Self::at_size(style, &candidate, font_size);
+            if resized.rendered_size.x < max_width {
+                return Ok(resized);
+            }
+        }
+        bail!(RasterizeErrorKind::TextDoesNotFit(text.to_string()))
+    }
+
+    /// Moves the words apart so the right edge of the last glyph is `length` dots from the start of the line,
+    /// sharing the extra space out evenly between the gaps. Returns `false` and leaves the text alone if it has no
+    /// gap between words.
+    fn justify(&mut self, length: u32) -> bool {
+        let (visual, _) = visual_order(&self.text);
+        let spaces: Vec<bool> = visual
+            .chars()
+            .filter(|c| !c.is_control())
+            .map(char::is_whitespace)
+            .collect();
+        let inked: Vec<usize> = (0..self.glyphs.len())
+            .filter(|&i| self.glyphs[i].pixel_bounding_box().is_some())
+            .collect();
+        let (first, last) = match (inked.first(), inked.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return false,
+        };
+        // Only gaps with words on both sides are stretched, a run of spaces counts as one gap
+        let gap_starts: Vec<usize> = (first + 1..last)
+            .filter(|&i| spaces[i] && !spaces[i - 1])
+            .collect();
+        if gap_starts.is_empty() {
+            return false;
+        }
+        let right = self.glyphs[last].pixel_bounding_box().unwrap().max.x;
+        let extra = (length as i32 - right).max(0);
+        let gaps = gap_starts.len() as i32;
+        let mut gaps_before = 0;
+        for i in 0..self.glyphs.len() {
+            if gap_starts.contains(&i) {
+                gaps_before += 1;
+            }
+            // Whole dots, with the last gap taking up the rounding so the text ends exactly at `length`
+            let shift = (extra * gaps_before / gaps) as f32;
+            let glyph = &self.glyphs[i];
+            let position = glyph.position();
+            self.glyphs[i] = glyph.unpositioned().clone().positioned(Point {
+                x: position.x + shift,
+                y: position.y,
+            });
+        }
+        self.rendered_size.x = calc_text_width(&self.glyphs);
+        true
+    }
 }
 
+/// Greedily packs words into lines no wider than `max_width` at the given font size.
+///
+/// Returns `None` if a single word is too wide to fit on a line by itself, unless `truncate` is set in which case
+/// that word is ellipsized instead.
+fn wrap_words<'a>(
+    style: TextStyle<'a>,
+    words: &[&str],
+    max_width: u32,
+    font_size: f32,
+    truncate: bool,
+) -> Option<Vec<ResizedText<'a>>> {
+    let mut lines = Vec::new();
+    let mut current: Option<ResizedText<'a>> = None;
+    for word in words {
+        let candidate = match &current {
+            Some(line) => format!("{} {}", line.text, word),
+            None => word.to_string(),
+        };
+        let resized = ResizedText::at_size(style, &candidate, font_size);
+        if resized.rendered_size.x < max_width {
+            current = Some(resized);
+            continue;
+        }
+        // Doesn't fit so start a new line with this word
+        if let Some(line) = current.take() {
+            lines.push(line);
+        }
+        let mut resized = ResizedText::at_size(style, word, font_size);
+        if resized.rendered_size.x >= max_width {
+            if !truncate {
+                return None;
+            }
+            resized = ResizedText::ellipsized(style, word, max_width, font_size).ok()?;
+        }
+        current = Some(resized);
+    }
+    if let Some(line) = current {
+        lines.push(line);
+    }
+    Some(lines)
+}
+
+/// Draws `glyphs` into `image` at `offset`. Any part of a glyph that falls outside `clip` (or the image) is dropped,
+/// so text laid out in one area of a label can't spill into the area next to it.
+///
+/// Glyphs are drawn with their anti-aliased coverage as shades of gray for the dithering step to turn into dots.
+/// Coverage is rounded so that with the default threshold a pixel prints exactly when at least half of it is
+/// covered, which keeps strokes the same weight wherever they fall between pixels. Where glyphs overlap (e.g. with
+/// kerning or negative letter spacing) the darker shade wins, or the lighter one when inverted, so the faint edge of
+/// one glyph never lightens the solid part of another.
 fn draw_glyphs(
     image: &mut image::GrayImage,
     glyphs: &[rusttype::PositionedGlyph],
     offset: XY<i32>,
+    clip: Rect<i32>,
     invert: bool,
 ) {
+    let (min_x, min_y) = (clip.min.x.max(0), clip.min.y.max(0));
+    let max_x = clip.max.x.min(image.width() as i32);
+    let max_y = clip.max.y.min(image.height() as i32);
     for glyph in glyphs {
         if let Some(bounding_box) = glyph.pixel_bounding_box() {
             // Draw the glyph into the image per-pixel by using the draw closure
             glyph.draw(|x, y, v| {
-                let color = if invert {
-                    (255.0 * v) as u8
+                // Offset the position by the glyph bounding box
+                let x = x as i32 + bounding_box.min.x + offset.x;
+                let y = y as i32 + bounding_box.min.y + offset.y;
+                if x < min_x || y < min_y || x >= max_x || y >= max_y {
+                    return;
+                }
+                let pixel = image.get_pixel_mut(x as u32, y as u32);
+                // Turn the coverage into an alpha value
+                let coverage = (255.0 * v.clamp(0.0, 1.0)).round() as u8;
+                pixel[0] = if invert {
+                    pixel[0].max(coverage)
                 } else {
-                    255 - (255.0 * v) as u8
+                    pixel[0].min(255 - coverage)
                 };
-
-                image.put_pixel(
-                    // Offset the position by the glyph bounding box
-                    (x as i32 + bounding_box.min.x + offset.x) as u32,
-                    (y as i32 + bounding_box.min.y + offset.y) as u32,
-                    // Turn the coverage into an alpha value
-                    Luma([color]),
-                )
             });
         }
     }
 }
 
-pub fn image_to_raster_lines(image: &image::GrayImage, width: u32) -> Vec<[u8; 90]> {
-    let width = width as usize;
-    let line_count = image.len() / width;
+/// The rectangle from `(0, 0)` to `(length, width)`, the main area of a label
+fn label_area(length: u32, width: u32) -> Rect<i32> {
+    Rect {
+        min: XY { x: 0, y: 0 },
+        max: XY {
+            x: length as i32,
+            y: width as i32,
+        },
+    }
+}
+
+/// Draws the lines of `decoration` along `text` as it was drawn by `draw_glyphs()` at `offset`. The strikethrough
+/// height comes from the height of `font`'s lowercase x.
+fn draw_decorations(
+    image: &mut image::GrayImage,
+    text: &ResizedText,
+    font: &Font,
+    offset: XY<i32>,
+    decoration: TextDecoration,
+    invert: bool,
+) {
+    let (start, end) = match glyph_span(&text.glyphs) {
+        Some(span) => span,
+        None => return,
+    };
+    let baseline = offset.y as f32 + text.glyphs[0].position().y;
+    let scale = Scale::uniform(text.font_size);
+    let thickness = (text.font_size / 15.0).round().max(1.0);
+    let x_height = font
+        .glyph('x')
+        .scaled(scale)
+        .exact_bounding_box()
+        .map_or(text.font_size * 0.5, |bounds| -bounds.min.y);
 
-    // We need to sidescan this generated image for the printer
-    let mut lines = Vec::with_capacity(width);
-    for c in 0..width {
-        let mut line = [0; 90]; // Always 90 for regular sized printers like the QL-700 (with a 0x00 byte to start)
-        let mut line_byte = 1;
-        // Bit index counts backwards
-        // First nibble (bits 7 through 4) in the second byte is blank
-        let mut line_bit_index: i8 = 3;
-        for r in 0..line_count {
-            line_bit_index -= 1;
-            if line_bit_index < 0 {
-                line_byte += 1;
-                line_bit_index += 8;
+    let mut bars = Vec::new();
+    if decoration.underline {
+        bars.push(baseline + thickness);
+    }
+    if decoration.strikethrough {
+        bars.push(baseline - x_height / 2.0 - thickness / 2.0);
+    }
+    let color = Luma([if invert { 255 } else { 0 }]);
+    for top in bars {
+        let top = top.round() as i32;
+        for y in top.max(0)..(top + thickness as i32).min(image.height() as i32) {
+            let xs = (start + offset.x).max(0)..(end + offset.x).min(image.width() as i32);
+            for x in xs {
+                image.put_pixel(x as u32, y as u32, color);
             }
-            image.get_pixel(0, 0);
-            let luma_pixel = image.get_pixel(c as u32, r as u32); // + 3 was here in TS code -- not sure if needed
-            let value: u8 = if luma_pixel[0] > 0xFF / 2 { 0 } else { 1 };
-            line[line_byte] |= value << line_bit_index;
         }
-        lines.push(line);
     }
-    lines
 }
 
-/// Easily convert text into a raster image that can be printed by a `ThermalPrinter`
-pub struct TextRasterizer {
-    label: Label,
-    font_path: PathBuf,
-    second_row_image: Option<PathBuf>,
+/// Converts an image into raster lines of `line_length` bytes each. The x axis of the image runs along the tape and
+/// becomes the sequence of lines while the y axis runs across the print head. Use `Label::raster_line_length()` or
+/// `ThermalPrinter::raster_line_length()` to choose the line length.
+///
+/// Every column of the image becomes one line. To print at `Resolution::High` the image must be twice as long as
+/// the label (two columns per 300dpi dot) and the lines marked with `RasterLines::set_resolution()`. Pixel `(x, y)`
+/// becomes dot `y` of line `x`, packed as described in `pack_raster_line()`. Since a label's image starts with its
+/// `right_margin` (see `Label::raster_width()`) nothing else needs to be skipped at the start of a line.
+///
+/// Pixels lighter than mid-gray are left white. See `image_to_raster_lines_with_threshold()` to change that.
+///
+/// Fails with `ImageDoesNotFitLine` if the image is taller than the lines have room for, rather than cutting it off.
+pub fn image_to_raster_lines(image: &image::GrayImage, line_length: usize) -> Result<RasterLines> {
+    image_to_raster_lines_with_threshold(image, line_length, DEFAULT_THRESHOLD)
 }
-impl TextRasterizer {
-    /// The text rasterizer needs to know the loaded label media currently in the printer in order to resize and
-    /// shift the text content accordingly so that it will fit.
-    pub fn new(label: Label, font_path: PathBuf) -> Self {
-        Self {
-            label,
-            font_path,
-            second_row_image: None,
+
+/// Length in dots of labels on continuous tape unless another length is chosen. At 300dpi this is 63.5mm, so
+/// `TextRasterizer::set_continuous_length_mm(63.5)` gives the same length.
+pub const DEFAULT_CONTINUOUS_LENGTH: u32 = 750;
+
+/// The shortest label in dots that `TextRasterizer::set_content_length_margins()` cuts continuous tape to, 12.7mm
+/// at 300dpi. Printers can't feed and cut anything shorter.
+pub const MIN_CONTINUOUS_LENGTH: u32 = 150;
+
+/// The luma value at or below which pixels are printed black unless another threshold is chosen
+pub const DEFAULT_THRESHOLD: u8 = 0xFF / 2;
+
+/// Same as `image_to_raster_lines()` but prints every pixel with a luma value at or below `threshold` as black. Raise
+/// the threshold to print more ink (e.g. on stock that comes out too light) or lower it to print less.
+pub fn image_to_raster_lines_with_threshold(
+    image: &image::GrayImage,
+    line_length: usize,
+    threshold: u8,
+) -> Result<RasterLines> {
+    check_fits_line(image, line_length)?;
+    let mut lines = RasterLines::new(line_length);
+    for line in transpose_for_printer(image).rows() {
+        let dots = line.map(|pixel| pixel[0] <= threshold);
+        lines.push(&pack_raster_line(dots, line_length));
+    }
+    Ok(lines)
+}
+
+/// Same as `image_to_raster_lines()` for images that are already black and white, e.g. dithered by another tool.
+/// Black (`0`) pixels are printed and white (`255`) ones aren't, with no threshold in between. Fails with
+/// `NotBilevel` at the first pixel of any other shade.
+pub fn bilevel_image_to_raster_lines(
+    image: &image::GrayImage,
+    line_length: usize,
+) -> Result<RasterLines> {
+    check_fits_line(image, line_length)?;
+    let mut lines = RasterLines::new(line_length);
+    for (x, line) in transpose_for_printer(image).rows().enumerate() {
+        let mut dots = Vec::with_capacity(image.height() as usize);
+        for (y, pixel) in line.enumerate() {
+            match pixel[0] {
+                0 => dots.push(true),
+                255 => dots.push(false),
+                luma => bail!(RasterizeErrorKind::NotBilevel(x as u32, y as u32, luma)),
+            }
         }
+        lines.push(&pack_raster_line(dots, line_length));
     }
-    /// Some types of label media (e.g. 12mm continuous tape) are wider than specified. Use this method to draw
-    /// an image onto this second, normally out-of-bounds part of the tape. The bottom portion of the tape
-    /// is usually pre-scored from the top part so consider this a way to make "bonus" labels with the same
-    /// amount of physical tape.
-    pub fn set_second_row_image(&mut self, path: PathBuf) {
-        self.second_row_image = Some(path);
+    Ok(lines)
+}
+
+/// Fails with `ImageDoesNotFitLine` if `image` is taller than raster lines of `line_length` bytes have dots for
+fn check_fits_line(image: &image::GrayImage, line_length: usize) -> Result<()> {
+    let capacity = line_length * 8;
+    if image.height() as usize > capacity {
+        bail!(RasterizeErrorKind::ImageDoesNotFitLine(
+            image.height(),
+            capacity as u32
+        ));
     }
-    /// Transforms text into a raster image ready to send to the `ThermalPrinter`. Typically, the text will appear
-    /// as black on a white background. Enable the `invert` flag to print white text on a black background. Note that
-    /// since the label is white, a faint border of white will still surround the label in areas that the printer
-    /// cannot print the black background.
-    pub fn rasterize(
-        &self,
-        text: &str,
-        secondary_text: Option<&str>,
-        font_scale: f32,
-        invert: bool,
-    ) -> Vec<[u8; 90]> {
-        let font_data = fs::read(&self.font_path).expect("Invalid font path");
-        let font: Font<'static> = Font::from_bytes(font_data).unwrap();
+    Ok(())
+}
 
-        let mut length = 750;
-        let mut width;
-        let mut secondary_width = 0;
+/// Side-scans a label image into the order the printer prints it in: row `n` of the result is raster line `n`, i.e.
+/// column `n` of `image`, with dot 0 of the line on the left. Pixel `(x, y)` of `image` becomes pixel `(y, x)`.
+///
+/// This is the transposition `image_to_raster_lines()` does before packing the dots, for content composited in
+/// the printer's own scan order or checked line by line.
+pub fn transpose_for_printer(image: &image::GrayImage) -> image::GrayImage {
+    image::GrayImage::from_fn(image.height(), image.width(), |dot, line| {
+        *image.get_pixel(line, dot)
+    })
+}
 
-        if self.label.tape_size.1 == 0 {
-            // Continuous tape
-            width = self.label.dots_printable.0 + self.label.right_margin as u32;
+/// Width in pixels of the gray lines between the labels of a `proof_sheet()`
+pub const PROOF_SHEET_SEPARATOR: u32 = 4;
 
-            if self.label.tape_size.0 == 12 {
-                // 12mm label seems to need this for some reason
-                width += 25;
-                // 12mm labels have a second label below the primary that can actually be used
-                if self.second_row_image.is_some() {
-                    secondary_width = 170;
-                }
-            }
+/// Tiles label images (e.g. from `TextRasterizer::render_image()`) into one image, `columns` to a row, to check the
+/// layout of a whole batch before printing it. Every label gets a white cell as large as the largest label with its
+/// image in the top left corner, and the cells are framed by gray lines `PROOF_SHEET_SEPARATOR` pixels wide. The
+/// labels are shown as drawn, with the tape running left to right.
+pub fn proof_sheet(labels: &[image::GrayImage], columns: usize) -> image::GrayImage {
+    if labels.is_empty() {
+        return image::GrayImage::new(0, 0);
+    }
+    let columns = columns.clamp(1, labels.len()) as u32;
+    let rows = (labels.len() as u32).div_ceil(columns);
+    let cell_width = labels.iter().map(|label| label.width()).max().unwrap();
+    let cell_height = labels.iter().map(|label| label.height()).max().unwrap();
+    let mut sheet = image::GrayImage::from_pixel(
+        columns * (cell_width + PROOF_SHEET_SEPARATOR) + PROOF_SHEET_SEPARATOR,
+        rows * (cell_height + PROOF_SHEET_SEPARATOR) + PROOF_SHEET_SEPARATOR,
+        Luma([0x80]),
+    );
+    for cell in 0..columns * rows {
+        let x = PROOF_SHEET_SEPARATOR + (cell % columns) * (cell_width + PROOF_SHEET_SEPARATOR);
+        let y = PROOF_SHEET_SEPARATOR + (cell / columns) * (cell_height + PROOF_SHEET_SEPARATOR);
+        let blank = image::GrayImage::from_pixel(cell_width, cell_height, Luma([255]));
+        image::imageops::replace(&mut sheet, &blank, x, y);
+        if let Some(label) = labels.get(cell as usize) {
+            image::imageops::replace(&mut sheet, label, x, y);
+        }
+    }
+    sheet
+}
+
+/// Packs one line of dots across the print head into a raster line of `line_length` bytes, `true` for a black dot.
+///
+/// Dots are packed most significant bit first: dot `n` is bit `7 - n % 8` of byte `n / 8`, so the first dot is the
+/// top bit of the first byte. The first dot is at the edge of the print head that a label's `right_margin` is
+/// measured from, which is why label images start with the margin. Dots that don't fit in the line are dropped and
+/// the rest of a short line is left white.
+///
+/// The result is only the printable bytes of the line. The raster command in front of it is added when the line is
+/// sent, see `printer::frame_raster_line()`.
+pub fn pack_raster_line<I: IntoIterator<Item = bool>>(dots: I, line_length: usize) -> Vec<u8> {
+    let mut line = vec![0; line_length];
+    for (dot, black) in dots.into_iter().take(line_length * 8).enumerate() {
+        if black {
+            line[dot / 8] |= 0x80 >> (dot % 8);
+        }
+    }
+    line
+}
+
+/// Converts a label image made with another tool into raster lines for `label`. The image is laid out like the
+/// images `TextRasterizer` renders: its x axis runs along the tape and its y axis across it.
+///
+/// The image is scaled (keeping its aspect ratio) so that it spans the printable width of the tape. On die-cut labels
+/// it's shrunk further if needed to fit the label length and then centered along it. On continuous tape the label is
+/// as long as the scaled image. Lines are for `Resolution::Standard`.
+pub fn raster_from_image(image: &image::GrayImage, label: &Label) -> RasterLines {
+    let mut lines = image_to_raster_lines(
+        &fit_to_label(image, label, ImageFit::Contain),
+        label.raster_line_length(),
+    )
+    .expect("Images fitted to a label fit its raster lines");
+    lines.set_label(*label);
+    lines
+}
+
+/// Opens the image file at `path` and converts it into raster lines for `label`, the way `raster_from_image()` does.
+/// The image is read with its x axis along the tape, so a landscape image prints the right way round on continuous
+/// tape.
+///
+/// With `ImageFit::Cover` the image fills the whole of a die-cut label instead, cropping whatever sticks out of it.
+/// The scaled image is reduced to black and white dots using `dithering`. Fails with `InvalidImage` if the file
+/// can't be loaded.
+pub fn raster_from_image_file(
+    path: &Path,
+    label: &Label,
+    fit: ImageFit,
+    dithering: Dithering,
+) -> Result<RasterLines> {
+    let image = image::open(path)
+        .chain_err(|| RasterizeErrorKind::InvalidImage(path.to_path_buf()))?
+        .to_luma8();
+    let fitted = dither_to_1bit(&fit_to_label(&image, label, fit), dithering);
+    let mut lines = image_to_raster_lines(&fitted, label.raster_line_length())?;
+    lines.set_label(*label);
+    Ok(lines)
+}
+
+/// Splits an image that is longer than one die-cut label across as many labels as it takes, like a banner. The image
+/// is laid out as for `raster_from_image()` and scaled (keeping its aspect ratio) to span the printable width of the
+/// tape, then cut along the tape into pieces as long as the label. The last piece is padded with white.
+///
+/// Returns one set of raster lines per label in order, e.g. to print with `ThermalPrinter::print_labels()`. On
+/// continuous tape the whole image is a single label, the same as `raster_from_image()`.
+pub fn tile_across_labels(image: &image::GrayImage, label: &Label) -> Vec<RasterLines> {
+    let label_length = label.dots_printable.1;
+    if label_length == 0 {
+        return vec![raster_from_image(image, label)];
+    }
+    // Scaled the way it would be for continuous tape of the same width
+    let strip = fit_to_label(
+        image,
+        &Label {
+            dots_printable: WidthLength(label.dots_printable.0, 0),
+            ..*label
+        },
+        ImageFit::Contain,
+    );
+    let count = strip.width().div_ceil(label_length).max(1);
+    (0..count)
+        .map(|index| {
+            let start = index * label_length;
+            let piece_length = label_length.min(strip.width() - start);
+            let mut piece = image::GrayImage::from_pixel(label_length, strip.height(), Luma([255]));
+            let cut = image::imageops::crop_imm(&strip, start, 0, piece_length, strip.height());
+            image::imageops::replace(&mut piece, &cut.to_image(), 0, 0);
+            let mut lines = image_to_raster_lines(&piece, label.raster_line_length())
+                .expect("Images fitted to a label fit its raster lines");
+            lines.set_label(*label);
+            lines
+        })
+        .collect()
+}
+
+/// Scales and pads `image` for `label` as described in `raster_from_image()` and `raster_from_image_file()`
+fn fit_to_label(image: &image::GrayImage, label: &Label, fit: ImageFit) -> image::GrayImage {
+    let printable = label.dots_printable;
+    let mut ratio = printable.0 as f32 / image.height().max(1) as f32;
+    let die_cut = printable.1 != 0;
+    if die_cut {
+        let length_ratio = printable.1 as f32 / image.width().max(1) as f32;
+        ratio = match fit {
+            ImageFit::Contain => ratio.min(length_ratio),
+            ImageFit::Cover => ratio.max(length_ratio),
+        };
+    }
+    let new_length = ((image.width() as f32 * ratio).round() as u32).max(1);
+    let new_width = ((image.height() as f32 * ratio).round() as u32).max(1);
+    let resized = if (new_length, new_width) == image.dimensions() {
+        image.clone()
+    } else {
+        image::imageops::resize(image, new_length, new_width, FilterType::Triangle)
+    };
+
+    // Crop whatever sticks out of a die-cut label evenly from both ends and sides, then center what's left
+    let length = if die_cut { printable.1 } else { new_length };
+    let crop_length = new_length.min(length);
+    let crop_width = new_width.min(printable.0);
+    let cropped = image::imageops::crop_imm(
+        &resized,
+        (new_length - crop_length) / 2,
+        (new_width - crop_width) / 2,
+        crop_length,
+        crop_width,
+    );
+    // The image starts with the label's right margin, which the print head can't reach
+    let mut fitted = image::GrayImage::from_pixel(length, label.raster_width(), Luma([255]));
+    image::imageops::overlay(
+        &mut fitted,
+        &cropped.to_image(),
+        (length - crop_length) / 2,
+        label.right_margin as u32,
+    );
+    fitted
+}
+
+/// Converts a color image into black and red raster lines for two-color media (see
+/// `ThermalPrinter::print_two_color()`). Strongly red pixels are printed red, other pixels are printed black if they
+/// are darker than mid-gray.
+pub fn image_to_two_color_raster_lines(
+    image: &image::RgbImage,
+    line_length: usize,
+) -> Result<TwoColorRasterLines> {
+    let is_red = |pixel: &image::Rgb<u8>| {
+        let [r, g, b] = pixel.0;
+        r > DEFAULT_THRESHOLD && g <= DEFAULT_THRESHOLD && b <= DEFAULT_THRESHOLD
+    };
+    let black = image::GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        if is_red(pixel) {
+            Luma([255])
         } else {
-            // Die cut labels
-            width = self.label.dots_printable.0 + self.label.right_margin as u32;
-            length = self.label.dots_printable.1;
+            pixel.to_luma()
         }
+    });
+    let red = image::GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        Luma([if is_red(image.get_pixel(x, y)) {
+            0
+        } else {
+            255
+        }])
+    });
+    Ok(TwoColorRasterLines {
+        black: image_to_raster_lines(&black, line_length)?,
+        red: image_to_raster_lines(&red, line_length)?,
+    })
+}
 
-        let mut image = DynamicImage::new_luma8(length, width + secondary_width).to_luma8();
-        // Set image background
-        for (_x, y, pixel) in image.enumerate_pixels_mut() {
-            if invert {
-                let top_label_size =
-                    self.label.dots_printable.0 + self.label.right_margin as u32 + 15;
-                if y > top_label_size {
-                    *pixel = Luma([255]); // Set to white
-                } else {
-                    *pixel = Luma([0]); // Set to black
+/// Ink colors available on two-color (black and red) media like DK-22251
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Color {
+    Black,
+    Red,
+}
+
+/// How grayscale images are reduced to the black and white dots that the printer can produce
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dithering {
+    /// Every pixel darker than mid-gray becomes black (default)
+    None,
+    /// Spread the error from each pixel to its neighbours. Best for photos.
+    FloydSteinberg,
+    /// Compare pixels against a repeating Bayer matrix of the given size (2, 4 or 8, other sizes are rounded up and
+    /// capped at 8). Gives a regular pattern that suits gradients and flat shades of gray.
+    Ordered(u8),
+}
+
+/// Reduces a grayscale image to pure black (`0`) and white (`255`) pixels using the chosen dithering mode. The
+/// result can be passed straight to `image_to_raster_lines()`.
+pub fn dither_to_1bit(image: &image::GrayImage, mode: Dithering) -> image::GrayImage {
+    let threshold = |value: u8| if value > DEFAULT_THRESHOLD { 255 } else { 0 };
+    match mode {
+        Dithering::None => image::GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            Luma([threshold(image.get_pixel(x, y)[0])])
+        }),
+        Dithering::FloydSteinberg => {
+            let (width, height) = (image.width() as usize, image.height() as usize);
+            let mut values: Vec<f32> = image.pixels().map(|pixel| pixel[0] as f32).collect();
+            let mut dithered = image::GrayImage::new(image.width(), image.height());
+            for y in 0..height {
+                for x in 0..width {
+                    let old = values[y * width + x];
+                    let new = threshold(old.round().clamp(0.0, 255.0) as u8);
+                    dithered.put_pixel(x as u32, y as u32, Luma([new]));
+                    let error = old - new as f32;
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx >= 0 && (nx as usize) < width && y + dy < height {
+                            values[(y + dy) * width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
                 }
-            } else {
-                *pixel = Luma([255]); // Set to white
             }
+            dithered
         }
+        Dithering::Ordered(size) => {
+            let order = match size {
+                0..=2 => 1,
+                3..=4 => 2,
+                _ => 3,
+            };
+            let cells = 1u32 << (2 * order);
+            image::GrayImage::from_fn(image.width(), image.height(), |x, y| {
+                let rank = bayer_rank(x, y, order) as f32;
+                let level = (rank + 0.5) * 256.0 / cells as f32;
+                let value = image.get_pixel(x, y)[0] as f32;
+                Luma([if value >= level { 255 } else { 0 }])
+            })
+        }
+    }
+}
 
-        match secondary_text {
-            Some(secondary_text) => {
-                let primary = ResizedText::create(&font, text, length, 90.0 * font_scale);
-                let secondary =
-                    ResizedText::create(&font, secondary_text, length, 35.0 * font_scale);
+/// Position of `(x, y)` in a Bayer matrix of size `2^order`, from `0` to `4^order - 1`
+fn bayer_rank(x: u32, y: u32, order: u32) -> u32 {
+    let mut rank = 0;
+    for bit in 0..order {
+        let x_bit = (x >> bit) & 1;
+        let y_bit = (y >> bit) & 1;
+        rank = (rank << 2) | ((x_bit ^ y_bit) << 1) | y_bit;
+    }
+    rank
+}
 
-                let primary_offset = XY {
-                    x: (length as i32 / 2) - (primary.rendered_size.x as i32 / 2),
-                    y: (width as i32 / 2) - (primary.rendered_size.y as i32 / 2) - 25,
-                };
-                let secondary_offset = XY {
-                    x: (length as i32 / 2) - (secondary.rendered_size.x as i32 / 2),
-                    y: (width as i32 / 1) - (secondary.rendered_size.y as i32 / 2) - 20,
-                };
-                draw_glyphs(&mut image, &primary.glyphs, primary_offset, invert);
-                draw_glyphs(&mut image, &secondary.glyphs, secondary_offset, invert);
-            }
-            None => {
-                let primary = ResizedText::create(&font, text, length, 125.0 * font_scale);
+/// Horizontal placement of text along the length of the label
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextAlign {
+    /// Start the text at the left margin
+    Left,
+    /// Center the text along the label (default)
+    Center,
+    /// End the text at the right margin
+    Right,
+    /// `Left` for left-to-right text and `Right` for right-to-left text
+    Start,
+    /// `Right` for left-to-right text and `Left` for right-to-left text
+    End,
+    /// Stretch the gaps between words so the text runs from the left margin to the right margin. Text without a gap
+    /// between words is centered, and so is the last line of wrapped text.
+    Justify,
+}
 
-                let offset = XY {
-                    x: (length as i32 / 2) - (primary.rendered_size.x as i32 / 2) - 5,
-                    y: (width as i32 / 2) - (primary.rendered_size.y as i32 / 2),
-                };
+/// Where a `TextRasterizer` gets its font data from
+enum FontSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+impl fmt::Display for FontSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontSource::Path(path) => write!(f, "{}", path.display()),
+            FontSource::Bytes(_) => write!(f, "Font data"),
+        }
+    }
+}
 
-                draw_glyphs(&mut image, &primary.glyphs, offset, invert);
-            }
+/// Space in dots kept clear around text on the label.
+///
+/// Margins are relative to the direction text runs in: `left` and `right` are at the start and end of a line of
+/// text and `top` and `bottom` are across from it. All margins default to 0.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Margins {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// One line of text in a label made up of several stacked rows (see `TextRasterizer::rasterize_rows()`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextRow<'a> {
+    pub text: &'a str,
+    /// The font size the row starts at before being shrunk to fit
+    pub max_font_size: f32,
+    /// Share of the label width given to this row relative to the other rows
+    pub weight: f32,
+    /// Lines drawn through or under the row
+    pub decoration: TextDecoration,
+}
+impl<'a> TextRow<'a> {
+    /// A row with a weight of `1.0` and no decoration
+    pub fn new(text: &'a str, max_font_size: f32) -> Self {
+        Self {
+            text,
+            max_font_size,
+            weight: 1.0,
+            decoration: TextDecoration::default(),
         }
+    }
+}
 
-        if let Some(image_path) = &self.second_row_image {
-            let overlay = image::open(image_path).unwrap().to_luma8();
+/// Lines drawn along a row of text. The lines get thicker with the font size and are drawn in the same color as
+/// the text.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TextDecoration {
+    /// A line just below the baseline
+    pub underline: bool,
+    /// A line through the middle of the lowercase letters, e.g. for a crossed out price
+    pub strikethrough: bool,
+}
 
-            let top_margin = 15;
-            let ratio = overlay.width() as f32 / overlay.height() as f32;
+/// Direction that text runs in on the label
+///
+/// On a die-cut label like DK-11201 (29x90mm) `Horizontal` text reads along the long side (landscape) and
+/// `Vertical` text along the short side (portrait). Either way the image is laid out in the direction the label
+/// feeds out of the printer, so the raster lines don't change length or count.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextOrientation {
+    /// Text runs along the length of the tape in the direction it feeds out of the printer (default)
+    Horizontal,
+    /// Text is turned a quarter turn clockwise so that lines run across the width of the tape. The font size is
+    /// then limited by the label length instead of the (often much smaller) tape width.
+    Vertical,
+}
 
-            let mut new_width: u32 = length;
-            let mut new_height: u32 = (new_width as f32 / ratio) as u32;
-            if new_height > secondary_width - top_margin {
-                new_height = secondary_width - top_margin;
-                new_width = (new_height as f32 * ratio) as u32;
-            }
-            let resized = image::imageops::resize(
-                &overlay,
-                new_width,
-                new_height,
-                image::imageops::FilterType::Triangle,
-            );
-            image::imageops::overlay(&mut image, &resized, (length - new_width) / 2, width);
-        }
+/// An image placed at one end of the label beside the text
+#[derive(Debug, Clone, PartialEq)]
+struct SideImage {
+    path: PathBuf,
+    /// Dots along the tape reserved for the image
+    length: u32,
+    fit: ImageFit,
+}
 
-        // Save the image to a png file if debug mode is enabled
-        if cfg!(debug_assertions) {
-            image.save("render.png").unwrap();
+/// Configures a `TextRasterizer` in one expression instead of a series of `set_*` calls. Every method does the same
+/// as the `TextRasterizer` setter of the same name.
+pub struct TextRasterizerBuilder {
+    rasterizer: TextRasterizer,
+    /// The first error from a setting that can fail, returned by `build()`
+    error: Option<RasterizeError>,
+}
+impl TextRasterizerBuilder {
+    /// Starts with the same defaults as `TextRasterizer::new()`
+    pub fn new(label: Label, font_path: PathBuf) -> Self {
+        Self::with_rasterizer(TextRasterizer::new(label, font_path))
+    }
+    /// Starts with the same defaults as `TextRasterizer::from_font_bytes()`
+    pub fn from_font_bytes(label: Label, font: Vec<u8>) -> Self {
+        Self::with_rasterizer(TextRasterizer::from_font_bytes(label, font))
+    }
+    fn with_rasterizer(rasterizer: TextRasterizer) -> Self {
+        Self {
+            rasterizer,
+            error: None,
         }
-        image_to_raster_lines(&image, length)
+    }
+    /// See `TextRasterizer::set_font_index()`
+    pub fn font_index(mut self, index: usize) -> Self {
+        self.rasterizer.set_font_index(index);
+        self
+    }
+    /// See `TextRasterizer::set_fallback_fonts()`
+    pub fn fallback_fonts(mut self, paths: Vec<PathBuf>) -> Self {
+        self.rasterizer.set_fallback_fonts(paths);
+        self
+    }
+    /// See `TextRasterizer::set_second_row_image()`
+    pub fn second_row_image(mut self, path: PathBuf) -> Self {
+        self.rasterizer.set_second_row_image(path);
+        self
+    }
+    /// See `TextRasterizer::set_second_row_rotation()`
+    pub fn second_row_rotation(mut self, rotation: Rotation) -> Self {
+        self.rasterizer.set_second_row_rotation(rotation);
+        self
+    }
+    /// See `TextRasterizer::set_invert_backgrounds()`
+    pub fn invert_backgrounds(mut self, main: bool, second_row: bool) -> Self {
+        self.rasterizer.set_invert_backgrounds(main, second_row);
+        self
+    }
+    /// See `TextRasterizer::set_leading_image()`
+    pub fn leading_image(mut self, path: PathBuf, length: u32, fit: ImageFit) -> Self {
+        self.rasterizer.set_leading_image(path, length, fit);
+        self
+    }
+    /// See `TextRasterizer::set_trailing_image()`
+    pub fn trailing_image(mut self, path: PathBuf, length: u32, fit: ImageFit) -> Self {
+        self.rasterizer.set_trailing_image(path, length, fit);
+        self
+    }
+    /// See `TextRasterizer::set_overlay_filter()`
+    pub fn overlay_filter(mut self, filter: FilterType) -> Self {
+        self.rasterizer.set_overlay_filter(filter);
+        self
+    }
+    /// See `TextRasterizer::set_alignment()`
+    pub fn alignment(mut self, align: TextAlign) -> Self {
+        self.rasterizer.set_alignment(align);
+        self
+    }
+    /// See `TextRasterizer::set_margins()`
+    pub fn margins(mut self, margins: Margins) -> Self {
+        self.rasterizer.set_margins(margins);
+        self
+    }
+    /// See `TextRasterizer::set_orientation()`
+    pub fn orientation(mut self, orientation: TextOrientation) -> Self {
+        self.rasterizer.set_orientation(orientation);
+        self
+    }
+    /// See `TextRasterizer::set_min_font_size()`
+    pub fn min_font_size(mut self, size: f32) -> Self {
+        self.rasterizer.set_min_font_size(size);
+        self
+    }
+    /// See `TextRasterizer::set_font_sizing()`
+    pub fn font_sizing(mut self, sizing: FontSizing) -> Self {
+        self.rasterizer.set_font_sizing(sizing);
+        self
+    }
+    /// See `TextRasterizer::set_letter_spacing()`
+    pub fn letter_spacing(mut self, spacing: f32) -> Self {
+        self.rasterizer.set_letter_spacing(spacing);
+        self
+    }
+    /// See `TextRasterizer::set_word_spacing()`
+    pub fn word_spacing(mut self, spacing: f32) -> Self {
+        self.rasterizer.set_word_spacing(spacing);
+        self
+    }
+    /// See `TextRasterizer::set_overflow()`
+    pub fn overflow(mut self, overflow: TextOverflow) -> Self {
+        self.rasterizer.set_overflow(overflow);
+        self
+    }
+    /// See `TextRasterizer::set_highlight_behind_text()`
+    pub fn highlight_behind_text(mut self, enabled: bool) -> Self {
+        self.rasterizer.set_highlight_behind_text(enabled);
+        self
+    }
+    /// See `TextRasterizer::set_outline()`
+    pub fn outline(mut self, width: u32, white: bool) -> Self {
+        self.rasterizer.set_outline(width, white);
+        self
+    }
+    /// See `TextRasterizer::set_mirror()`
+    pub fn mirror(mut self, enabled: bool) -> Self {
+        self.rasterizer.set_mirror(enabled);
+        self
+    }
+    /// See `TextRasterizer::set_dithering()`
+    pub fn dithering(mut self, dithering: Dithering) -> Self {
+        self.rasterizer.set_dithering(dithering);
+        self
+    }
+    /// See `TextRasterizer::set_threshold()`
+    pub fn threshold(mut self, threshold: u8) -> Self {
+        self.rasterizer.set_threshold(threshold);
+        self
+    }
+    /// See `TextRasterizer::set_resolution()`
+    pub fn resolution(mut self, resolution: Resolution) -> Self {
+        self.rasterizer.set_resolution(resolution);
+        self
+    }
+    /// See `TextRasterizer::set_continuous_length()`
+    pub fn continuous_length(mut self, dots: u32) -> Self {
+        self.rasterizer.set_continuous_length(dots);
+        self
+    }
+    /// See `TextRasterizer::set_continuous_length_mm()`
+    pub fn continuous_length_mm(mut self, mm: f32) -> Self {
+        self.rasterizer.set_continuous_length_mm(mm);
+        self
+    }
+    /// See `TextRasterizer::set_content_length_margins()`
+    pub fn content_length_margins(mut self, margins: Option<(u32, u32)>) -> Self {
+        self.rasterizer.set_content_length_margins(margins);
+        self
+    }
+    /// See `TextRasterizer::set_debug_output()`
+    pub fn debug_output(mut self, path: Option<PathBuf>) -> Self {
+        self.rasterizer.set_debug_output(path);
+        self
+    }
+    /// See `TextRasterizer::set_min_readable_font_size()`
+    pub fn min_readable_font_size(mut self, size: f32) -> Self {
+        self.rasterizer.set_min_readable_font_size(size);
+        self
+    }
+    /// See `TextRasterizer::set_warning_handler()`
+    pub fn warning_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&RasterizeWarning) + Send + Sync + 'static,
+    {
+        self.rasterizer.set_warning_handler(handler);
+        self
+    }
+    /// See `TextRasterizer::set_qr_code()`. An error is returned by `build()`.
+    pub fn qr_code(mut self, data: &str, ec_level: QrEcLevel) -> Self {
+        if let Err(error) = self.rasterizer.set_qr_code(data, ec_level) {
+            self.error.get_or_insert(error);
+        }
+        self
+    }
+    /// Returns the configured rasterizer, or the first error from a setting that failed
+    pub fn build(self) -> Result<TextRasterizer> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.rasterizer),
+        }
+    }
+}
+
+/// Easily convert text into a raster image that can be printed by a `ThermalPrinter`
+///
+/// Use `TextRasterizerBuilder` to configure one in a single expression.
+pub struct TextRasterizer {
+    label: Label,
+    font: FontSource,
+    font_index: usize,
+    fallback_fonts: Vec<FontSource>,
+    /// The main font followed by the fallback fonts. Parsed the first time they're needed and reused for every label
+    /// after that.
+    parsed_fonts: OnceLock<Vec<Font<'static>>>,
+    second_row_image: Option<PathBuf>,
+    second_row_rotation: Rotation,
+    invert_backgrounds: (bool, bool),
+    qr_code: Option<QrModules>,
+    leading_image: Option<SideImage>,
+    trailing_image: Option<SideImage>,
+    overlay_filter: FilterType,
+    alignment: TextAlign,
+    margins: Margins,
+    orientation: TextOrientation,
+    min_font_size: f32,
+    font_sizing: FontSizing,
+    letter_spacing: f32,
+    word_spacing: f32,
+    overflow: TextOverflow,
+    highlight_behind_text: bool,
+    outline: Option<(u32, bool)>,
+    mirror: bool,
+    dithering: Dithering,
+    threshold: u8,
+    resolution: Resolution,
+    continuous_length: u32,
+    content_length_margins: Option<(u32, u32)>,
+    debug_output: Option<PathBuf>,
+    min_readable_font_size: Option<f32>,
+    warning_handler: Option<WarningHandler>,
+}
+impl TextRasterizer {
+    /// The text rasterizer needs to know the loaded label media currently in the printer in order to resize and
+    /// shift the text content accordingly so that it will fit.
+    pub fn new(label: Label, font_path: PathBuf) -> Self {
+        Self::with_font_source(label, FontSource::Path(font_path))
+    }
+    /// Same as `new()` but looks the label up by its size (e.g. `"62"` or `"29x90"`) or part number (e.g.
+    /// `"DK-1201"`) with `constants::label_by_name()`. Fails with `UnknownLabel` if there's no such label.
+    pub fn new_for(label: &str, font_path: PathBuf) -> Result<Self> {
+        let label = crate::printer::constants::label_by_name(label)
+            .ok_or_else(|| RasterizeErrorKind::UnknownLabel(label.to_string()))?;
+        Ok(Self::new(label, font_path))
+    }
+    /// Same as `new()` but renders with font data that is already in memory (e.g. from `include_bytes!`) instead
+    /// of reading a font file from disk.
+    pub fn from_font_bytes(label: Label, font: Vec<u8>) -> Self {
+        Self::with_font_source(label, FontSource::Bytes(font))
+    }
+    fn with_font_source(label: Label, font: FontSource) -> Self {
+        Self {
+            label,
+            font,
+            font_index: 0,
+            fallback_fonts: Vec::new(),
+            parsed_fonts: OnceLock::new(),
+            second_row_image: None,
+            second_row_rotation: Rotation::Deg0,
+            invert_backgrounds: (true, false),
+            qr_code: None,
+            leading_image: None,
+            trailing_image: None,
+            overlay_filter: FilterType::Triangle,
+            alignment: TextAlign::Center,
+            margins: Margins::default(),
+            orientation: TextOrientation::Horizontal,
+            min_font_size: 1.0,
+            font_sizing: FontSizing::AutoShrink { max: f32::INFINITY },
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            overflow: TextOverflow::Error,
+            highlight_behind_text: false,
+            outline: None,
+            mirror: false,
+            dithering: Dithering::None,
+            threshold: DEFAULT_THRESHOLD,
+            resolution: Resolution::Standard,
+            continuous_length: DEFAULT_CONTINUOUS_LENGTH,
+            content_length_margins: None,
+            debug_output: None,
+            min_readable_font_size: None,
+            warning_handler: None,
+        }
+    }
+    /// Same as `new()` but uses the face at `index` of a font collection (e.g. a `.ttc` file containing several
+    /// weights) instead of the first one.
+    ///
+    /// The font file is read and parsed immediately so that a missing file or an out-of-range index is reported
+    /// here rather than when rasterizing.
+    pub fn with_font_index(label: Label, font_path: PathBuf, index: usize) -> Result<Self> {
+        let mut rasterizer = Self::new(label, font_path);
+        rasterizer.set_font_index(index);
+        rasterizer.try_load_fonts()?;
+        Ok(rasterizer)
+    }
+    /// Some types of label media (e.g. 12mm continuous tape) are wider than specified. Use this method to draw
+    /// an image onto this second, normally out-of-bounds part of the tape. The bottom portion of the tape
+    /// is usually pre-scored from the top part so consider this a way to make "bonus" labels with the same
+    /// amount of physical tape.
+    ///
+    /// The image is only opened when rasterizing, which fails with `InvalidSecondRowImage` if it can't be loaded.
+    pub fn set_second_row_image(&mut self, path: PathBuf) {
+        self.second_row_image = Some(path);
+    }
+    /// Turns the second row image before it's scaled into place, e.g. by 180° so it reads the right way up once the
+    /// second row is peeled off and stuck on upside down. Defaults to `Rotation::Deg0`.
+    pub fn set_second_row_rotation(&mut self, rotation: Rotation) {
+        self.second_row_rotation = rotation;
+    }
+    /// Chooses which areas turn black when rasterizing with `invert`: the main label and the bonus second row of 12mm
+    /// tape (see `set_second_row_image()`). An area left white keeps black text or its second row image as usual.
+    /// Defaults to `(true, false)`, inverting only the main label.
+    pub fn set_invert_backgrounds(&mut self, main: bool, second_row: bool) {
+        self.invert_backgrounds = (main, second_row);
+    }
+    /// Place a QR code encoding `data` at the end of the label. It's made as large as fits across the tape inside
+    /// the margins with every module a whole number of dots, and text is laid out in the length left in front of
+    /// it. Fails if `data` is too long for a QR code at `ec_level`.
+    pub fn set_qr_code(&mut self, data: &str, ec_level: QrEcLevel) -> Result<()> {
+        self.qr_code = Some(QrModules::encode(data, ec_level)?);
+        Ok(())
+    }
+    /// Place an image (e.g. a logo) at the start of the label, in front of the text. The image is fitted into an
+    /// area `length` dots along the tape and as wide as the tape inside the margins, and text is laid out in the
+    /// length left after it.
+    ///
+    /// The image is only opened when rasterizing, which fails with `InvalidImage` if it can't be loaded.
+    pub fn set_leading_image(&mut self, path: PathBuf, length: u32, fit: ImageFit) {
+        self.leading_image = Some(SideImage { path, length, fit });
+    }
+    /// Same as `set_leading_image()` but places the image at the end of the label after the text (and in front of
+    /// the QR code, if any).
+    pub fn set_trailing_image(&mut self, path: PathBuf, length: u32, fit: ImageFit) {
+        self.trailing_image = Some(SideImage { path, length, fit });
+    }
+    /// Choose the filter used to scale the second row, leading and trailing images. `FilterType::Nearest` keeps line
+    /// art crisp while `FilterType::Lanczos3` suits photos. Defaults to `FilterType::Triangle`.
+    pub fn set_overlay_filter(&mut self, filter: FilterType) {
+        self.overlay_filter = filter;
+    }
+    /// Select which face of a font collection to render with. Plain font files only contain face `0`, which is
+    /// the default.
+    pub fn set_font_index(&mut self, index: usize) {
+        self.font_index = index;
+        self.parsed_fonts = OnceLock::new();
+    }
+    /// Fonts to take characters from when the main font doesn't have them (e.g. emoji or symbols), tried in order.
+    /// Characters that none of the fonts have are drawn with the main font's missing glyph symbol, which is usually
+    /// a box. Only monochrome outline fonts can be drawn, so color emoji fonts don't work here.
+    pub fn set_fallback_fonts(&mut self, paths: Vec<PathBuf>) {
+        self.fallback_fonts = paths.into_iter().map(FontSource::Path).collect();
+        self.parsed_fonts = OnceLock::new();
+    }
+    /// Choose how text is placed along the length of the label. Both the primary and secondary text (and every
+    /// line of wrapped text) follow this alignment. Defaults to `TextAlign::Center`.
+    pub fn set_alignment(&mut self, align: TextAlign) {
+        self.alignment = align;
+    }
+    /// Keep text away from the edges of the label. Text is fitted and aligned within the area left inside the
+    /// margins, so a larger left margin moves left aligned text right and shifts centered text by half as much.
+    pub fn set_margins(&mut self, margins: Margins) {
+        self.margins = margins;
+    }
+    /// Choose whether text runs along the tape or across it. Alignment is applied along the direction the text
+    /// runs. Defaults to `TextOrientation::Horizontal`.
+    pub fn set_orientation(&mut self, orientation: TextOrientation) {
+        self.orientation = orientation;
+    }
+    /// The smallest font size (in dots) that text will be shrunk to while trying to fit it on the label. Defaults
+    /// to 1 dot.
+    pub fn set_min_font_size(&mut self, size: f32) {
+        self.min_font_size = size;
+    }
+    /// Choose between shrinking text to fit the label and drawing it at one exact size so that every label comes out
+    /// the same. With `FontSizing::Fixed` the secondary text and every wrapped line or row are drawn at the same size.
+    /// Defaults to `FontSizing::AutoShrink` with no maximum.
+    pub fn set_font_sizing(&mut self, sizing: FontSizing) {
+        self.font_sizing = sizing;
+    }
+    /// Add `spacing` dots of space after every character, or remove space between characters with a negative value.
+    /// Text is still shrunk to fit with the spacing included. Defaults to 0.
+    pub fn set_letter_spacing(&mut self, spacing: f32) {
+        self.letter_spacing = spacing;
+    }
+    /// Add `spacing` dots of space at every space between words (on top of the letter spacing), or tighten the gaps
+    /// with a negative value. Defaults to 0.
+    pub fn set_word_spacing(&mut self, spacing: f32) {
+        self.word_spacing = spacing;
+    }
+    /// Choose what happens to text that doesn't fit on the label even at the minimum font size. Defaults to
+    /// `TextOverflow::Error`.
+    pub fn set_overflow(&mut self, overflow: TextOverflow) {
+        self.overflow = overflow;
+    }
+    /// Draw every row of text white on a black box just large enough for it, leaving the rest of the label white.
+    /// This gives a dark banner look that needs far less heat than inverting the whole label. With `invert` the
+    /// colors swap: black text on white boxes on a black label. Defaults to `false`.
+    pub fn set_highlight_behind_text(&mut self, enabled: bool) {
+        self.highlight_behind_text = enabled;
+    }
+    /// Draw an outline `width` dots wide around every glyph, white if `white` is set and black otherwise, so text
+    /// stays legible where it overlaps images. A width of 0 turns the outline off (default). The outline can grow
+    /// text past the margins and is clipped like the text itself, so leave room for it.
+    pub fn set_outline(&mut self, width: u32, white: bool) {
+        self.outline = if width == 0 {
+            None
+        } else {
+            Some((width, white))
+        };
+    }
+    /// Print labels mirrored along the tape, so they read correctly from the other side once applied, e.g. on iron-on
+    /// transfers or from behind transparent tape. The label image is flipped end to end just before it's turned into
+    /// raster lines, which sends the lines in reverse order. Images returned by the `render_*` methods aren't
+    /// mirrored. Defaults to `false`.
+    pub fn set_mirror(&mut self, enabled: bool) {
+        self.mirror = enabled;
+    }
+    /// Choose how the anti-aliased label image (including any second row image) is reduced to black and white
+    /// dots. Defaults to `Dithering::None`, which cuts off the anti-aliased edges of text at the threshold.
+    /// `Dithering::Ordered` keeps some of them as a pattern of dots instead, which makes small text look smoother.
+    pub fn set_dithering(&mut self, dithering: Dithering) {
+        self.dithering = dithering;
+    }
+    /// Pixels with a luma value at or below `threshold` are printed black. Raise it to print text heavier or lower
+    /// it to print lighter. At the default, pixels of text that are at least half covered by a glyph are printed. Only
+    /// used with `Dithering::None` since dithered images are already black and white. Defaults to `DEFAULT_THRESHOLD`.
+    ///
+    /// Brother's raster command reference has no print density or energy command for QL printers, so adjusting the
+    /// threshold is how to make labels print darker or lighter.
+    pub fn set_threshold(&mut self, threshold: u8) {
+        self.threshold = threshold;
+    }
+    /// Render labels at 600dpi along the length of the tape, which sharpens small text. The rasterized lines are
+    /// marked with the resolution so `ThermalPrinter` prints them in high resolution mode. Images returned by the
+    /// `render_*` methods are then twice as long. Defaults to `Resolution::Standard`.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+    /// Length in 300dpi dots of labels on continuous tape, which is the number of raster lines printed at
+    /// `Resolution::Standard`. Defaults to `DEFAULT_CONTINUOUS_LENGTH`. Die-cut labels always have their own length.
+    pub fn set_continuous_length(&mut self, dots: u32) {
+        self.continuous_length = dots.max(1);
+    }
+    /// Same as `set_continuous_length()` but in millimeters, rounded to the nearest dot
+    pub fn set_continuous_length_mm(&mut self, mm: f32) {
+        self.set_continuous_length(self.label.mm_to_dots(mm));
+    }
+    /// Cut labels on continuous tape to the length of what's drawn on them instead of the continuous length. With
+    /// `Some((leading, trailing))` the label runs from `leading` dots before the first drawn text or image to
+    /// `trailing` dots after the last, but never past the continuous length, which then acts as the longest label
+    /// the content is fitted into. Labels are never shorter than `MIN_CONTINUOUS_LENGTH` and blank labels keep the
+    /// continuous length. The printer feeds and cuts the tape after the last line, so nothing else needs to change to
+    /// print them. Defaults to `None`. Die-cut labels always have their own length.
+    pub fn set_content_length_margins(&mut self, margins: Option<(u32, u32)>) {
+        self.content_length_margins = margins;
+    }
+    /// Save a copy of every label image that gets rasterized to `path` (in a format chosen by the file extension)
+    /// for debugging. Disabled by default. A failure to write the file is returned as an error when rasterizing.
+    pub fn set_debug_output(&mut self, path: Option<PathBuf>) {
+        self.debug_output = path;
+    }
+    /// Report text shrunk below `size` dots with a `RasterizeWarning::TextTooSmall`, e.g. to flag labels that would
+    /// come out unreadable before printing them. Unlike `set_min_font_size()` the text is still drawn. Disabled by
+    /// default.
+    pub fn set_min_readable_font_size(&mut self, size: f32) {
+        self.min_readable_font_size = Some(size);
+    }
+    /// Call `handler` with every warning while rasterizing. Without a handler warnings are ignored.
+    pub fn set_warning_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&RasterizeWarning) + Send + Sync + 'static,
+    {
+        self.warning_handler = Some(Box::new(handler));
+    }
+
+    /// Warns if `text` drawn at `font_size` (in dots of the image being drawn) is below the readable size
+    fn check_readable(&self, text: &str, font_size: f32) {
+        let font_size = font_size / self.scale() as f32;
+        if let (Some(min_readable), Some(handler)) =
+            (self.min_readable_font_size, &self.warning_handler)
+        {
+            if font_size < min_readable {
+                handler(&RasterizeWarning::TextTooSmall {
+                    text: text.to_string(),
+                    font_size,
+                    min_readable,
+                });
+            }
+        }
+    }
+
+    fn fit_text<'a>(
+        &self,
+        style: TextStyle<'a>,
+        text: &str,
+        max_width: u32,
+        max_font_size: f32,
+    ) -> Result<ResizedText<'a>> {
+        let (max_font_size, min_font_size) = self.font_sizes(max_font_size);
+        let resized = ResizedText::create(
+            style,
+            text,
+            self.available_length(max_width),
+            max_font_size,
+            min_font_size,
+            self.overflow,
+        )?;
+        self.check_readable(text, resized.font_size);
+        Ok(resized)
+    }
+
+    /// Returns the font size to start at and the smallest size to shrink to for text that would otherwise start at
+    /// `max_font_size`, all in dots of the image being drawn
+    fn font_sizes(&self, max_font_size: f32) -> (f32, f32) {
+        let scale = self.scale() as f32;
+        match self.font_sizing {
+            FontSizing::AutoShrink { max } => (
+                max_font_size.min(max * scale).ceil(),
+                (self.min_font_size * scale).max(1.0),
+            ),
+            FontSizing::Fixed(size) => (size * scale, size * scale),
+        }
+    }
+
+    /// The fonts and spacing to lay text out with in dots of the image being drawn
+    fn text_style<'a>(&self, fonts: &'a [Font<'a>]) -> TextStyle<'a> {
+        let scale = self.scale() as f32;
+        TextStyle {
+            letter_spacing: self.letter_spacing * scale,
+            word_spacing: self.word_spacing * scale,
+            ..TextStyle::new(fonts)
+        }
+    }
+
+    /// Labels are drawn this many times larger than printed in each direction. High resolution labels are then
+    /// squeezed back to the printed width across the tape (see `finish_image()`).
+    fn scale(&self) -> u32 {
+        self.resolution.lines_per_dot()
+    }
+
+    /// The margins in dots of the image being drawn, not counting space reserved for a QR code. Round labels add the
+    /// space around the square inscribed in their printable circle.
+    fn user_margins(&self) -> Margins {
+        let scale = self.scale();
+        let (near, far, ends) = self.shape_inset();
+        let (top, bottom, left, right) = match self.orientation {
+            TextOrientation::Horizontal => (near, far, ends, ends),
+            TextOrientation::Vertical => (ends, ends, near, far),
+        };
+        Margins {
+            top: self.margins.top * scale + top,
+            bottom: self.margins.bottom * scale + bottom,
+            left: self.margins.left * scale + left,
+            right: self.margins.right * scale + right,
+        }
+    }
+
+    /// The `(near, far, ends)` space in dots that keeps content inside the printable circle of round labels. The
+    /// circle starts after the label's right margin at the near edge.
+    fn shape_inset(&self) -> (u32, u32, u32) {
+        match self.label.shape {
+            LabelShape::Rectangle => (0, 0, 0),
+            LabelShape::Round => {
+                let diameter = (self.label.dots_printable.0 * self.scale()) as f32;
+                let inset =
+                    (diameter * (1.0 - std::f32::consts::FRAC_1_SQRT_2) / 2.0).ceil() as u32;
+                let right_margin = self.label.right_margin as u32 * self.scale();
+                (right_margin + inset, inset, inset)
+            }
+        }
+    }
+
+    /// The margins in dots that text is laid out inside. The length taken by images and the QR code at either end of
+    /// the label is added to whichever margin runs along the tape at that end.
+    fn margins(&self) -> Margins {
+        let mut margins = self.user_margins();
+        let image_length =
+            |image: &Option<SideImage>| image.as_ref().map_or(0, |image| image.length);
+        let leading = image_length(&self.leading_image) * self.scale();
+        let mut trailing = image_length(&self.trailing_image) * self.scale();
+        if let Some((_, qr_side)) = self.qr_code_layout() {
+            trailing += qr_side;
+        }
+        match self.orientation {
+            TextOrientation::Horizontal => {
+                margins.left += leading;
+                margins.right += trailing;
+            }
+            // The top of a vertical text canvas ends up at the end of the label once rotated
+            TextOrientation::Vertical => {
+                margins.bottom += leading;
+                margins.top += trailing;
+            }
+        }
+        margins
+    }
+
+    /// Width in dots across the tape inside the margins given to leading and trailing images and the QR code
+    fn image_width(&self) -> u32 {
+        let (near, far, ..) = self.edge_margins();
+        let (_, width, _) = self.dimensions();
+        width.saturating_sub(near + far)
+    }
+
+    /// Returns the `(module_px, side)` of the QR code in dots of the image being drawn. The code may take up at most
+    /// half of the label length. Modules are a whole number of printer dots so they stay square when high
+    /// resolution labels are squeezed.
+    fn qr_code_layout(&self) -> Option<(u32, u32)> {
+        let qr_code = self.qr_code.as_ref()?;
+        let (_, _, start, end) = self.edge_margins();
+        let (length, ..) = self.dimensions();
+        let side = self
+            .image_width()
+            .min(length.saturating_sub(start + end) / 2);
+        let scale = self.scale();
+        let module_px = side / scale / qr_code.total_size() * scale;
+        Some((module_px, module_px * qr_code.total_size()))
+    }
+
+    /// The `(near, far, start, end)` margins of the label image regardless of text orientation: across the tape from
+    /// the first printed dot and the other side, then along the tape from the start and end of the label
+    fn edge_margins(&self) -> (u32, u32, u32, u32) {
+        let margins = self.user_margins();
+        match self.orientation {
+            TextOrientation::Horizontal => {
+                (margins.top, margins.bottom, margins.left, margins.right)
+            }
+            TextOrientation::Vertical => (margins.left, margins.right, margins.bottom, margins.top),
+        }
+    }
+
+    /// How much of the label length text may take up inside the left and right margins
+    fn available_length(&self, length: u32) -> u32 {
+        let margins = self.margins();
+        length.saturating_sub(margins.left + margins.right)
+    }
+
+    /// How much of the label width text may take up inside the top and bottom margins
+    fn available_width(&self, width: u32) -> u32 {
+        let margins = self.margins();
+        width.saturating_sub(margins.top + margins.bottom)
+    }
+
+    /// Returns the x offset at which the text starts for the current alignment
+    /// With `TextAlign::Justify` the text is also stretched to fill the line (see `ResizedText::justify()`).
+    fn aligned_x(&self, length: u32, text: &mut ResizedText) -> i32 {
+        let left = self.margins().left as i32;
+        let text_width = text.rendered_size.x as i32;
+        match (self.alignment, text.rtl) {
+            (TextAlign::Left, _) | (TextAlign::Start, false) | (TextAlign::End, true) => left,
+            (TextAlign::Center, _) => self.centered_x(length, text),
+            (TextAlign::Right, _) | (TextAlign::Start, true) | (TextAlign::End, false) => {
+                length as i32 - self.margins().right as i32 - text_width
+            }
+            (TextAlign::Justify, _) => {
+                if text.justify(self.available_length(length)) {
+                    left
+                } else {
+                    self.centered_x(length, text)
+                }
+            }
+        }
+    }
+
+    fn centered_x(&self, length: u32, text: &ResizedText) -> i32 {
+        self.margins().left as i32 + (self.available_length(length) as i32 / 2)
+            - (text.rendered_size.x as i32 / 2)
+    }
+
+    /// Returns the y offset that vertically centers text of the given height between `top` and `bottom`
+    fn centered_y(top: i32, bottom: i32, text_height: u32) -> i32 {
+        top + ((bottom - top) / 2) - (text_height as i32 / 2)
+    }
+
+    /// Returns the parsed main font followed by the fallback fonts, reading and parsing them only on first use
+    fn try_load_fonts(&self) -> Result<&[Font<'static>]> {
+        if let Some(fonts) = self.parsed_fonts.get() {
+            return Ok(fonts);
+        }
+        let mut fonts = vec![Self::load_font(&self.font, self.font_index)?];
+        for fallback in &self.fallback_fonts {
+            fonts.push(Self::load_font(fallback, 0)?);
+        }
+        Ok(self.parsed_fonts.get_or_init(|| fonts))
+    }
+
+    fn load_font(source: &FontSource, face_index: usize) -> Result<Font<'static>> {
+        let mut font_data = match source {
+            FontSource::Path(path) => fs::read(path)?,
+            FontSource::Bytes(bytes) => bytes.clone(),
+        };
+        let invalid_font = || RasterizeErrorKind::InvalidFont(source.to_string(), face_index);
+        let index = u32::try_from(face_index).map_err(|_| invalid_font())?;
+        // Plain font files ignore the index entirely so check it against the collection size ourselves
+        if index >= face_count(&font_data) {
+            bail!(invalid_font());
+        }
+        repair_v_metrics(&mut font_data, index);
+        Ok(Font::try_from_vec_and_index(font_data, index).ok_or_else(invalid_font)?)
+    }
+
+    /// Returns the `(length, width, secondary_width)` in dots of the image to draw for the loaded label
+    ///
+    /// The width covers the label's right margin and printable area (see `Label::raster_width()`) so that the image
+    /// lines up with the raster lines sent to the printer. Everything is multiplied by `scale()`.
+    fn dimensions(&self) -> (u32, u32, u32) {
+        let scale = self.scale();
+        let width = self.label.raster_width() * scale;
+        if self.label.tape_size.1 != 0 {
+            // Die cut labels
+            return (self.label.dots_printable.1 * scale, width, 0);
+        }
+        // Continuous tape
+        let mut secondary_width = 0;
+        // 12mm labels have a second label below the primary that can actually be used. It's pre-scored from the
+        // primary and as wide as the tape itself.
+        if self.label.tape_size.0 == 12 && self.second_row_image.is_some() {
+            secondary_width = self.label.dots.0 * scale;
+        }
+        (self.continuous_length * scale, width, secondary_width)
+    }
+
+    /// Which of the main label and the bonus row are drawn light on dark when rasterizing with `invert`, see
+    /// `set_invert_backgrounds()`
+    fn inverted_areas(&self, invert: bool) -> (bool, bool) {
+        let (main, second_row) = self.invert_backgrounds;
+        (invert && main, invert && second_row)
+    }
+
+    /// Creates the image to draw on: the main `length` by `width` label followed by the `secondary_width` bonus row
+    /// of 12mm tape. When inverted, the areas chosen with `set_invert_backgrounds()` are black.
+    fn blank_image(
+        &self,
+        length: u32,
+        width: u32,
+        secondary_width: u32,
+        invert: bool,
+    ) -> image::GrayImage {
+        let (main, second_row) = self.inverted_areas(invert);
+        let background = |inverted| Luma([if inverted { 0 } else { 255 }]);
+        image::GrayImage::from_fn(length, width + secondary_width, |_, y| {
+            background(if y < width { main } else { second_row })
+        })
+    }
+
+    /// Runs `draw` with a canvas covering the main `length` by `width` area of the label along with the length and
+    /// width to lay text out in. For vertical text the canvas is transposed and rotated into place afterwards.
+    fn draw_text_area<F>(
+        &self,
+        image: &mut image::GrayImage,
+        length: u32,
+        width: u32,
+        invert: bool,
+        draw: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut image::GrayImage, u32, u32) -> Result<()>,
+    {
+        match self.orientation {
+            TextOrientation::Horizontal => draw(image, length, width),
+            TextOrientation::Vertical => {
+                let background = if invert { 0 } else { 255 };
+                let mut canvas = image::GrayImage::from_pixel(width, length, Luma([background]));
+                draw(&mut canvas, width, length)?;
+                image::imageops::overlay(image, &image::imageops::rotate90(&canvas), 0, 0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Draws one row of `text` at `offset` inside `clip` like `draw_glyphs()`. With `set_highlight_behind_text()` the
+    /// row's box is filled in the text color first, padded along the tape by a sixth of its height, and the text is
+    /// drawn in the background color on top. With `set_outline()` the outline is drawn under the text. Returns
+    /// whether the text was drawn light on dark.
+    fn draw_text_row(
+        &self,
+        image: &mut image::GrayImage,
+        text: &ResizedText,
+        offset: XY<i32>,
+        clip: Rect<i32>,
+        invert: bool,
+    ) -> bool {
+        if self.highlight_behind_text {
+            Self::draw_highlight(image, text, offset, clip, invert);
+        }
+        if let Some((width, white)) = self.outline {
+            // The text shifted by up to the outline width in all 8 directions covers the glyphs grown by that width
+            for distance in 1..=(width * self.scale()) as i32 {
+                for (dx, dy) in [
+                    (-1, -1),
+                    (0, -1),
+                    (1, -1),
+                    (-1, 0),
+                    (1, 0),
+                    (-1, 1),
+                    (0, 1),
+                    (1, 1),
+                ] {
+                    let shifted = XY {
+                        x: offset.x + dx * distance,
+                        y: offset.y + dy * distance,
+                    };
+                    draw_glyphs(image, &text.glyphs, shifted, clip, white);
+                }
+            }
+        }
+        let light_on_dark = invert != self.highlight_behind_text;
+        draw_glyphs(image, &text.glyphs, offset, clip, light_on_dark);
+        light_on_dark
+    }
+
+    /// Fills the box behind `text` at `offset` in the text color for `set_highlight_behind_text()`
+    fn draw_highlight(
+        image: &mut image::GrayImage,
+        text: &ResizedText,
+        offset: XY<i32>,
+        clip: Rect<i32>,
+        invert: bool,
+    ) {
+        let ink = text
+            .glyphs
+            .iter()
+            .filter_map(|glyph| glyph.pixel_bounding_box())
+            .map(|bounds| (bounds.min.x, bounds.max.x))
+            .reduce(|(min, max), (start, end)| (min.min(start), max.max(end)));
+        if let Some((start, end)) = ink {
+            let padding = text.rendered_size.y as i32 / 6;
+            let min_x = (offset.x + start - padding).max(clip.min.x).max(0);
+            let max_x = (offset.x + end + padding)
+                .min(clip.max.x)
+                .min(image.width() as i32);
+            let min_y = offset.y.max(clip.min.y).max(0);
+            let max_y = (offset.y + text.rendered_size.y as i32)
+                .min(clip.max.y)
+                .min(image.height() as i32);
+            let fill = Luma([if invert { 255 } else { 0 }]);
+            for x in min_x..max_x {
+                for y in min_y..max_y {
+                    image.put_pixel(x as u32, y as u32, fill);
+                }
+            }
+        }
+    }
+
+    /// Draws the QR code (if any) at the end of the main label area, centered across the tape inside the margins
+    fn draw_qr_code(&self, image: &mut image::GrayImage, length: u32, width: u32) -> Result<()> {
+        let (qr_code, (module_px, side)) = match (&self.qr_code, self.qr_code_layout()) {
+            (Some(qr_code), Some(layout)) => (qr_code, layout),
+            _ => return Ok(()),
+        };
+        let (near, far, _, end) = self.edge_margins();
+        if module_px == 0 || side + end > length {
+            bail!(RasterizeErrorKind::QrCodeDoesNotFit);
+        }
+        let x = length - end - side;
+        let y = near + (width.saturating_sub(near + far) - side) / 2;
+        image::imageops::overlay(image, &qr_code.render(module_px), x, y);
+        Ok(())
+    }
+
+    /// Draws the leading and trailing images and the QR code (if any) in the space reserved for them by `margins()`
+    fn draw_images(&self, image: &mut image::GrayImage, length: u32, width: u32) -> Result<()> {
+        let (near, _, start, end) = self.edge_margins();
+        if let Some(leading) = &self.leading_image {
+            let fitted = self.fit_image(leading)?;
+            image::imageops::overlay(image, &fitted, start, near);
+        }
+        if let Some(trailing) = &self.trailing_image {
+            let qr_side = self.qr_code_layout().map_or(0, |(_, qr_side)| qr_side);
+            let fitted = self.fit_image(trailing)?;
+            let x = length.saturating_sub(end + qr_side + fitted.width());
+            image::imageops::overlay(image, &fitted, x, near);
+        }
+        self.draw_qr_code(image, length, width)
+    }
+
+    /// Opens a leading or trailing image and scales it to the area reserved for it, centering it if it doesn't fill
+    /// the area
+    fn fit_image(&self, side_image: &SideImage) -> Result<image::GrayImage> {
+        let (length, width) = (side_image.length * self.scale(), self.image_width());
+        self.open_image_into(&side_image.path, length, width, side_image.fit)
+    }
+
+    /// Opens the image at `path` and scales it to a `length` by `width` area, cropping whatever sticks out of the area
+    /// evenly from both sides and centering what's left
+    fn open_image_into(
+        &self,
+        path: &Path,
+        length: u32,
+        width: u32,
+        fit: ImageFit,
+    ) -> Result<image::GrayImage> {
+        let source = image::open(path)
+            .chain_err(|| RasterizeErrorKind::InvalidImage(path.to_path_buf()))?
+            .to_luma8();
+        let (source_length, source_width) = (source.width() as f32, source.height() as f32);
+        let ratio = match fit {
+            ImageFit::Contain => (length as f32 / source_length).min(width as f32 / source_width),
+            ImageFit::Cover => (length as f32 / source_length).max(width as f32 / source_width),
+        };
+        let new_length = ((source_length * ratio).round() as u32).max(1);
+        let new_width = ((source_width * ratio).round() as u32).max(1);
+        let resized = image::imageops::resize(&source, new_length, new_width, self.overlay_filter);
+
+        let crop_length = new_length.min(length);
+        let crop_width = new_width.min(width);
+        let cropped = image::imageops::crop_imm(
+            &resized,
+            (new_length - crop_length) / 2,
+            (new_width - crop_width) / 2,
+            crop_length,
+            crop_width,
+        );
+        let mut fitted = image::GrayImage::from_pixel(length, width, Luma([255]));
+        image::imageops::overlay(
+            &mut fitted,
+            &cropped.to_image(),
+            (length - crop_length) / 2,
+            (width - crop_width) / 2,
+        );
+        Ok(fitted)
+    }
+
+    /// Draws the second row image (if any) below the main label area. It's drawn in its own colors unless the bonus
+    /// row is inverted, in which case the image is inverted along with its background.
+    fn draw_second_row(
+        &self,
+        image: &mut image::GrayImage,
+        length: u32,
+        width: u32,
+        secondary_width: u32,
+        invert: bool,
+    ) -> Result<()> {
+        if let Some(image_path) = &self.second_row_image {
+            let overlay = image::open(image_path)
+                .chain_err(|| RasterizeErrorKind::InvalidSecondRowImage(image_path.clone()))?
+                .to_luma8();
+            let overlay = self.second_row_rotation.apply(overlay);
+
+            let top_margin = 15 * self.scale();
+            let ratio = overlay.width() as f32 / overlay.height() as f32;
+
+            let mut new_width: u32 = length;
+            let mut new_height: u32 = (new_width as f32 / ratio) as u32;
+            if new_height > secondary_width - top_margin {
+                new_height = secondary_width - top_margin;
+                new_width = (new_height as f32 * ratio) as u32;
+            }
+            let mut resized =
+                image::imageops::resize(&overlay, new_width, new_height, self.overlay_filter);
+            if invert {
+                image::imageops::invert(&mut resized);
+            }
+            image::imageops::overlay(image, &resized, (length - new_width) / 2, width);
+        }
+        Ok(())
+    }
+
+    /// Crops `images` (the planes of one label, as drawn by `blank_image()`) along the tape to their content and the
+    /// margins chosen with `set_content_length_margins()`. Anything that isn't the background color of its area counts
+    /// as content.
+    fn fit_length_to_content(&self, images: &mut [&mut image::GrayImage], inverted: (bool, bool)) {
+        let (leading, trailing) = match self.content_length_margins {
+            Some(margins) if self.label.tape_size.1 == 0 => margins,
+            _ => return,
+        };
+        let (_, width, _) = self.dimensions();
+        let (main, second_row) = inverted;
+        let drawn = |image: &image::GrayImage, x: u32| {
+            (0..image.height()).any(|y| {
+                let inverted = if y < width { main } else { second_row };
+                image.get_pixel(x, y)[0] != if inverted { 0 } else { 255 }
+            })
+        };
+        let length = images.iter().map(|image| image.width()).min().unwrap_or(0);
+        let columns = || (0..length).filter(|&x| images.iter().any(|image| drawn(image, x)));
+        let (first, last) = match (columns().next(), columns().next_back()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+        let scale = self.scale();
+        let min_length = (MIN_CONTINUOUS_LENGTH * scale).min(length);
+        let mut start = first.saturating_sub(leading * scale);
+        let mut end = (last + 1 + trailing * scale).min(length);
+        if end - start < min_length {
+            end = (start + min_length).min(length);
+            start = end - min_length;
+        }
+        for image in images.iter_mut() {
+            let height = image.height();
+            **image = image::imageops::crop_imm(&**image, start, 0, end - start, height).to_image();
+        }
+    }
+
+    /// Squeezes a label drawn at `scale()` back down to the printed width across the tape. Its length is left alone
+    /// since high resolution labels are printed with extra raster lines along the tape.
+    fn finish_image(&self, image: image::GrayImage) -> image::GrayImage {
+        match self.resolution {
+            Resolution::Standard => image,
+            Resolution::High => image::imageops::resize(
+                &image,
+                image.width(),
+                image.height() / self.scale(),
+                FilterType::Triangle,
+            ),
+        }
+    }
+
+    /// Applies the dithering mode, returning the image to rasterize and the threshold to rasterize it with
+    fn black_and_white<'a>(&self, image: &'a image::GrayImage) -> (Cow<'a, image::GrayImage>, u8) {
+        if self.dithering == Dithering::None {
+            (Cow::Borrowed(image), self.threshold)
+        } else {
+            (
+                Cow::Owned(dither_to_1bit(image, self.dithering)),
+                DEFAULT_THRESHOLD,
+            )
+        }
+    }
+
+    /// Flips the image end to end if `set_mirror()` is enabled
+    fn mirrored<'a>(&self, image: &'a image::GrayImage) -> Cow<'a, image::GrayImage> {
+        if self.mirror {
+            Cow::Owned(image::imageops::flip_horizontal(image))
+        } else {
+            Cow::Borrowed(image)
+        }
+    }
+
+    fn to_raster_lines(&self, image: &image::GrayImage) -> Result<RasterLines> {
+        let image = self.mirrored(image);
+        let (image, threshold) = self.black_and_white(&image);
+        if let Some(path) = &self.debug_output {
+            image.save(path)?;
+        }
+        let mut lines = image_to_raster_lines_with_threshold(
+            &image,
+            self.label.raster_line_length(),
+            threshold,
+        )?;
+        lines.set_resolution(self.resolution);
+        lines.set_label(self.label);
+        Ok(lines)
+    }
+
+    fn to_two_color_raster_lines(
+        &self,
+        black: &image::GrayImage,
+        red: &image::GrayImage,
+    ) -> Result<TwoColorRasterLines> {
+        let (black, red) = (self.mirrored(black), self.mirrored(red));
+        let (black, black_threshold) = self.black_and_white(&black);
+        let (red, red_threshold) = self.black_and_white(&red);
+        if let Some(path) = &self.debug_output {
+            let preview = image::RgbImage::from_fn(black.width(), black.height(), |x, y| {
+                if black.get_pixel(x, y)[0] <= black_threshold {
+                    image::Rgb([0, 0, 0])
+                } else if red.get_pixel(x, y)[0] <= red_threshold {
+                    image::Rgb([255, 0, 0])
+                } else {
+                    image::Rgb([255, 255, 255])
+                }
+            });
+            preview.save(path)?;
+        }
+        let line_length = self.label.raster_line_length();
+        let mut lines = TwoColorRasterLines {
+            black: image_to_raster_lines_with_threshold(&black, line_length, black_threshold)?,
+            red: image_to_raster_lines_with_threshold(&red, line_length, red_threshold)?,
+        };
+        for plane in [&mut lines.black, &mut lines.red] {
+            plane.set_resolution(self.resolution);
+            plane.set_label(self.label);
+        }
+        Ok(lines)
+    }
+
+    /// Returns the font size in dots that a single line of `text` starting at `max_font_size` ends up at once it's
+    /// fitted to the label, without drawing anything. Measuring every label of a batch and printing them all with
+    /// `FontSizing::Fixed` at the smallest size makes the batch look consistent.
+    ///
+    /// Fails under the same conditions as `try_rasterize()`.
+    pub fn measure(&self, text: &str, max_font_size: f32) -> Result<f32> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let (length, width, _) = self.dimensions();
+        let line_length = match self.orientation {
+            TextOrientation::Horizontal => length,
+            TextOrientation::Vertical => width,
+        };
+        let scale = self.scale() as f32;
+        let resized = self.fit_text(style, text, line_length, max_font_size * scale)?;
+        Ok(resized.font_size / scale)
+    }
+
+    /// Returns the size in dots that a single line of `text` takes up at exactly `font_size`, e.g. to lay out
+    /// several pieces of text by hand. Nothing is drawn and the text isn't shrunk to fit the label. The length (`x`)
+    /// runs from the first to the last inked pixel, the height (`y`) from the fonts' ascent to their descent.
+    ///
+    /// Fails if the font can't be read or parsed.
+    pub fn measure_text(&self, text: &str, font_size: f32) -> Result<XY<u32>> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let scale = self.scale();
+        let size = ResizedText::at_size(style, text, font_size * scale as f32).rendered_size;
+        Ok(XY {
+            x: size.x.div_ceil(scale),
+            y: size.y.div_ceil(scale),
+        })
+    }
+
+    /// Transforms text into a raster image ready to send to the `ThermalPrinter`. Typically, the text will appear
+    /// as black on a white background. Enable the `invert` flag to print white text on a black background. Note that
+    /// since the label is white, a faint border of white will still surround the label in areas that the printer
+    /// cannot print the black background.
+    ///
+    /// Panics if the font or second row image can't be loaded or the text can't be made to fit. Use
+    /// `try_rasterize()` to handle these errors instead.
+    pub fn rasterize(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+    ) -> RasterLines {
+        self.try_rasterize(text, secondary_text, font_scale, invert)
+            .expect("Failed to rasterize text")
+    }
+
+    /// Same as `rasterize()` but returns an error instead of panicking if the font can't be read or parsed, the
+    /// second row image can't be opened or the text doesn't fit on the label at the minimum font size (see
+    /// `set_overflow()`).
+    pub fn try_rasterize(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+    ) -> Result<RasterLines> {
+        let image = self.render_image(text, secondary_text, font_scale, invert)?;
+        self.to_raster_lines(&image)
+    }
+
+    /// Same as `try_rasterize()` but also returns the size in dots the primary text was drawn at, as `measure()`
+    /// reports it
+    pub(crate) fn try_rasterize_measured(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+    ) -> Result<(RasterLines, f32)> {
+        let (image, font_size) =
+            self.render_measured_image(text, secondary_text, font_scale, invert)?;
+        Ok((self.to_raster_lines(&image)?, font_size))
+    }
+
+    /// Composes the same label image as `try_rasterize()` without converting it to raster lines, e.g. to preview
+    /// or save a label before printing it.
+    pub fn render_image(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+    ) -> Result<image::GrayImage> {
+        self.render_measured_image(text, secondary_text, font_scale, invert)
+            .map(|(image, _)| image)
+    }
+
+    /// `render_image()` along with the size the primary text was drawn at (see `try_rasterize_measured()`)
+    fn render_measured_image(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+    ) -> Result<(image::GrayImage, f32)> {
+        let (mut image, length, width, secondary_width, font_size) =
+            self.render_text(text, secondary_text, font_scale, invert, (true, true))?;
+        let inverted = self.inverted_areas(invert);
+        self.draw_images(&mut image, length, width)?;
+        self.draw_second_row(&mut image, length, width, secondary_width, inverted.1)?;
+        self.fit_length_to_content(&mut [&mut image], inverted);
+        Ok((self.finish_image(image), font_size / self.scale() as f32))
+    }
+
+    /// Lays out the primary and secondary text and draws the ones selected by `draw`. Returns the image along with
+    /// its `(length, width, secondary_width)` and the size in pixels the primary text was laid out at.
+    fn render_text(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        invert: bool,
+        draw: (bool, bool),
+    ) -> Result<(image::GrayImage, u32, u32, u32, f32)> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let (length, width, secondary_width) = self.dimensions();
+        let mut image = self.blank_image(length, width, secondary_width, invert);
+        let invert = self.inverted_areas(invert).0;
+        let (draw_primary, draw_secondary) = draw;
+        let mut font_size = 0.0;
+
+        self.draw_text_area(&mut image, length, width, invert, |image, length, width| {
+            let top = self.margins().top as i32;
+            let bottom = width as i32 - self.margins().bottom as i32;
+            let font_scale = font_scale * self.scale() as f32;
+            match secondary_text {
+                Some(secondary_text) => {
+                    let mut primary = self.fit_text(style, text, length, 90.0 * font_scale)?;
+                    let mut secondary =
+                        self.fit_text(style, secondary_text, length, 35.0 * font_scale)?;
+                    font_size = primary.font_size;
+
+                    // The secondary text sits on the bottom margin and the primary text is centered above it
+                    let secondary_offset = XY {
+                        x: self.aligned_x(length, &mut secondary),
+                        y: bottom - secondary.rendered_size.y as i32,
+                    };
+                    let primary_offset = XY {
+                        x: self.aligned_x(length, &mut primary),
+                        y: Self::centered_y(top, secondary_offset.y, primary.rendered_size.y),
+                    };
+                    if draw_primary {
+                        self.draw_text_row(
+                            image,
+                            &primary,
+                            primary_offset,
+                            label_area(length, width),
+                            invert,
+                        );
+                    }
+                    if draw_secondary {
+                        self.draw_text_row(
+                            image,
+                            &secondary,
+                            secondary_offset,
+                            label_area(length, width),
+                            invert,
+                        );
+                    }
+                }
+                None => {
+                    let mut primary = self.fit_text(style, text, length, 125.0 * font_scale)?;
+                    font_size = primary.font_size;
+
+                    let offset = XY {
+                        x: self.aligned_x(length, &mut primary),
+                        y: Self::centered_y(top, bottom, primary.rendered_size.y),
+                    };
+
+                    if draw_primary {
+                        self.draw_text_row(
+                            image,
+                            &primary,
+                            offset,
+                            label_area(length, width),
+                            invert,
+                        );
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        Ok((image, length, width, secondary_width, font_size))
+    }
+
+    /// Same as `rasterize()` but prints the primary and secondary text in the chosen colors on two-color media.
+    /// The second row image (if any) is printed in black. Print the result with `ThermalPrinter::print_two_color()`.
+    ///
+    /// Panics under the same conditions as `rasterize()`. Use `try_rasterize_two_color()` to handle errors instead.
+    pub fn rasterize_two_color(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        colors: (Color, Color),
+    ) -> TwoColorRasterLines {
+        self.try_rasterize_two_color(text, secondary_text, font_scale, colors)
+            .expect("Failed to rasterize text")
+    }
+
+    /// Same as `rasterize_two_color()` but returns an error instead of panicking.
+    pub fn try_rasterize_two_color(
+        &self,
+        text: &str,
+        secondary_text: Option<&str>,
+        font_scale: f32,
+        colors: (Color, Color),
+    ) -> Result<TwoColorRasterLines> {
+        let (primary_color, secondary_color) = colors;
+        let in_color = |color| (primary_color == color, secondary_color == color);
+        let (mut black, length, width, secondary_width, _) = self.render_text(
+            text,
+            secondary_text,
+            font_scale,
+            false,
+            in_color(Color::Black),
+        )?;
+        self.draw_images(&mut black, length, width)?;
+        self.draw_second_row(&mut black, length, width, secondary_width, false)?;
+        let (mut red, ..) = self.render_text(
+            text,
+            secondary_text,
+            font_scale,
+            false,
+            in_color(Color::Red),
+        )?;
+        self.fit_length_to_content(&mut [&mut black, &mut red], (false, false));
+        self.to_two_color_raster_lines(&self.finish_image(black), &self.finish_image(red))
+    }
+
+    /// Like `rasterize()` but wraps long text onto as many lines as needed instead of shrinking it onto one.
+    ///
+    /// Words are packed greedily into lines that fit the length of the label and the resulting block of lines is
+    /// centered across the width of the tape. The font starts at `max_font_size` and is reduced until every word
+    /// fits on a line and the stacked lines fit the tape. `line_spacing` scales the font's natural line advance
+    /// (ascent, descent, and line gap), so `1.0` spaces lines as the font designer intended.
+    ///
+    /// Panics under the same conditions as `rasterize()`. Use `try_rasterize_wrapped()` to handle errors instead.
+    pub fn rasterize_wrapped(
+        &self,
+        text: &str,
+        max_font_size: f32,
+        line_spacing: f32,
+        invert: bool,
+    ) -> RasterLines {
+        self.try_rasterize_wrapped(text, max_font_size, line_spacing, invert)
+            .expect("Failed to rasterize text")
+    }
+
+    /// Same as `rasterize_wrapped()` but returns an error instead of panicking.
+    pub fn try_rasterize_wrapped(
+        &self,
+        text: &str,
+        max_font_size: f32,
+        line_spacing: f32,
+        invert: bool,
+    ) -> Result<RasterLines> {
+        let image = self.render_wrapped_image(text, max_font_size, line_spacing, invert)?;
+        self.to_raster_lines(&image)
+    }
+
+    /// Composes the same label image as `try_rasterize_wrapped()` without converting it to raster lines.
+    pub fn render_wrapped_image(
+        &self,
+        text: &str,
+        max_font_size: f32,
+        line_spacing: f32,
+        invert: bool,
+    ) -> Result<image::GrayImage> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let (length, width, secondary_width) = self.dimensions();
+        let mut image = self.blank_image(length, width, secondary_width, invert);
+        let (invert, invert_second_row) = self.inverted_areas(invert);
+
+        self.draw_text_area(&mut image, length, width, invert, |image, length, width| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let max_line_length = self.available_length(length);
+            let max_height = self.available_width(width);
+            let (mut font_size, min_font_size) =
+                self.font_sizes(max_font_size * self.scale() as f32);
+
+            // Scale the font size down until every line fits length-wise and the lines all fit width-wise
+            let (mut lines, line_advance, total_height) = loop {
+                // Once at the minimum size, ellipsize whatever still doesn't fit if allowed to
+                let truncate = font_size < min_font_size;
+                if truncate && self.overflow == TextOverflow::Error {
+                    bail!(RasterizeErrorKind::TextDoesNotFit(text.to_string()));
+                }
+                let size = if truncate { min_font_size } else { font_size };
+                let v_metrics = combined_v_metrics(style.fonts, text, Scale::uniform(size));
+                let line_height = v_metrics.ascent - v_metrics.descent;
+                let line_advance = (line_height + v_metrics.line_gap) * line_spacing;
+                let total_height = |line_count: usize| {
+                    if line_count == 0 {
+                        0.0
+                    } else {
+                        line_height + line_advance * (line_count - 1) as f32
+                    }
+                };
+                match wrap_words(style, &words, max_line_length, size, truncate) {
+                    Some(lines) if total_height(lines.len()).ceil() as u32 <= max_height => {
+                        let height = total_height(lines.len());
+                        break (lines, line_advance, height);
+                    }
+                    Some(mut lines) if truncate => {
+                        // Keep as many lines as fit and mark the last one as cut short
+                        let mut fitting = 0;
+                        while fitting < lines.len()
+                            && total_height(fitting + 1).ceil() as u32 <= max_height
+                        {
+                            fitting += 1;
+                        }
+                        if fitting == 0 {
+                            bail!(RasterizeErrorKind::TextDoesNotFit(text.to_string()));
+                        }
+                        lines.truncate(fitting);
+                        let last = lines.pop().unwrap();
+                        lines.push(ResizedText::ellipsized(
+                            style,
+                            &last.text,
+                            max_line_length,
+                            size,
+                        )?);
+                        break (lines, line_advance, total_height(fitting));
+                    }
+                    None if truncate => {
+                        bail!(RasterizeErrorKind::TextDoesNotFit(text.to_string()));
+                    }
+                    _ => {}
+                }
+                font_size -= 1.0;
+            };
+            if let Some(line) = lines.first() {
+                self.check_readable(text, line.font_size);
+            }
+
+            let top = self.margins().top as f32 + (max_height as f32 - total_height) / 2.0;
+            let line_count = lines.len();
+            for (i, line) in lines.iter_mut().enumerate() {
+                let last_justified = self.alignment == TextAlign::Justify && i + 1 == line_count;
+                let offset = XY {
+                    x: if last_justified {
+                        self.centered_x(length, line)
+                    } else {
+                        self.aligned_x(length, line)
+                    },
+                    y: (top + line_advance * i as f32).round() as i32,
+                };
+                self.draw_text_row(image, line, offset, label_area(length, width), invert);
+            }
+            Ok(())
+        })?;
+
+        self.draw_images(&mut image, length, width)?;
+        self.draw_second_row(
+            &mut image,
+            length,
+            width,
+            secondary_width,
+            invert_second_row,
+        )?;
+        self.fit_length_to_content(&mut [&mut image], (invert, invert_second_row));
+        Ok(self.finish_image(image))
+    }
+
+    /// Lays out a shipping label: a QR code encoding `qr_data` in a square at the start of the label and `lines` of
+    /// text (e.g. an address) in a left aligned block filling the rest of it. Every line starts on a line of its own
+    /// and wraps onto more lines if it's too long. All lines share one font size, as large as lets the whole block
+    /// fit across the tape inside the margins.
+    ///
+    /// The QR code takes up at most half of the label length. This layout is always drawn along the tape and
+    /// doesn't use the rasterizer's alignment, orientation, QR code or images. Fails with `QrCodeDoesNotFit` or
+    /// `TextDoesNotFit` if there isn't room for the code or the text at the minimum font size.
+    pub fn render_shipping_label(&self, qr_data: &str, lines: &[&str]) -> Result<image::GrayImage> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let scale = self.scale();
+        let (length, width, secondary_width) = self.dimensions();
+        let mut image = self.blank_image(length, width, secondary_width, false);
+        let margins = self.user_margins();
+        let available_width = width.saturating_sub(margins.top + margins.bottom);
+        let available_length = length.saturating_sub(margins.left + margins.right);
+
+        // A square QR code on the leading edge, centered across the tape
+        let qr_code = QrModules::encode(qr_data, QrEcLevel::Medium)?;
+        let side = available_width.min(available_length / 2);
+        let module_px = side / scale / qr_code.total_size() * scale;
+        if module_px == 0 {
+            bail!(RasterizeErrorKind::QrCodeDoesNotFit);
+        }
+        let qr_side = module_px * qr_code.total_size();
+        let qr_y = margins.top + (available_width - qr_side) / 2;
+        image::imageops::overlay(&mut image, &qr_code.render(module_px), margins.left, qr_y);
+
+        // The text block takes up the rest of the length. The QR code's quiet zone keeps the two apart.
+        let text_x = margins.left + qr_side;
+        let text_length = length.saturating_sub(text_x + margins.right);
+        let all_text = lines.join("\n");
+        let (mut font_size, min_font_size) = self.font_sizes(available_width as f32);
+        let (wrapped, line_advance, total_height) = loop {
+            if font_size < min_font_size {
+                bail!(RasterizeErrorKind::TextDoesNotFit(all_text));
+            }
+            let v_metrics = combined_v_metrics(style.fonts, &all_text, Scale::uniform(font_size));
+            let line_height = v_metrics.ascent - v_metrics.descent;
+            let line_advance = line_height + v_metrics.line_gap;
+            let wrapped: Option<Vec<ResizedText>> = lines
+                .iter()
+                .map(|line| {
+                    let words: Vec<&str> = line.split_whitespace().collect();
+                    wrap_words(style, &words, text_length, font_size, false)
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|lines| lines.into_iter().flatten().collect());
+            if let Some(wrapped) = wrapped {
+                let total_height = match wrapped.len() {
+                    0 => 0.0,
+                    count => line_height + line_advance * (count - 1) as f32,
+                };
+                if total_height.ceil() as u32 <= available_width {
+                    break (wrapped, line_advance, total_height);
+                }
+            }
+            font_size -= 1.0;
+        };
+        self.check_readable(&all_text, font_size);
+
+        let top = margins.top as f32 + (available_width as f32 - total_height) / 2.0;
+        let clip = Rect {
+            min: XY {
+                x: text_x as i32,
+                y: margins.top as i32,
+            },
+            max: XY {
+                x: (length - margins.right) as i32,
+                y: (width - margins.bottom) as i32,
+            },
+        };
+        for (i, line) in wrapped.iter().enumerate() {
+            let offset = XY {
+                x: text_x as i32,
+                y: (top + line_advance * i as f32).round() as i32,
+            };
+            self.draw_text_row(&mut image, line, offset, clip, false);
+        }
+        self.fit_length_to_content(&mut [&mut image], (false, false));
+        Ok(self.finish_image(image))
+    }
+
+    /// Same as `render_shipping_label()` but converts the label into raster lines ready to print
+    pub fn try_rasterize_shipping_label(
+        &self,
+        qr_data: &str,
+        lines: &[&str],
+    ) -> Result<RasterLines> {
+        let image = self.render_shipping_label(qr_data, lines)?;
+        self.to_raster_lines(&image)
+    }
+
+    /// Stacks any number of rows of text across the width of the label.
+    ///
+    /// The width inside the margins is divided into one band per row in proportion to each row's `weight`. Every
+    /// row is sized independently: it starts at its own `max_font_size` and is shrunk until it fits both the length
+    /// of the label and the height of its band, so rows never overlap. Each row is centered in its band.
+    ///
+    /// Panics under the same conditions as `rasterize()`. Use `try_rasterize_rows()` to handle errors instead.
+    pub fn rasterize_rows(&self, rows: &[TextRow], invert: bool) -> RasterLines {
+        self.try_rasterize_rows(rows, invert)
+            .expect("Failed to rasterize text")
+    }
+
+    /// Same as `rasterize_rows()` but returns an error instead of panicking.
+    pub fn try_rasterize_rows(&self, rows: &[TextRow], invert: bool) -> Result<RasterLines> {
+        let image = self.render_rows_image(rows, invert)?;
+        self.to_raster_lines(&image)
+    }
+
+    /// Composes the same label image as `try_rasterize_rows()` without converting it to raster lines.
+    pub fn render_rows_image(&self, rows: &[TextRow], invert: bool) -> Result<image::GrayImage> {
+        let style = self.text_style(self.try_load_fonts()?);
+        let (length, width, secondary_width) = self.dimensions();
+        let mut image = self.blank_image(length, width, secondary_width, invert);
+        let (invert, invert_second_row) = self.inverted_areas(invert);
+
+        self.draw_text_area(&mut image, length, width, invert, |image, length, width| {
+            let total_weight: f32 = rows.iter().map(|row| row.weight.max(0.0)).sum();
+            if total_weight <= 0.0 {
+                return Ok(());
+            }
+            let available = self.available_width(width) as f32;
+
+            let mut band_top = self.margins().top as f32;
+            for row in rows {
+                // Height of a line of the row's text per unit of font size
+                let unit_height = {
+                    let v_metrics = combined_v_metrics(style.fonts, row.text, Scale::uniform(1.0));
+                    v_metrics.ascent - v_metrics.descent
+                };
+                let band_height = available * row.weight.max(0.0) / total_weight;
+                let max_font_size = (row.max_font_size * self.scale() as f32)
+                    .min((band_height / unit_height).floor());
+                let mut resized = self.fit_text(style, row.text, length, max_font_size)?;
+                let offset = XY {
+                    x: self.aligned_x(length, &mut resized),
+                    y: Self::centered_y(
+                        band_top as i32,
+                        (band_top + band_height) as i32,
+                        resized.rendered_size.y,
+                    ),
+                };
+                // Each row stays inside its own band
+                let band = Rect {
+                    min: XY {
+                        x: 0,
+                        y: band_top as i32,
+                    },
+                    max: XY {
+                        x: length as i32,
+                        y: (band_top + band_height).ceil() as i32,
+                    },
+                };
+                let light_on_dark = self.draw_text_row(image, &resized, offset, band, invert);
+                draw_decorations(
+                    image,
+                    &resized,
+                    &style.fonts[0],
+                    offset,
+                    row.decoration,
+                    light_on_dark,
+                );
+                band_top += band_height;
+            }
+            Ok(())
+        })?;
+
+        self.draw_images(&mut image, length, width)?;
+        self.draw_second_row(
+            &mut image,
+            length,
+            width,
+            secondary_width,
+            invert_second_row,
+        )?;
+        self.fit_length_to_content(&mut [&mut image], (invert, invert_second_row));
+        Ok(self.finish_image(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::constants::label_data;
+
+    const TEST_FONT: &[u8] = include_bytes!("../test-fonts/DejaVuSansMono.ttf");
+
+    /// Builds a minimal TrueType font in which every one of `chars` is a filled square, for testing fonts that only
+    /// cover some characters
+    fn box_font(chars: &[char]) -> Vec<u8> {
+        fn be16(data: &mut Vec<u8>, values: &[u16]) {
+            for value in values {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        let glyph_count = chars.len() as u16 + 1;
+        // Glyph 0 (the missing glyph) is empty and the rest are the same square
+        let mut glyf = Vec::new();
+        be16(&mut glyf, &[1, 100, 0, 700, 700, 3, 0]);
+        glyf.extend_from_slice(&[1; 4]);
+        be16(&mut glyf, &[100, 0, 600, 0, 0, 700, 0, (-700i16) as u16]);
+        let glyph_length = glyf.len() as u16;
+        glyf = glyf.repeat(chars.len());
+        let mut loca = vec![0, 0];
+        for i in 0..glyph_count {
+            be16(&mut loca, &[i * glyph_length / 2]);
+        }
+        let mut hmtx = Vec::new();
+        for _ in 0..glyph_count {
+            be16(&mut hmtx, &[800, 100]);
+        }
+        // A format 4 cmap with one segment per character and the closing 0xFFFF segment
+        let segments: Vec<(u16, u16)> = chars
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| (c as u16, (i as u16 + 1).wrapping_sub(c as u16)))
+            .chain(std::iter::once((0xFFFF, 1)))
+            .collect();
+        let seg_count = segments.len() as u16;
+        let mut cmap = Vec::new();
+        be16(&mut cmap, &[0, 1, 3, 1, 0, 12, 4, 16 + 8 * seg_count, 0]);
+        be16(&mut cmap, &[seg_count * 2, 2, 0, 0]);
+        be16(&mut cmap, &segments.iter().map(|s| s.0).collect::<Vec<_>>());
+        be16(&mut cmap, &[0]);
+        be16(&mut cmap, &segments.iter().map(|s| s.0).collect::<Vec<_>>());
+        be16(&mut cmap, &segments.iter().map(|s| s.1).collect::<Vec<_>>());
+        be16(&mut cmap, &vec![0; segments.len()]);
+
+        let mut head = Vec::new();
+        be16(&mut head, &[1, 0, 1, 0, 0, 0, 0x5F0F, 0x3CF5, 0, 1000]);
+        head.extend_from_slice(&[0; 16]);
+        be16(&mut head, &[100, 0, 700, 700, 0, 8, 2, 0, 0]);
+        let mut hhea = Vec::new();
+        be16(
+            &mut hhea,
+            &[1, 0, 800, (-200i16) as u16, 0, 800, 100, 100, 700, 1],
+        );
+        be16(&mut hhea, &[0, 0, 0, 0, 0, 0, 0, glyph_count]);
+        let mut maxp = Vec::new();
+        be16(&mut maxp, &[0, 0x5000, glyph_count]);
+
+        let tables: [(&[u8; 4], Vec<u8>); 7] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut font = Vec::new();
+        be16(&mut font, &[1, 0, tables.len() as u16, 0, 0, 0]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend_from_slice(*tag);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            font.extend_from_slice(data);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        font
+    }
+
+    #[test]
+    fn missing_characters_come_from_fallback_fonts() {
+        let fonts = [
+            Font::try_from_vec(box_font(&['A'])).unwrap(),
+            Font::try_from_bytes(TEST_FONT).unwrap(),
+        ];
+        let glyphs = layout_glyphs(TextStyle::new(&fonts), "AB", Scale::uniform(20.0), 16.0);
+        assert_eq!(glyphs[0].id().0, 1);
+        assert_eq!(glyphs[1].id(), fonts[1].glyph('B').id());
+        // The box is 700 units wide with an advance of 800, so B starts 16 dots along at 20 dots per 1000 units
+        assert_eq!(glyphs[1].position().x, 16.0);
+
+        // Characters that no font has use the main font's missing glyph
+        let glyphs = layout_glyphs(
+            TextStyle::new(&fonts[..1]),
+            "AB",
+            Scale::uniform(20.0),
+            16.0,
+        );
+        assert_eq!(glyphs[1].id().0, 0);
+
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), box_font(&['A']));
+        rasterizer.set_fallback_fonts(vec![PathBuf::from("./does-not-exist.ttf")]);
+        assert!(rasterizer.try_rasterize("AB", None, 1.0, false).is_err());
+    }
+
+    #[test]
+    fn mixed_fonts_share_a_baseline() {
+        let path = std::env::temp_dir().join("brother-ql-rs-cjk-boxes.ttf");
+        fs::write(&path, box_font(&['世', '界'])).unwrap();
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_fallback_fonts(vec![path]);
+        let fonts = rasterizer.try_load_fonts().unwrap();
+
+        let text = ResizedText::at_size(TextStyle::new(fonts), "Hello 世界", 50.0);
+        // Every character has a real glyph drawn on the same baseline
+        assert!(text.glyphs.iter().all(|glyph| glyph.id().0 != 0));
+        assert!(text
+            .glyphs
+            .iter()
+            .all(|glyph| glyph.position().y == text.glyphs[0].position().y));
+        let boxes: Vec<_> = text.glyphs[6..]
+            .iter()
+            .map(|glyph| glyph.pixel_bounding_box().unwrap())
+            .collect();
+        // 600 of 1000 units wide at 50 dots per 1000 units
+        assert!((30..=31).contains(&boxes[0].width()));
+        assert!(boxes[0].max.x <= boxes[1].min.x);
+        // The line is tall enough for both fonts
+        let latin = ResizedText::at_size(TextStyle::new(&fonts[..1]), "Hello", 50.0);
+        assert!(text.rendered_size.y >= latin.rendered_size.y);
+        assert!(text.glyphs.iter().all(|glyph| {
+            let bounds = glyph.pixel_bounding_box().unwrap_or_default();
+            bounds.max.y <= text.rendered_size.y as i32
+        }));
+
+        let image = rasterizer
+            .render_image("Hello 世界", None, 1.0, false)
+            .unwrap();
+        let inked_columns = (0..image.width())
+            .filter(|&x| (0..image.height()).any(|y| image.get_pixel(x, y)[0] == 0))
+            .count();
+        assert!(inked_columns > 0);
+    }
+
+    #[test]
+    fn spacing_widens_text() {
+        let fonts = [Font::try_from_bytes(TEST_FONT).unwrap()];
+        let plain = ResizedText::at_size(TextStyle::new(&fonts), "ab cd", 40.0);
+        let style = |letter_spacing, word_spacing| TextStyle {
+            letter_spacing,
+            word_spacing,
+            ..TextStyle::new(&fonts)
+        };
+        // Four gaps between the five characters
+        let tracked = ResizedText::at_size(style(3.0, 0.0), "ab cd", 40.0);
+        assert_eq!(tracked.rendered_size.x, plain.rendered_size.x + 12);
+        let tight = ResizedText::at_size(style(-2.0, 0.0), "ab cd", 40.0);
+        assert_eq!(tight.rendered_size.x, plain.rendered_size.x - 8);
+        let words = ResizedText::at_size(style(0.0, 10.0), "ab cd", 40.0);
+        assert_eq!(words.rendered_size.x, plain.rendered_size.x + 10);
+
+        // Text is shrunk further to make room for the spacing
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        let fitted = |rasterizer: &TextRasterizer| {
+            let style = rasterizer.text_style(rasterizer.try_load_fonts().unwrap());
+            let text = rasterizer
+                .fit_text(style, "Hello world", 400, 200.0)
+                .unwrap();
+            assert!(text.rendered_size.x < 400);
+            text.glyphs[0].scale().y
+        };
+        let unspaced = fitted(&rasterizer);
+        rasterizer.set_letter_spacing(10.0);
+        assert!(fitted(&rasterizer) < unspaced);
+    }
+
+    #[test]
+    fn fixed_font_sizes_are_never_shrunk() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        let fit = |rasterizer: &TextRasterizer, text: &str| {
+            let style = rasterizer.text_style(rasterizer.try_load_fonts().unwrap());
+            rasterizer
+                .fit_text(style, text, 750, 200.0)
+                .map(|text| (text.text, text.glyphs[0].scale().y))
+        };
+        rasterizer.set_font_sizing(FontSizing::AutoShrink { max: 20.5 });
+        assert_eq!(fit(&rasterizer, "Hello").unwrap().1, 21.0);
+
+        rasterizer.set_font_sizing(FontSizing::Fixed(30.5));
+        assert_eq!(fit(&rasterizer, "Hello").unwrap().1, 30.5);
+        let long = "Hello ".repeat(10);
+        assert!(fit(&rasterizer, &long).is_err());
+        rasterizer.set_overflow(TextOverflow::Ellipsis);
+        let (text, size) = fit(&rasterizer, &long).unwrap();
+        assert_eq!(size, 30.5);
+        assert!(text.ends_with('…'));
+
+        // Wrapped text keeps the size and wraps onto more lines instead
+        let image = rasterizer
+            .render_wrapped_image("Hello there world", 100.0, 1.0, false)
+            .unwrap();
+        assert!(black_dots(&image) > 0);
+    }
+
+    #[test]
+    fn wrapping_words() {
+        let rasterizer =
+            TextRasterizer::from_font_bytes(label_data(62, None).unwrap(), TEST_FONT.to_vec());
+        let style = rasterizer.text_style(rasterizer.try_load_fonts().unwrap());
+        let width = |text| ResizedText::at_size(style, text, 40.0).rendered_size.x;
+        let texts = |lines: Vec<ResizedText>| -> Vec<String> {
+            lines.into_iter().map(|line| line.text).collect()
+        };
+        let words = ["ab", "ab", "ab"];
+        let lines = wrap_words(style, &words, width("ab ab") + 1, 40.0, false).unwrap();
+        assert_eq!(texts(lines), ["ab ab", "ab"]);
+        // A line has to be shorter than the length it's wrapped to
+        let lines = wrap_words(style, &words, width("ab ab"), 40.0, false).unwrap();
+        assert_eq!(texts(lines), ["ab", "ab", "ab"]);
+        let lines = wrap_words(style, &words, 10_000, 40.0, false).unwrap();
+        assert_eq!(texts(lines), ["ab ab ab"]);
+        // Words too long for a line of their own can't be wrapped
+        assert!(wrap_words(style, &words, width("ab"), 40.0, false).is_none());
+    }
+
+    #[test]
+    fn wrapped_text() {
+        let rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        // The rows of ink across the tape, one for each line of text
+        let bands = |image: &image::GrayImage| -> Vec<(u32, u32)> {
+            let inked: Vec<u32> = (0..image.height())
+                .filter(|&y| (0..image.width()).any(|x| image.get_pixel(x, y)[0] == 0))
+                .collect();
+            let mut bands: Vec<(u32, u32)> = Vec::new();
+            for y in inked {
+                match bands.last_mut() {
+                    Some(band) if band.1 + 1 == y => band.1 = y,
+                    _ => bands.push((y, y)),
+                }
+            }
+            bands
+        };
+        let render = |text: &str, line_spacing| {
+            rasterizer
+                .render_wrapped_image(text, 40.0, line_spacing, false)
+                .unwrap()
+        };
+        assert_eq!(bands(&render("word", 1.0)).len(), 1);
+        let text = "word ".repeat(15);
+        let single = bands(&render(&text, 1.0));
+        assert_eq!(single.len(), 3);
+        // Lines are spaced further apart but keep their height
+        let double = bands(&render(&text, 2.0));
+        assert_eq!(double.len(), 3);
+        let spread = |bands: &[(u32, u32)]| bands[2].0 - bands[0].0;
+        assert!(spread(&double) > spread(&single) * 19 / 10);
+        assert_eq!(double[0].1 - double[0].0, single[0].1 - single[0].0);
+
+        assert_eq!(
+            rasterizer.rasterize_wrapped(&text, 40.0, 1.0, false),
+            rasterizer.to_raster_lines(&render(&text, 1.0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn measuring_text() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        let short = rasterizer.measure("Hi", 100.0).unwrap();
+        let long = rasterizer
+            .measure("A much longer line of text", 100.0)
+            .unwrap();
+        assert_eq!(short, 100.0);
+        assert!(long < short);
+        // Measurements don't depend on the resolution
+        rasterizer.set_resolution(Resolution::High);
+        assert_eq!(rasterizer.measure("Hi", 100.0).unwrap(), 100.0);
+        assert!(
+            (rasterizer
+                .measure("A much longer line of text", 100.0)
+                .unwrap()
+                - long)
+                .abs()
+                <= 1.0
+        );
+
+        rasterizer.set_font_sizing(FontSizing::Fixed(long));
+        assert_eq!(rasterizer.measure("Hi", 100.0).unwrap(), long);
+    }
+
+    #[test]
+    fn measuring_text_at_a_size() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        assert_eq!(rasterizer.measure_text("", 50.0).unwrap().x, 0);
+        let size = rasterizer.measure_text("Hello", 50.0).unwrap();
+        let double = rasterizer.measure_text("Hello", 100.0).unwrap();
+        assert!(size.x > 100 && size.y >= 50);
+        assert!((double.x as i32 - 2 * size.x as i32).abs() <= 2);
+        assert!((double.y as i32 - 2 * size.y as i32).abs() <= 2);
+
+        // Text too long for the label isn't shrunk
+        let long = rasterizer
+            .measure_text("A much longer line of text than fits", 100.0)
+            .unwrap()
+            .x;
+        assert!(long > rasterizer.dimensions().0);
+
+        rasterizer.set_resolution(Resolution::High);
+        let high = rasterizer.measure_text("Hello", 50.0).unwrap();
+        assert!((high.x as i32 - size.x as i32).abs() <= 1);
+        assert!((high.y as i32 - size.y as i32).abs() <= 1);
+    }
+
+    #[test]
+    fn raster_lines_know_their_dimensions() {
+        let label = label_data(29, Some(90)).unwrap();
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let image = rasterizer.render_image("Hi", None, 1.0, false).unwrap();
+        let lines = rasterizer.try_rasterize("Hi", None, 1.0, false).unwrap();
+        assert_eq!(lines.dimensions(), image.dimensions());
+        assert_eq!(lines.dimensions(), (991, label.raster_width()));
+
+        rasterizer.set_resolution(Resolution::High);
+        let lines = rasterizer.try_rasterize("Hi", None, 1.0, false).unwrap();
+        assert_eq!(lines.dimensions(), (2 * 991, label.raster_width()));
+
+        let mut unlabeled = RasterLines::new(90);
+        unlabeled.push(&[0; 90]);
+        assert_eq!(unlabeled.dimensions(), (1, 720));
+    }
+
+    #[test]
+    fn decorations_are_drawn_along_the_text() {
+        let fonts = [Font::try_from_bytes(TEST_FONT).unwrap()];
+        let text = ResizedText::at_size(TextStyle::new(&fonts), "Sale", 45.0);
+        let offset = XY { x: 10, y: 5 };
+        let draw = |decoration, invert| {
+            let mut image =
+                image::GrayImage::from_pixel(200, 60, Luma([if invert { 0 } else { 255 }]));
+            draw_decorations(&mut image, &text, &fonts[0], offset, decoration, invert);
+            image
+        };
+        let underline = TextDecoration {
+            underline: true,
+            strikethrough: false,
+        };
+        let image = draw(underline, false);
+        // A 3 dot thick bar as long as the text
+        assert_eq!(black_dots(&image), 3 * text.rendered_size.x as usize);
+        let rows: Vec<u32> = (0..60)
+            .filter(|&y| image.get_pixel(100, y)[0] == 0)
+            .collect();
+        let baseline = (offset.y as f32 + text.glyphs[0].position().y).round() as u32;
+        assert_eq!(rows, vec![baseline + 3, baseline + 4, baseline + 5]);
+        // Glyphs laid out right to left are spanned the same way
+        let mut reversed = ResizedText::at_size(TextStyle::new(&fonts), "Sale", 45.0);
+        reversed.glyphs.reverse();
+        let mut image = image::GrayImage::from_pixel(200, 60, Luma([255]));
+        draw_decorations(&mut image, &reversed, &fonts[0], offset, underline, false);
+        assert_eq!(black_dots(&image), 3 * text.rendered_size.x as usize);
+
+        let strikethrough = TextDecoration {
+            underline: false,
+            strikethrough: true,
+        };
+        let top = (0..60)
+            .find(|&y| draw(strikethrough, false).get_pixel(100, y)[0] == 0)
+            .unwrap();
+        assert!(top < baseline && top > offset.y as u32);
+        // Inverted labels get white lines
+        let inverted = draw(strikethrough, true);
+        assert_eq!(inverted.get_pixel(100, top)[0], 255);
+        assert_eq!(black_dots(&draw(TextDecoration::default(), false)), 0);
+    }
+
+    #[test]
+    fn glyphs_outside_the_image_are_clipped() {
+        let font = Font::try_from_bytes(TEST_FONT).unwrap();
+        let glyphs: Vec<_> = font
+            .layout("jW", Scale::uniform(40.0), rusttype::point(0.0, 30.0))
+            .collect();
+        // Glyphs hanging off every edge of the image are drawn as far as they fit without panicking
+        for offset in [XY { x: -15, y: -10 }, XY { x: 10, y: 5 }] {
+            let mut image = image::GrayImage::from_pixel(20, 20, Luma([255]));
+            draw_glyphs(&mut image, &glyphs, offset, label_area(20, 20), false);
+            assert!(image.pixels().any(|pixel| pixel[0] < 255));
+        }
+
+        // Nothing is drawn outside the clipping rectangle, even inside the image
+        let mut image = image::GrayImage::from_pixel(60, 40, Luma([255]));
+        let clip = Rect {
+            min: XY { x: 10, y: 5 },
+            max: XY { x: 30, y: 25 },
+        };
+        draw_glyphs(&mut image, &glyphs, XY { x: 0, y: 0 }, clip, false);
+        let inked: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] < 255)
+            .collect();
+        assert!(!inked.is_empty());
+        assert!(inked
+            .iter()
+            .all(|&(x, y, _)| (10..30).contains(&x) && (5..25).contains(&y)));
+
+        // A whole label with text pushed past the start of the label
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(12, None).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_alignment(TextAlign::Left);
+        let fonts = rasterizer.try_load_fonts().unwrap();
+        let resized = rasterizer
+            .fit_text(TextStyle::new(fonts), "jjj", 750, 80.0)
+            .unwrap();
+        let (length, width, _) = rasterizer.dimensions();
+        let mut image = rasterizer.blank_image(length, width, 0, false);
+        let clip = label_area(length, width);
+        draw_glyphs(
+            &mut image,
+            &resized.glyphs,
+            XY { x: -25, y: -25 },
+            clip,
+            false,
+        );
+        assert!(black_dots(&image) > 0);
+    }
+
+    #[test]
+    fn proof_sheets() {
+        let labels = [
+            image::GrayImage::from_pixel(10, 5, Luma([0])),
+            image::GrayImage::from_pixel(20, 5, Luma([0])),
+            image::GrayImage::from_pixel(10, 8, Luma([0])),
+        ];
+        let sheet = proof_sheet(&labels, 2);
+        let separator = PROOF_SHEET_SEPARATOR;
+        assert_eq!(
+            sheet.dimensions(),
+            (2 * 20 + 3 * separator, 2 * 8 + 3 * separator)
+        );
+        // Each label sits in the top left of its cell, the rest of the cell is white
+        let black = |x: u32, y: u32| sheet.get_pixel(x, y)[0] == 0;
+        assert!(black(separator, separator) && black(separator + 9, separator + 4));
+        assert_eq!(sheet.get_pixel(separator + 10, separator)[0], 255);
+        assert_eq!(sheet.get_pixel(separator, separator + 5)[0], 255);
+        let second = 2 * separator + 20;
+        assert!(black(second, separator) && black(second + 19, separator + 4));
+        let third = 2 * separator + 8;
+        assert!(black(separator, third) && black(separator + 9, third + 7));
+        // The fourth cell is empty
+        assert_eq!(sheet.get_pixel(second, third)[0], 255);
+        assert_eq!(sheet.get_pixel(0, 0)[0], 0x80);
+        assert_eq!(sheet.get_pixel(separator + 20, separator)[0], 0x80);
+
+        assert_eq!(
+            proof_sheet(&labels, 0).dimensions(),
+            (20 + 2 * separator, 3 * 8 + 4 * separator)
+        );
+        assert_eq!(
+            proof_sheet(&labels, 10).dimensions().0,
+            3 * 20 + 4 * separator
+        );
+        assert_eq!(proof_sheet(&[], 2).dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn outlined_text() {
+        let label = label_data(62, None).unwrap();
+        let layout = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let style = layout.text_style(layout.try_load_fonts().unwrap());
+        let text = ResizedText::at_size(style, "Hi", 60.0);
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let offset = XY { x: 20, y: 20 };
+        let draw = |rasterizer: &TextRasterizer, background: u8| {
+            let mut image = image::GrayImage::from_pixel(200, 100, Luma([background]));
+            rasterizer.draw_text_row(&mut image, &text, offset, label_area(200, 100), false);
+            image
+        };
+        let plain = draw(&rasterizer, 255);
+        let (x0, y0, x1, y1) = ink_bounds(&plain);
+
+        // A black outline grows the text by its width on every side
+        rasterizer.set_outline(3, false);
+        let (outlined_x0, outlined_y0, outlined_x1, outlined_y1) =
+            ink_bounds(&draw(&rasterizer, 255));
+        assert_eq!(
+            (outlined_x0, outlined_y0, outlined_x1, outlined_y1),
+            (x0 - 3, y0 - 3, x1 + 3, y1 + 3)
+        );
+
+        // A white outline separates black text from a black background
+        rasterizer.set_outline(3, true);
+        let on_black = draw(&rasterizer, 0);
+        let white: Vec<_> = on_black
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] > DEFAULT_THRESHOLD)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!white.is_empty());
+        assert!(white
+            .iter()
+            .all(|&(x, y)| x + 3 >= x0 && x <= x1 + 3 && y + 3 >= y0 && y <= y1 + 3));
+        for (x, y, pixel) in plain.enumerate_pixels() {
+            if pixel[0] == 0 {
+                assert_eq!(on_black.get_pixel(x, y)[0], 0);
+            }
+        }
+
+        rasterizer.set_outline(0, true);
+        assert_eq!(draw(&rasterizer, 255), plain);
+    }
+
+    #[test]
+    fn mirroring_labels() {
+        let label = label_data(29, Some(90)).unwrap();
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        rasterizer.set_alignment(TextAlign::Left);
+        let image = rasterizer.render_image("Hi", None, 1.0, false).unwrap();
+        let lines = rasterizer.rasterize("Hi", None, 1.0, false);
+        rasterizer.set_mirror(true);
+        assert_eq!(
+            rasterizer.render_image("Hi", None, 1.0, false).unwrap(),
+            image
+        );
+        let mirrored = rasterizer.rasterize("Hi", None, 1.0, false);
+
+        // The label reads backwards along the tape, while every line keeps its dots across the tape in place
+        assert_eq!(mirrored.len(), lines.len());
+        let reversed: Vec<_> = lines.iter().collect::<Vec<_>>().into_iter().rev().collect();
+        assert!(mirrored.iter().eq(reversed));
+        assert_ne!(mirrored, lines);
+        let flipped = image::imageops::flip_horizontal(&image);
+        assert_eq!(
+            mirrored,
+            image_to_raster_lines(&flipped, label.raster_line_length())
+                .map(|mut flipped| {
+                    flipped.set_label(label);
+                    flipped
+                })
+                .unwrap()
+        );
+        // Left aligned text ends up at the end of the label
+        let first_inked = |lines: &RasterLines| {
+            lines
+                .iter()
+                .position(|line| line.iter().any(|&byte| byte != 0))
+        };
+        assert!(first_inked(&mirrored).unwrap() > lines.len() / 2);
+        assert!(first_inked(&lines).unwrap() < lines.len() / 2);
+    }
+
+    #[test]
+    fn highlighting_behind_text() {
+        let label = label_data(62, None).unwrap();
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let plain = rasterizer.render_image("Hi", None, 1.0, false).unwrap();
+        rasterizer.set_highlight_behind_text(true);
+        let highlighted = rasterizer.render_image("Hi", None, 1.0, false).unwrap();
+
+        // Only a box around the text is black and the text itself is white inside it
+        let (x0, y0, x1, y1) = ink_bounds(&highlighted);
+        let (text_x0, text_y0, text_x1, text_y1) = ink_bounds(&plain);
+        assert!(x0 < text_x0 && x1 > text_x1 && y0 <= text_y0 && y1 >= text_y1);
+        assert!(x0 > 0 && x1 < highlighted.width() - 1);
+        let black = highlighted
+            .pixels()
+            .filter(|pixel| pixel[0] <= DEFAULT_THRESHOLD)
+            .count() as u32;
+        assert!(black < highlighted.width() * highlighted.height() / 4);
+        for (x, y, pixel) in plain.enumerate_pixels() {
+            if pixel[0] == 0 {
+                assert_eq!(highlighted.get_pixel(x, y)[0], 255);
+            }
+        }
+        assert_eq!(highlighted.get_pixel(x0 + 1, (y0 + y1) / 2)[0], 0);
+    }
+
+    #[test]
+    fn justified_text_fills_the_line() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, Some(90)).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_margins(Margins {
+            top: 0,
+            bottom: 0,
+            left: 40,
+            right: 60,
+        });
+        rasterizer.set_alignment(TextAlign::Justify);
+        let image = rasterizer.render_image("A B  C", None, 0.5, false).unwrap();
+        let (x0, _, x1, _) = ink_bounds(&image);
+        assert!((40..50).contains(&x0), "{}", x0);
+        // The last glyph ends at the right margin, give or take its anti-aliased edge
+        assert!((991 - 60 - 3..991 - 60).contains(&x1), "{}", x1);
+
+        // Both gaps get the same share of the extra space, however many spaces they're made of
+        let gaps = |image: &image::GrayImage| -> Vec<i32> {
+            let inked: Vec<i32> = (0..image.width())
+                .filter(|&x| {
+                    (0..image.height()).any(|y| image.get_pixel(x, y)[0] <= DEFAULT_THRESHOLD)
+                })
+                .map(|x| x as i32)
+                .collect();
+            inked
+                .windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .filter(|&gap| gap > 1)
+                .collect()
+        };
+        rasterizer.set_alignment(TextAlign::Left);
+        let natural = gaps(&rasterizer.render_image("A B  C", None, 0.5, false).unwrap());
+        let justified = gaps(&image);
+        assert_eq!((natural.len(), justified.len()), (2, 2));
+        let extra = (justified[0] - natural[0], justified[1] - natural[1]);
+        assert!(
+            extra.0 > 100 && (extra.0 - extra.1).abs() <= 1,
+            "{:?}",
+            extra
+        );
+        rasterizer.set_alignment(TextAlign::Justify);
+
+        // A single word is centered instead
+        let justified = rasterizer.render_image("Hello", None, 0.5, false).unwrap();
+        rasterizer.set_alignment(TextAlign::Center);
+        let centered = rasterizer.render_image("Hello", None, 0.5, false).unwrap();
+        assert_eq!(justified, centered);
+    }
+
+    #[test]
+    fn labels_by_name() {
+        let font = PathBuf::from("./does-not-exist.ttf");
+        let rasterizer = TextRasterizer::new_for("DK-1201", font.clone()).unwrap();
+        assert_eq!(rasterizer.dimensions(), (991, 312, 0));
+        let rasterizer = TextRasterizer::new_for("62", font.clone()).unwrap();
+        assert_eq!(rasterizer.dimensions().1, 708);
+        match TextRasterizer::new_for("63mm", font) {
+            Err(RasterizeError(RasterizeErrorKind::UnknownLabel(name), _)) => {
+                assert_eq!(name, "63mm")
+            }
+            _ => panic!("Expected UnknownLabel"),
+        }
+    }
+
+    #[test]
+    fn missing_font_is_an_error() {
+        let label = label_data(29, None).unwrap();
+        let rasterizer = TextRasterizer::new(label, PathBuf::from("./does-not-exist.ttf"));
+        match rasterizer.try_rasterize("Hello", None, 1.0, false) {
+            Err(RasterizeError(RasterizeErrorKind::Io(_), _)) => {}
+            other => panic!(
+                "Expected an IO error, got {:?}",
+                other.map(|lines| lines.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn garbage_font_bytes_are_an_error() {
+        let label = label_data(29, None).unwrap();
+        let rasterizer = TextRasterizer::from_font_bytes(label, b"not a font".to_vec());
+        match rasterizer.try_rasterize("Hello", None, 1.0, false) {
+            Err(RasterizeError(RasterizeErrorKind::InvalidFont(_, 0), _)) => {}
+            other => panic!(
+                "Expected an invalid font error, got {:?}",
+                other.map(|lines| lines.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn right_to_left_text_is_reordered() {
+        assert_eq!(visual_order("Hello"), (Cow::Borrowed("Hello"), false));
+        assert_eq!(visual_order("שלום").0, "םולש");
+        assert!(visual_order("שלום").1);
+        // Left-to-right runs inside right-to-left text keep their own order
+        assert_eq!(visual_order("שלום ABC").0, "ABC םולש");
+    }
+
+    #[test]
+    fn missing_second_row_image_is_an_error() {
+        let label = label_data(12, None).unwrap();
+        let mut rasterizer = TextRasterizer::new(label, PathBuf::from("./does-not-exist.ttf"));
+        rasterizer.set_second_row_image(PathBuf::from("./does-not-exist.png"));
+        let (length, width, secondary_width) = rasterizer.dimensions();
+        let mut image = rasterizer.blank_image(length, width, secondary_width, false);
+        match rasterizer.draw_second_row(&mut image, length, width, secondary_width, false) {
+            Err(RasterizeError(RasterizeErrorKind::InvalidSecondRowImage(path), _)) => {
+                assert_eq!(path, PathBuf::from("./does-not-exist.png"))
+            }
+            other => panic!(
+                "Expected an invalid second row image error, got {:?}",
+                other
+            ),
+        }
+    }
+
+    fn black_dots(image: &image::GrayImage) -> usize {
+        image.pixels().filter(|pixel| pixel[0] == 0).count()
+    }
+
+    /// The smallest `(x, y, x, y)` box around the black pixels of `image`
+    fn ink_bounds(image: &image::GrayImage) -> (u32, u32, u32, u32) {
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] <= DEFAULT_THRESHOLD)
+            .fold((u32::MAX, u32::MAX, 0, 0), |(x0, y0, x1, y1), (x, y, _)| {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            })
+    }
+
+    /// `font` with the ascent and descent of its `hhea` table zeroed and its `OS/2` table hidden, so it reports no
+    /// vertical metrics at all
+    fn without_v_metrics(font: &[u8]) -> Vec<u8> {
+        let mut font = font.to_vec();
+        let table_count = u16::from_be_bytes([font[4], font[5]]) as usize;
+        for record in (0..table_count).map(|table| 12 + 16 * table) {
+            let offset =
+                u32::from_be_bytes(<[u8; 4]>::try_from(&font[record + 8..record + 12]).unwrap());
+            match &font[record..record + 4] {
+                b"hhea" => font[offset as usize + 4..offset as usize + 8].fill(0),
+                b"OS/2" => font[record..record + 4].copy_from_slice(b"XS/2"),
+                _ => {}
+            }
+        }
+        font
+    }
+
+    #[test]
+    fn fonts_without_vertical_metrics() {
+        let broken = without_v_metrics(TEST_FONT);
+        let v_metrics = Font::try_from_bytes(&broken)
+            .unwrap()
+            .v_metrics(Scale::uniform(100.0));
+        assert!(v_metrics.ascent.is_nan() && v_metrics.descent.is_nan());
+
+        let label = label_data(29, None).unwrap();
+        let rasterizer = TextRasterizer::from_font_bytes(label, broken);
+        let height = rasterizer.measure_text("Hg", 100.0).unwrap().y;
+        assert!((90..=110).contains(&height), "{}", height);
+
+        // The text is drawn in full like with the font's real metrics, not squashed into a blank label
+        let image = rasterizer.render_image("Hg", None, 1.0, false).unwrap();
+        let (_, y0, _, y1) = ink_bounds(&image);
+        let reference = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec())
+            .render_image("Hg", None, 1.0, false)
+            .unwrap();
+        let (_, reference_y0, _, reference_y1) = ink_bounds(&reference);
+        assert!(y0 > label.right_margin as u32 && y1 < image.height() - 1);
+        assert!(((y1 - y0) as i32 - (reference_y1 - reference_y0) as i32).abs() <= 40);
+    }
+
+    #[test]
+    fn dithering_mid_gray_is_half_black() {
+        let gray = image::GrayImage::from_pixel(16, 16, Luma([128]));
+        assert_eq!(black_dots(&dither_to_1bit(&gray, Dithering::None)), 0);
+        for mode in [Dithering::FloydSteinberg, Dithering::Ordered(4)] {
+            let dithered = dither_to_1bit(&gray, mode);
+            assert!(dithered
+                .pixels()
+                .all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+            let black = black_dots(&dithered);
+            assert!(
+                (120..=136).contains(&black),
+                "{:?} gave {} black dots",
+                mode,
+                black
+            );
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_black_and_white() {
+        let image = image::GrayImage::from_fn(8, 8, |x, _| Luma([if x < 4 { 0 } else { 255 }]));
+        for mode in [
+            Dithering::None,
+            Dithering::FloydSteinberg,
+            Dithering::Ordered(8),
+        ] {
+            assert_eq!(dither_to_1bit(&image, mode), image, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn threshold_controls_ink() {
+        let image = image::GrayImage::from_pixel(2, 8, Luma([150]));
+        let ink = |threshold| {
+            image_to_raster_lines_with_threshold(&image, 90, threshold)
+                .unwrap()
+                .iter()
+                .map(|line| line.iter().map(|byte| byte.count_ones()).sum::<u32>())
+                .sum::<u32>()
+        };
+        assert_eq!(ink(DEFAULT_THRESHOLD), 0);
+        assert_eq!(ink(150), 16);
+        assert_eq!(image_to_raster_lines(&image, 90).unwrap().iter().count(), 2);
+    }
+
+    #[test]
+    fn overlapping_glyphs_keep_their_coverage() {
+        let fonts = [Font::try_from_bytes(TEST_FONT).unwrap()];
+        let style = TextStyle {
+            letter_spacing: -20.0,
+            ..TextStyle::new(&fonts)
+        };
+        let text = ResizedText::at_size(style, "MM", 40.0);
+        let mut image = image::GrayImage::from_pixel(60, 40, Luma([255]));
+        let clip = label_area(image.width(), image.height());
+        draw_glyphs(&mut image, &text.glyphs, XY { x: 0, y: 0 }, clip, false);
+        // Each pixel is as dark as the darkest glyph covering it
+        for glyph in &text.glyphs {
+            let bounding_box = glyph.pixel_bounding_box().unwrap();
+            glyph.draw(|x, y, v| {
+                let (x, y) = (x as i32 + bounding_box.min.x, y as i32 + bounding_box.min.y);
+                if (0..60).contains(&x) && (0..40).contains(&y) {
+                    assert!(image.get_pixel(x as u32, y as u32)[0] <= 255 - (255.0 * v) as u8);
+                }
+            });
+        }
+        // Anti-aliased edges are gray until they're dithered
+        assert!(image.pixels().any(|pixel| pixel[0] > 0 && pixel[0] < 255));
+    }
+
+    #[test]
+    fn strokes_keep_their_weight_between_pixels() {
+        // The glyph is a 30 dot wide square at this size, starting 5 dots after the pen position
+        let font = Font::try_from_vec(box_font(&['A'])).unwrap();
+        for offset in [0.0, 0.25, 0.5, 0.75] {
+            let glyphs: Vec<_> = font
+                .layout("A", Scale::uniform(50.0), rusttype::point(offset, 45.0))
+                .collect();
+            let mut image = image::GrayImage::from_pixel(60, 50, Luma([255]));
+            draw_glyphs(
+                &mut image,
+                &glyphs,
+                XY { x: 0, y: 0 },
+                label_area(60, 50),
+                false,
+            );
+            // Dots across the middle of the square that are printed black
+            let stroke = (0..60)
+                .filter(|&x| image.get_pixel(x, 25)[0] <= DEFAULT_THRESHOLD)
+                .count();
+            // Half covered edge pixels are printed, so the stroke is never thinner than the glyph
+            let expected = if offset == 0.5 { 31 } else { 30 };
+            assert_eq!(stroke, expected, "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn packing_raster_lines() {
+        assert_eq!(pack_raster_line([true], 2), [0x80, 0x00]);
+        assert_eq!(pack_raster_line([false, true, false, true], 1), [0x50]);
+        let mut dots = vec![false; 16];
+        dots[8] = true;
+        dots[15] = true;
+        assert_eq!(pack_raster_line(dots, 3), [0x00, 0x81, 0x00]);
+        // Dots past the end of the line are dropped
+        assert_eq!(pack_raster_line(vec![true; 20], 2), [0xFF, 0xFF]);
+
+        // A single black pixel at (x, y) is dot y of line x
+        for (x, y) in [(0, 0), (2, 7), (1, 8), (4, 707), (3, 719)] {
+            let mut image = image::GrayImage::from_pixel(5, 720, Luma([255]));
+            image.put_pixel(x, y, Luma([0]));
+            let lines = image_to_raster_lines(&image, 90).unwrap();
+            assert_eq!(lines.len(), 5);
+            for (index, line) in lines.iter().enumerate() {
+                let mut expected = [0; 90];
+                if index == x as usize {
+                    expected[y as usize / 8] = 0x80 >> (y % 8);
+                }
+                assert_eq!(line, expected, "pixel ({}, {}), line {}", x, y, index);
+            }
+        }
+
+        // A whole 62mm label, right margin included, fits the 90 byte lines of a QL-700
+        let label = label_data(62, None).unwrap();
+        let image = image::GrayImage::from_pixel(2, label.raster_width(), Luma([0]));
+        let lines = image_to_raster_lines(&image, label.raster_line_length()).unwrap();
+        let mut expected = [0xFF; 90];
+        expected[88] = 0xF0;
+        expected[89] = 0x00;
+        assert_eq!(lines.get(1).unwrap(), expected);
+    }
+
+    #[test]
+    fn transposing_images() {
+        let image = image::GrayImage::from_fn(3, 10, |x, y| Luma([(x * 10 + y) as u8]));
+        let transposed = transpose_for_printer(&image);
+        assert_eq!(transposed.dimensions(), (10, 3));
+        assert_eq!(transposed.get_pixel(7, 2)[0], 27);
+        assert_eq!(
+            image::imageops::flip_horizontal(&image::imageops::rotate90(&image)),
+            transposed
+        );
+
+        // Each row of the transposed image is packed into the matching raster line
+        let lines = image_to_raster_lines_with_threshold(&image, 2, 12).unwrap();
+        for (line, row) in lines.iter().zip(transposed.rows()) {
+            assert_eq!(line, pack_raster_line(row.map(|pixel| pixel[0] <= 12), 2));
+        }
+        assert_eq!(lines.get(0).unwrap(), [0xFF, 0xC0]);
+        assert_eq!(lines.get(1).unwrap(), [0xE0, 0x00]);
+    }
+
+    #[test]
+    fn bilevel_images() {
+        let image = image::GrayImage::from_fn(20, 700, |x, y| {
+            Luma([if (x * 7 + y) % 3 == 0 { 0 } else { 255 }])
+        });
+        assert_eq!(
+            bilevel_image_to_raster_lines(&image, 90).unwrap(),
+            image_to_raster_lines(&image, 90).unwrap()
+        );
+
+        let mut gray = image.clone();
+        gray.put_pixel(4, 9, Luma([40]));
+        let error = bilevel_image_to_raster_lines(&gray, 90).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            RasterizeErrorKind::NotBilevel(4, 9, 40)
+        ));
+        let error = bilevel_image_to_raster_lines(&image, 80).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            RasterizeErrorKind::ImageDoesNotFitLine(700, 640)
+        ));
+    }
+
+    #[test]
+    fn odd_sized_images() {
+        // Every column becomes a line and every row a dot, whatever the size
+        let image = image::GrayImage::from_pixel(7, 13, Luma([0]));
+        let lines = image_to_raster_lines(&image, 90).unwrap();
+        assert_eq!(lines.len(), 7);
+        assert!(lines
+            .iter()
+            .all(|line| line.iter().map(|byte| byte.count_ones()).sum::<u32>() == 13));
+
+        let image = image::GrayImage::new(3, 90 * 8 + 1);
+        let error = image_to_raster_lines(&image, 90).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            RasterizeErrorKind::ImageDoesNotFitLine(721, 720)
+        ));
+        assert!(image_to_raster_lines(&image, 162).is_ok());
+    }
+
+    #[test]
+    fn continuous_label_widths() {
+        let widths = [
+            (12, 135),
+            (29, 312),
+            (38, 425),
+            (50, 566),
+            (54, 590),
+            (62, 708),
+            (102, 1176),
+        ];
+        for (tape, width) in widths {
+            let label = label_data(tape, None).unwrap();
+            let rasterizer = TextRasterizer::new(label, PathBuf::from("./does-not-exist.ttf"));
+            assert_eq!(rasterizer.dimensions(), (750, width, 0), "{}mm", tape);
+        }
+
+        let mut rasterizer = TextRasterizer::new(
+            label_data(12, None).unwrap(),
+            PathBuf::from("./does-not-exist.ttf"),
+        );
+        rasterizer.set_second_row_image(PathBuf::from("./logo.png"));
+        assert_eq!(rasterizer.dimensions(), (750, 135, 142));
+
+        // High resolution labels are drawn at double size and squeezed back across the tape
+        rasterizer.set_resolution(Resolution::High);
+        assert_eq!(rasterizer.dimensions(), (1500, 270, 284));
+        let image = rasterizer.finish_image(image::GrayImage::new(1500, 270 + 284));
+        assert_eq!(image.dimensions(), (1500, 135 + 142));
+    }
+
+    #[test]
+    fn continuous_label_lengths() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, None).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_continuous_length_mm(63.5);
+        assert_eq!(rasterizer.dimensions().0, DEFAULT_CONTINUOUS_LENGTH);
+        rasterizer.set_continuous_length_mm(100.0);
+        assert_eq!(rasterizer.dimensions().0, 1181);
+        assert_eq!(rasterizer.rasterize("Hi", None, 1.0, false).len(), 1181);
+
+        // Die-cut labels keep their own length
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, Some(90)).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_continuous_length(100);
+        assert_eq!(rasterizer.dimensions().0, 991);
+    }
+
+    #[test]
+    fn continuous_labels_fit_their_content() {
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(62, None).unwrap(), TEST_FONT.to_vec());
+        let full = rasterizer.render_image("Hello", None, 1.0, false).unwrap();
+        let drawn: Vec<u32> = (0..full.width())
+            .filter(|&x| (0..full.height()).any(|y| full.get_pixel(x, y)[0] != 255))
+            .collect();
+        let content = drawn.last().unwrap() + 1 - drawn[0];
+
+        rasterizer.set_content_length_margins(Some((20, 30)));
+        let fitted = rasterizer.render_image("Hello", None, 1.0, false).unwrap();
+        assert_eq!(fitted.dimensions(), (content + 50, full.height()));
+        assert_eq!(
+            fitted,
+            image::imageops::crop_imm(&full, drawn[0] - 20, 0, content + 50, full.height())
+                .to_image()
+        );
+        assert_eq!(
+            rasterizer.rasterize("Hello", None, 1.0, true).len() as u32,
+            content + 50
+        );
+
+        // Short content still makes a label the printer can cut
+        rasterizer.set_font_sizing(FontSizing::Fixed(20.0));
+        let short = rasterizer.render_image(".", None, 1.0, false).unwrap();
+        assert_eq!(short.width(), MIN_CONTINUOUS_LENGTH);
+        rasterizer.set_resolution(Resolution::High);
+        let short = rasterizer.render_image(".", None, 1.0, false).unwrap();
+        assert_eq!(short.width(), 2 * MIN_CONTINUOUS_LENGTH);
+
+        // Die-cut labels keep their own length
+        let mut rasterizer =
+            TextRasterizer::from_font_bytes(label_data(29, Some(90)).unwrap(), TEST_FONT.to_vec());
+        rasterizer.set_content_length_margins(Some((0, 0)));
+        assert_eq!(rasterizer.rasterize("Hi", None, 1.0, false).len(), 991);
+    }
+
+    #[test]
+    fn small_text_is_reported() {
+        use std::sync::{Arc, Mutex};
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&warnings);
+        let rasterizer = TextRasterizerBuilder::from_font_bytes(
+            label_data(29, None).unwrap(),
+            TEST_FONT.to_vec(),
+        )
+        .min_readable_font_size(40.0)
+        .warning_handler(move |warning| reported.lock().unwrap().push(warning.clone()))
+        .build()
+        .unwrap();
+
+        rasterizer.try_rasterize("Short", None, 1.0, false).unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+
+        let long = "A very long line of text that has to shrink a lot to fit on the label";
+        rasterizer.try_rasterize(long, None, 1.0, false).unwrap();
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        match &warnings[0] {
+            RasterizeWarning::TextTooSmall {
+                text,
+                font_size,
+                min_readable,
+            } => {
+                assert_eq!(text, long);
+                assert!(*font_size < 40.0);
+                assert_eq!(*min_readable, 40.0);
+            }
+        }
+    }
+
+    #[test]
+    fn builder_matches_setters() {
+        let label = label_data(29, None).unwrap();
+        let margins = Margins {
+            left: 40,
+            ..Margins::default()
+        };
+        let built = TextRasterizerBuilder::from_font_bytes(label, TEST_FONT.to_vec())
+            .alignment(TextAlign::Left)
+            .margins(margins)
+            .letter_spacing(2.0)
+            .build()
+            .unwrap();
+        let mut configured = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        configured.set_alignment(TextAlign::Left);
+        configured.set_margins(margins);
+        configured.set_letter_spacing(2.0);
+        assert_eq!(
+            built.render_image("Built", None, 1.0, false).unwrap(),
+            configured.render_image("Built", None, 1.0, false).unwrap()
+        );
+
+        let too_long = "x".repeat(8000);
+        let result = TextRasterizerBuilder::from_font_bytes(label, TEST_FONT.to_vec())
+            .qr_code(&too_long, QrEcLevel::High)
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn inverted_labels_are_black() {
+        let rasterizer =
+            TextRasterizer::from_font_bytes(label_data(62, None).unwrap(), TEST_FONT.to_vec());
+        let (length, width, _) = rasterizer.dimensions();
+        let image = rasterizer.blank_image(length, width, 0, true);
+        assert!(image.pixels().all(|pixel| pixel[0] == 0));
+
+        let image = rasterizer.blank_image(length, width, 142, true);
+        assert!((0..length).all(|x| image.get_pixel(x, width - 1)[0] == 0));
+        assert!((0..length).all(|x| image.get_pixel(x, width)[0] == 255));
+        assert_eq!(rasterizer.blank_image(length, width, 142, false), {
+            let mut white = image.clone();
+            white.pixels_mut().for_each(|pixel| pixel[0] = 255);
+            white
+        });
+
+        // Either area can be chosen to stay white or turn black
+        let mut rasterizer = rasterizer;
+        rasterizer.set_invert_backgrounds(false, true);
+        let image = rasterizer.blank_image(length, width, 142, true);
+        assert!((0..length).all(|x| image.get_pixel(x, width - 1)[0] == 255));
+        assert!((0..length).all(|x| image.get_pixel(x, width)[0] == 0));
+        // Text on a main label left white stays black
+        let image = rasterizer
+            .render_image("Inverted", None, 1.0, true)
+            .unwrap();
+        assert_eq!(image.get_pixel(0, 0)[0], 255);
+        assert!(image.pixels().any(|pixel| pixel[0] == 0));
+        rasterizer.set_invert_backgrounds(true, false);
+
+        let image = rasterizer
+            .render_image("Inverted", None, 1.0, true)
+            .unwrap();
+        assert_eq!(image.get_pixel(0, 0)[0], 0);
+        assert_eq!(image.get_pixel(length - 1, width - 1)[0], 0);
+        assert!(image.pixels().any(|pixel| pixel[0] == 255));
+    }
+
+    #[test]
+    fn wide_labels_use_wide_lines() {
+        let label = label_data(102, Some(51)).unwrap();
+        let rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let lines = rasterizer.try_rasterize("W", None, 4.0, false).unwrap();
+        assert_eq!(lines.line_length(), 162);
+        assert_eq!(lines.len(), label.dots_printable.1 as usize);
+        // Text is centered across the whole tape so some of it lands past the 90 bytes of a regular print head
+        assert!(lines
+            .iter()
+            .any(|line| line[90..].iter().any(|&byte| byte != 0)));
+    }
+
+    #[test]
+    fn text_stays_inside_round_labels() {
+        let label = label_data(24, Some(24)).unwrap();
+        let rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let image = rasterizer.render_image("Round", None, 1.0, false).unwrap();
+        let radius = label.dots_printable.0 as f32 / 2.0;
+        let center = (radius, label.right_margin as f32 + radius);
+        let mut dark = 0;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel[0] < 128 {
+                dark += 1;
+                let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+                assert!(
+                    (dx * dx + dy * dy).sqrt() <= radius,
+                    "({}, {}) is outside the label",
+                    x,
+                    y
+                );
+            }
+        }
+        assert!(dark > 0);
+    }
+
+    #[test]
+    fn vertical_text_runs_across_die_cut_labels() {
+        let label = label_data(29, Some(90)).unwrap();
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        // Returns the extent of the ink (along, across) the tape
+        let extent = |rasterizer: &TextRasterizer| {
+            let image = rasterizer.render_image("HELLO", None, 1.0, false).unwrap();
+            assert_eq!(image.dimensions(), (991, label.raster_width()));
+            let dark: Vec<_> = image
+                .enumerate_pixels()
+                .filter(|(_, _, pixel)| pixel[0] == 0)
+                .map(|(x, y, _)| (x, y))
+                .collect();
+            let span =
+                |values: Vec<u32>| values.iter().max().unwrap() - values.iter().min().unwrap();
+            (
+                span(dark.iter().map(|&(x, _)| x).collect()),
+                span(dark.iter().map(|&(_, y)| y).collect()),
+            )
+        };
+
+        let (along, across) = extent(&rasterizer);
+        assert!(along > across);
+        rasterizer.set_orientation(TextOrientation::Vertical);
+        let (along, across) = extent(&rasterizer);
+        assert!(across > along);
+        assert_eq!(rasterizer.rasterize("HELLO", None, 1.0, false).len(), 991);
+    }
+
+    #[test]
+    fn tall_images_are_tiled_across_labels() {
+        let label = label_data(29, Some(90)).unwrap();
+        let image = image::GrayImage::from_pixel(2500, label.dots_printable.0, Luma([0]));
+        let tiles = tile_across_labels(&image, &label);
+        assert_eq!(tiles.len(), 3);
+        for tile in &tiles {
+            assert_eq!(tile.len(), 991);
+            assert_eq!(tile.label(), Some(&label));
+        }
+        let black_dots = |line: &[u8]| line.iter().map(|byte| byte.count_ones()).sum::<u32>();
+        assert!(tiles[1].iter().all(|line| black_dots(line) == 306));
+        // The last label is only partly covered and padded with white
+        assert_eq!(black_dots(tiles[2].get(2500 - 2 * 991 - 1).unwrap()), 306);
+        assert_eq!(black_dots(tiles[2].get(2500 - 2 * 991).unwrap()), 0);
+
+        let continuous = label_data(29, None).unwrap();
+        assert_eq!(tile_across_labels(&image, &continuous).len(), 1);
+    }
+
+    #[test]
+    fn images_are_fitted_to_labels() {
+        let image = image::GrayImage::from_pixel(100, 50, Luma([0]));
+        // Continuous tape is as long as the image once it's scaled to the printable width
+        let continuous = label_data(29, None).unwrap();
+        let fitted = fit_to_label(&image, &continuous, ImageFit::Contain);
+        assert_eq!(fitted.dimensions(), (612, continuous.raster_width()));
+        assert_eq!(raster_from_image(&image, &continuous).len(), 612);
+
+        // Die-cut labels are always the length of the label with the image centered
+        let die_cut = label_data(29, Some(90)).unwrap();
+        let fitted = fit_to_label(&image, &die_cut, ImageFit::Contain);
+        assert_eq!(fitted.dimensions(), (991, die_cut.raster_width()));
+        assert_eq!(black_dots(&fitted), 612 * 306);
+        let margin = die_cut.right_margin as u32;
+        assert_eq!(fitted.get_pixel((991 - 612) / 2 - 1, margin)[0], 255);
+        assert_eq!(fitted.get_pixel((991 - 612) / 2, margin)[0], 0);
+        // Images too long for the label are shrunk to fit its length
+        let long = image::GrayImage::from_pixel(1000, 100, Luma([0]));
+        assert_eq!(
+            black_dots(&fit_to_label(&long, &die_cut, ImageFit::Contain)),
+            991 * 99
+        );
+
+        // Covering the label crops the image instead of leaving blank ends
+        let fitted = fit_to_label(&image, &die_cut, ImageFit::Cover);
+        assert_eq!(fitted.dimensions(), (991, die_cut.raster_width()));
+        assert_eq!(black_dots(&fitted), 991 * 306);
+    }
+
+    #[test]
+    fn image_files_are_printed() {
+        let path = std::env::temp_dir().join("brother-ql-rs-image-file.png");
+        let mut image = image::RgbImage::from_pixel(200, 100, image::Rgb([255, 255, 255]));
+        for x in 0..100 {
+            for y in 0..100 {
+                image.put_pixel(x, y, image::Rgb([0, 0, 0]));
+            }
+        }
+        image.save(&path).unwrap();
+
+        let label = label_data(29, None).unwrap();
+        let lines = raster_from_image_file(&path, &label, ImageFit::Contain, Dithering::None);
+        let lines = lines.unwrap();
+        assert_eq!(lines.len(), 612);
+        // The left half of the image is printed first, across the whole printable width
+        let inked = |line: &[u8]| line.iter().map(|byte| byte.count_ones()).sum::<u32>();
+        assert_eq!(inked(lines.get(0).unwrap()), label.dots_printable.0);
+        assert_eq!(inked(lines.get(611).unwrap()), 0);
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = Path::new("./does-not-exist.png");
+        match raster_from_image_file(missing, &label, ImageFit::Contain, Dithering::None) {
+            Err(RasterizeError(RasterizeErrorKind::InvalidImage(_), _)) => {}
+            other => panic!(
+                "Expected InvalidImage, got {:?}",
+                other.map(|lines| lines.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn fitted_images_stay_in_the_printable_width() {
+        let image = image::GrayImage::from_pixel(300, 200, Luma([0]));
+        let labels = ["d12", "d24", "29x90", "12"]
+            .map(|name| crate::printer::constants::label_by_name(name).unwrap());
+        for label in &labels {
+            let printable = label.right_margin as u32..label.raster_width();
+            for fit in [ImageFit::Contain, ImageFit::Cover] {
+                let fitted = fit_to_label(&image, label, fit);
+                assert!(
+                    fitted
+                        .enumerate_pixels()
+                        .filter(|(_, _, pixel)| pixel[0] == 0)
+                        .all(|(_, y, _)| printable.contains(&y)),
+                    "{} {:?}",
+                    label.name,
+                    fit
+                );
+            }
+            for lines in tile_across_labels(&image, label) {
+                for line in lines.iter() {
+                    assert!((0..line.len() * 8)
+                        .filter(|dot| line[dot / 8] & (0x80 >> (dot % 8)) != 0)
+                        .all(|dot| printable.contains(&(dot as u32))));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn second_row_images_are_rotated() {
+        let path = std::env::temp_dir().join("brother-ql-rs-second-row-image.png");
+        // Black on the left half and the top right quarter
+        image::GrayImage::from_fn(40, 20, |x, y| {
+            Luma([if x < 20 || y < 10 { 0 } else { 255 }])
+        })
+        .save(&path)
+        .unwrap();
+        let mut rasterizer = TextRasterizer::new(
+            label_data(12, None).unwrap(),
+            PathBuf::from("./does-not-exist.ttf"),
+        );
+        rasterizer.set_second_row_image(path);
+        let (length, width, secondary_width) = rasterizer.dimensions();
+        let second_row = |rasterizer: &TextRasterizer| {
+            let mut image = rasterizer.blank_image(length, width, secondary_width, false);
+            rasterizer
+                .draw_second_row(&mut image, length, width, secondary_width, false)
+                .unwrap();
+            image::imageops::crop_imm(&image, 0, width, length, secondary_width).to_image()
+        };
+        let (x0, y0, x1, y1) = ink_bounds(&second_row(&rasterizer));
+        let size = (x1 - x0 + 1, y1 - y0 + 1);
+        let at = |image: &image::GrayImage, fx: f32, fy: f32| {
+            image.get_pixel(
+                x0 + (size.0 as f32 * fx) as u32,
+                y0 + (size.1 as f32 * fy) as u32,
+            )[0]
+        };
+        let upright = second_row(&rasterizer);
+        assert_eq!(at(&upright, 0.75, 0.75), 255);
+
+        rasterizer.set_second_row_rotation(Rotation::Deg180);
+        let flipped = second_row(&rasterizer);
+        assert_eq!(ink_bounds(&flipped), (x0, y0, x1, y1));
+        assert_eq!(at(&flipped, 0.25, 0.25), 255);
+        assert_eq!(at(&flipped, 0.75, 0.75), 0);
+
+        // A quarter turn makes the image taller than it is long, so it's scaled down to fit the row
+        rasterizer.set_second_row_rotation(Rotation::Deg90);
+        let (x0, y0, x1, y1) = ink_bounds(&second_row(&rasterizer));
+        assert!(x1 - x0 < y1 - y0);
+
+        // An inverted bonus row inverts the image along with its background
+        rasterizer.set_second_row_rotation(Rotation::Deg0);
+        rasterizer.set_invert_backgrounds(true, true);
+        let mut image = rasterizer.blank_image(length, width, secondary_width, true);
+        rasterizer
+            .draw_second_row(&mut image, length, width, secondary_width, true)
+            .unwrap();
+        let mut inverted =
+            image::imageops::crop_imm(&image, 0, width, length, secondary_width).to_image();
+        image::imageops::invert(&mut inverted);
+        assert_eq!(inverted, upright);
+    }
+
+    #[test]
+    fn leading_images_are_fitted() {
+        let path = std::env::temp_dir().join("brother-ql-rs-leading-image.png");
+        // A black image twice as long as it is wide
+        image::GrayImage::from_pixel(40, 20, Luma([0]))
+            .save(&path)
+            .unwrap();
+        let mut rasterizer = TextRasterizer::new(
+            label_data(29, None).unwrap(),
+            PathBuf::from("./does-not-exist.ttf"),
+        );
+        assert_eq!(rasterizer.margins().left, 0);
+        rasterizer.set_leading_image(path.clone(), 312, ImageFit::Contain);
+        assert_eq!(rasterizer.margins().left, 312);
+
+        // The area is 312 dots square so the image fills its length and half its width
+        let contained = rasterizer
+            .fit_image(rasterizer.leading_image.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(contained.dimensions(), (312, 312));
+        assert_eq!(black_dots(&contained), 312 * 156);
+        assert_eq!(contained.get_pixel(10, 77)[0], 255);
+        assert_eq!(contained.get_pixel(10, 78)[0], 0);
+        rasterizer.set_leading_image(path, 312, ImageFit::Cover);
+        let covered = rasterizer
+            .fit_image(rasterizer.leading_image.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(black_dots(&covered), 312 * 312);
+
+        rasterizer.set_leading_image(PathBuf::from("./does-not-exist.png"), 100, ImageFit::Cover);
+        let (length, width, _) = rasterizer.dimensions();
+        let mut image = rasterizer.blank_image(length, width, 0, false);
+        match rasterizer.draw_images(&mut image, length, width) {
+            Err(RasterizeError(RasterizeErrorKind::InvalidImage(_), _)) => {}
+            other => panic!("Expected an invalid image error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shipping_labels() {
+        let label = label_data(62, Some(100)).unwrap();
+        let mut rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        let address = [
+            "Ada Lovelace",
+            "12 St James's Square, Westminster",
+            "London SW1Y 4JH",
+        ];
+        let image = rasterizer
+            .render_shipping_label("https://example.com/track/1234", &address)
+            .unwrap();
+        let (length, width, _) = rasterizer.dimensions();
+        assert_eq!(image.dimensions(), (length, width));
+
+        // The QR code is a square at the start of the label with its quiet zone, and the text fills the rest
+        let crop = |x, width_| image::imageops::crop_imm(&image, x, 0, width_, width).to_image();
+        let (quiet_zone, y0, _, y1) = ink_bounds(&crop(0, 100));
+        let qr_size = y1 - y0 + 1;
+        assert!(qr_size > 300 && quiet_zone > 0);
+        assert_eq!(ink_bounds(&crop(quiet_zone, qr_size)).2, qr_size - 1);
+        assert_eq!(black_dots(&crop(quiet_zone + qr_size, quiet_zone)), 0);
+        let text_x = 2 * quiet_zone + qr_size;
+        let text = crop(text_x, length - text_x);
+        let (_, y0, x1, y1) = ink_bounds(&text);
+        assert!(x1 > 100 && y1 - y0 > width / 2, "{:?}", ink_bounds(&text));
+
+        // Lines that are too long are wrapped rather than shrunk
+        let rows_of_ink = |image: &image::GrayImage| {
+            let inked: Vec<bool> = (0..image.height())
+                .map(|y| (0..image.width()).any(|x| image.get_pixel(x, y)[0] <= DEFAULT_THRESHOLD))
+                .collect();
+            inked.windows(2).filter(|pair| pair[1] && !pair[0]).count() + inked[0] as usize
+        };
+        assert!(rows_of_ink(&text) > address.len());
+        assert_eq!(
+            rasterizer
+                .try_rasterize_shipping_label("1234", &address)
+                .unwrap()
+                .len(),
+            length as usize
+        );
+        let too_much = ["word"; 200].join(" ");
+        rasterizer.set_min_font_size(30.0);
+        assert!(rasterizer
+            .render_shipping_label("1234", &[&too_much])
+            .is_err());
+    }
+
+    #[test]
+    fn qr_code_is_placed_at_the_end() {
+        let mut rasterizer = TextRasterizer::new(
+            label_data(29, None).unwrap(),
+            PathBuf::from("./does-not-exist.ttf"),
+        );
+        rasterizer.set_margins(Margins {
+            top: 10,
+            bottom: 10,
+            left: 0,
+            right: 5,
+        });
+        rasterizer.set_qr_code("Hello", QrEcLevel::Medium).unwrap();
+        // 292 dots across the tape fit 10 dots per module of a 29 module code
+        assert_eq!(rasterizer.qr_code_layout(), Some((10, 290)));
+        assert_eq!(rasterizer.margins().right, 5 + 290);
+
+        let (length, width, _) = rasterizer.dimensions();
+        let mut image = rasterizer.blank_image(length, width, 0, false);
+        rasterizer.draw_qr_code(&mut image, length, width).unwrap();
+        let black_columns: Vec<u32> = (0..length)
+            .filter(|&x| (0..width).any(|y| image.get_pixel(x, y)[0] == 0))
+            .collect();
+        // The first and last modules are inside the quiet zone
+        assert_eq!(black_columns.first(), Some(&(length - 5 - 290 + 40)));
+        assert_eq!(black_columns.last(), Some(&(length - 5 - 40 - 1)));
+
+        // Modules stay whole printer dots in high resolution
+        rasterizer.set_resolution(Resolution::High);
+        assert_eq!(rasterizer.qr_code_layout(), Some((20, 580)));
+    }
+
+    #[test]
+    fn red_pixels_go_in_the_red_plane() {
+        let colors = [[0, 0, 0], [255, 0, 0], [255, 255, 255], [200, 30, 40]];
+        let image = image::RgbImage::from_fn(4, 1, |x, _| image::Rgb(colors[x as usize]));
+        let lines = image_to_two_color_raster_lines(&image, 90).unwrap();
+        let inked = |lines: &RasterLines| -> Vec<bool> {
+            lines
+                .iter()
+                .map(|line| line.iter().any(|&byte| byte != 0))
+                .collect()
+        };
+        assert_eq!(inked(&lines.black), vec![true, false, false, false]);
+        assert_eq!(inked(&lines.red), vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn empty_text_has_no_width() {
+        assert_eq!(calc_text_width(&[]), 0);
+    }
+
+    #[test]
+    fn spaces_and_combining_marks_are_measured() {
+        let fonts = [Font::try_from_vec(TEST_FONT.to_vec()).unwrap()];
+        let width = |text| {
+            calc_text_width(&layout_glyphs(
+                TextStyle::new(&fonts),
+                text,
+                Scale::uniform(100.0),
+                0.0,
+            ))
+        };
+        assert_eq!(width("   "), 0);
+        // Only the glyphs with ink count, give or take a pixel of rounding at a different caret position
+        assert!((width(" café ") as i32 - width("café") as i32).abs() <= 1);
+        assert!(width("café") > width("caf"));
+
+        // The marks stacked on the A are measured even though they don't advance the caret
+        let glyphs = layout_glyphs(
+            TextStyle::new(&fonts),
+            "A\u{308}\u{30A}",
+            Scale::uniform(100.0),
+            0.0,
+        );
+        let boxes: Vec<_> = glyphs
+            .iter()
+            .filter_map(|g| g.pixel_bounding_box())
+            .collect();
+        let min = boxes.iter().map(|bb| bb.min.x).min().unwrap();
+        let max = boxes.iter().map(|bb| bb.max.x).max().unwrap();
+        assert_eq!(width("A\u{308}\u{30A}"), (max - min) as u32);
+        assert!(width("A\u{308}\u{30A}") >= width("A"));
+
+        let label = label_data(29, Some(90)).unwrap();
+        let rasterizer = TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec());
+        assert_eq!(
+            rasterizer
+                .render_image(" Ä\u{30A} ", None, 80.0, false)
+                .unwrap()
+                .dimensions(),
+            (991, label.raster_width())
+        );
     }
 }
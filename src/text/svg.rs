@@ -0,0 +1,161 @@
+//! Labels designed as SVG files, rendered with `resvg`
+//!
+//! Only available with the `svg` feature. Text in the SVG is drawn with the fonts installed on the system.
+
+use super::{
+    dither_to_1bit, image_to_raster_lines, Dithering, ImageFit, RasterizeErrorKind, Result,
+};
+use crate::printer::constants::Label;
+use crate::printer::RasterLines;
+use image::Luma;
+use resvg::tiny_skia::{Color, Pixmap, Transform};
+use resvg::usvg;
+
+/// Renders `svg_data` into a label image for `label`, laid out like the images `TextRasterizer` renders: the SVG's x
+/// axis runs along the tape and its y axis across it.
+///
+/// The drawing is scaled (keeping its aspect ratio) to span the printable width of the tape, the same way
+/// `raster_from_image()` scales images. It's rendered straight at the printer's 300dpi rather than scaled up from
+/// a bitmap, so the size the SVG claims only sets its aspect ratio. Fails with `InvalidSvg` if the SVG can't be
+/// parsed.
+pub fn render_svg(svg_data: &str, label: &Label) -> Result<image::GrayImage> {
+    render_fitted(svg_data, label, ImageFit::Contain)
+}
+
+/// Renders `svg_data` for `label` like `render_svg()` and converts it into raster lines. With `ImageFit::Cover` the
+/// drawing fills the whole of a die-cut label, cropping whatever sticks out of it. Gray and antialiased edges are
+/// reduced to black and white dots using `dithering`.
+pub fn raster_from_svg(
+    svg_data: &str,
+    label: &Label,
+    fit: ImageFit,
+    dithering: Dithering,
+) -> Result<RasterLines> {
+    let image = dither_to_1bit(&render_fitted(svg_data, label, fit)?, dithering);
+    let mut lines = image_to_raster_lines(&image, label.raster_line_length())?;
+    lines.set_label(*label);
+    Ok(lines)
+}
+
+fn render_fitted(svg_data: &str, label: &Label, fit: ImageFit) -> Result<image::GrayImage> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(svg_data, &options)
+        .map_err(|error| RasterizeErrorKind::InvalidSvg(error.to_string()))?;
+    let (svg_length, svg_width) = (tree.size().width(), tree.size().height());
+
+    // Scaled the same way as `fit_to_label()` scales images
+    let printable = label.dots_printable;
+    let mut ratio = printable.0 as f32 / svg_width;
+    let die_cut = printable.1 != 0;
+    if die_cut {
+        let length_ratio = printable.1 as f32 / svg_length;
+        ratio = match fit {
+            ImageFit::Contain => ratio.min(length_ratio),
+            ImageFit::Cover => ratio.max(length_ratio),
+        };
+    }
+    let length = if die_cut {
+        printable.1
+    } else {
+        ((svg_length * ratio).round() as u32).max(1)
+    };
+
+    // Centered along the label, and cropped evenly from both sides if it's too wide for the printable width
+    let offset_x = (length as f32 - svg_length * ratio) / 2.0;
+    let offset_y = ((printable.0 as f32 - svg_width * ratio) / 2.0).min(0.0);
+    let mut pixmap = Pixmap::new(length, printable.0).expect("Labels are at least a dot in size");
+    pixmap.fill(Color::WHITE);
+    let transform = Transform::from_row(ratio, 0.0, 0.0, ratio, offset_x, offset_y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // The pixmap is opaque everywhere since it started out white, so its colors can be used as they are
+    let rendered = image::GrayImage::from_fn(length, printable.0, |x, y| {
+        let pixel = pixmap.pixel(x, y).unwrap();
+        let (r, g, b) = (
+            pixel.red() as f32,
+            pixel.green() as f32,
+            pixel.blue() as f32,
+        );
+        Luma([(0.299 * r + 0.587 * g + 0.114 * b).round() as u8])
+    });
+    // Like every label image it starts with the right margin, which the print head can't reach
+    let mut image = image::GrayImage::from_pixel(length, label.raster_width(), Luma([255]));
+    image::imageops::overlay(&mut image, &rendered, 0, label.right_margin as u32);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{raster_from_svg, render_svg};
+    use crate::printer::constants::label_data;
+    use crate::text::{Dithering, ImageFit, RasterizeError, RasterizeErrorKind};
+
+    /// A black bar across the top half of a `length` by `width` drawing
+    fn half_black(length: u32, width: u32) -> String {
+        format!(
+            "<svg xmlns='http://www.w3.org/2000/svg' width='{0}mm' height='{1}mm' viewBox='0 0 {0} {1}'>\
+             <rect width='{0}' height='{2}'/></svg>",
+            length,
+            width,
+            width / 2
+        )
+    }
+
+    #[test]
+    fn svgs_span_the_printable_width() {
+        // Drawn the other way round from the label, so it's shrunk to fit its length
+        let label = label_data(29, Some(90)).unwrap();
+        let image = render_svg(&half_black(90, 90), &label).unwrap();
+        assert_eq!(image.dimensions(), (991, label.raster_width()));
+        let margin = label.right_margin as u32;
+        let black = |x, y| image.get_pixel(x, y + margin)[0] == 0;
+        assert!(black(495, 0) && black(495, 152) && !black(495, 154));
+        assert!(!black(495, 200) && !black(0, 0) && !black(990, 0));
+        assert_eq!(image.get_pixel(495, margin - 1)[0], 255);
+        let black_dots = image.pixels().filter(|pixel| pixel[0] == 0).count();
+        assert!((306 * 153 - 306..306 * 153 + 306).contains(&black_dots));
+
+        // On continuous tape the label is as long as the scaled drawing
+        let label = label_data(62, None).unwrap();
+        let image = render_svg(&half_black(100, 50), &label).unwrap();
+        assert_eq!(image.dimensions(), (1392, label.raster_width()));
+
+        let label = label_data(29, Some(90)).unwrap();
+        let lines = raster_from_svg(
+            &half_black(90, 90),
+            &label,
+            ImageFit::Cover,
+            Dithering::None,
+        )
+        .unwrap();
+        assert_eq!(lines.len(), 991);
+        assert_eq!(lines.label(), Some(&label));
+        // The first 6 dots are the label's right margin
+        assert_eq!(lines.get(0).unwrap()[..2], [0x03, 0xFF]);
+    }
+
+    #[test]
+    fn svgs_stay_in_the_printable_width() {
+        for name in ["d12", "29x90", "62"] {
+            let label = crate::printer::constants::label_by_name(name).unwrap();
+            let image = render_svg(&half_black(40, 20), &label).unwrap();
+            let printable = label.right_margin as u32..label.raster_width();
+            let mut black = image
+                .enumerate_pixels()
+                .filter(|(_, _, pixel)| pixel[0] == 0)
+                .peekable();
+            assert!(black.peek().is_some(), "{}", name);
+            assert!(black.all(|(_, y, _)| printable.contains(&y)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn invalid_svgs_are_an_error() {
+        let label = label_data(29, Some(90)).unwrap();
+        match render_svg("<svg", &label) {
+            Err(RasterizeError(RasterizeErrorKind::InvalidSvg(_), _)) => {}
+            other => panic!("Expected InvalidSvg, got {:?}", other.map(|_| ())),
+        }
+    }
+}
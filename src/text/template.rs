@@ -0,0 +1,251 @@
+//! Reusable label layouts made of positioned text boxes and images
+//!
+//! A `Template` is set up once with the text boxes and images of a layout (e.g. a name badge or a shelf tag) and
+//! then rendered again and again with different text filled into its fields.
+
+use super::{
+    combined_v_metrics, ImageFit, RasterizeErrorKind, ResizedText, Result, TextRasterizer, XY,
+};
+use crate::printer::RasterLines;
+use rusttype::{Rect, Scale};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A rectangle on the label in dots. `x` and `length` run along the tape from the start of the label, `y` and `width`
+/// across it from the first dot of each raster line, as in the images `TextRasterizer::render_image()` draws.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Area {
+    pub x: u32,
+    pub y: u32,
+    pub length: u32,
+    pub width: u32,
+}
+impl Area {
+    fn scaled(self, scale: u32) -> Self {
+        Area {
+            x: self.x * scale,
+            y: self.y * scale,
+            length: self.length * scale,
+            width: self.width * scale,
+        }
+    }
+    fn rect(self) -> Rect<i32> {
+        Rect {
+            min: XY {
+                x: self.x as i32,
+                y: self.y as i32,
+            },
+            max: XY {
+                x: (self.x + self.length) as i32,
+                y: (self.y + self.width) as i32,
+            },
+        }
+    }
+}
+
+/// Where text sits inside its `Area` when it doesn't fill it. Left and right are the start and end of the area along
+/// the tape, top is the side of the area closest to `y = 0`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+impl Anchor {
+    /// Top left corner of text that is `size` dots big placed in `area`
+    fn offset(self, area: Area, size: XY<u32>) -> XY<i32> {
+        // How far along the free space the text goes in halves
+        let (along, across) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        };
+        let free_length = area.length as i32 - size.x as i32;
+        let free_width = area.width as i32 - size.y as i32;
+        XY {
+            x: area.x as i32 + free_length * along / 2,
+            y: area.y as i32 + free_width * across / 2,
+        }
+    }
+}
+
+struct TextBox {
+    area: Area,
+    anchor: Anchor,
+    max_font_size: f32,
+    field: String,
+}
+
+struct ImageSlot {
+    area: Area,
+    path: PathBuf,
+    fit: ImageFit,
+}
+
+/// A label layout of text boxes and images at fixed positions
+///
+/// The text of each box comes from a named field when the template is rendered. Text is shrunk to fit its box the
+/// same way `TextRasterizer` fits text to a label and anything that still sticks out of the box is clipped.
+pub struct Template {
+    rasterizer: TextRasterizer,
+    text_boxes: Vec<TextBox>,
+    images: Vec<ImageSlot>,
+}
+impl Template {
+    /// Creates an empty template for the label, fonts and settings (e.g. spacing, font sizing, overflow, dithering and
+    /// resolution) of `rasterizer`. Continuous labels are as long as `TextRasterizer::set_continuous_length()`.
+    pub fn new(rasterizer: TextRasterizer) -> Self {
+        Template {
+            rasterizer,
+            text_boxes: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+    /// Adds a box of text filled in from the field called `field`. The text starts at `max_size` dots and is shrunk
+    /// until it fits inside `area`, then placed in the area according to `anchor`.
+    pub fn add_text_box(&mut self, area: Area, anchor: Anchor, max_size: f32, field: &str) {
+        self.text_boxes.push(TextBox {
+            area,
+            anchor,
+            max_font_size: max_size,
+            field: field.to_string(),
+        });
+    }
+    /// Adds the image at `path`, scaled to `area` according to `fit`. The image is opened every time the template is
+    /// rendered, which fails with `InvalidImage` if it can't be loaded. Text is drawn over images.
+    pub fn add_image(&mut self, area: Area, path: PathBuf, fit: ImageFit) {
+        self.images.push(ImageSlot { area, path, fit });
+    }
+
+    /// Draws the template with the text of every box taken from `fields`. Fails with `MissingField` if a box's field
+    /// isn't in `fields` and under the same conditions as `TextRasterizer::try_rasterize()`.
+    pub fn render(&self, fields: &HashMap<&str, &str>) -> Result<image::GrayImage> {
+        let rasterizer = &self.rasterizer;
+        let style = rasterizer.text_style(rasterizer.try_load_fonts()?);
+        let scale = rasterizer.scale();
+        let (length, width, _) = rasterizer.dimensions();
+        let mut image = rasterizer.blank_image(length, width, 0, false);
+
+        for slot in &self.images {
+            let area = slot.area.scaled(scale);
+            let fitted =
+                rasterizer.open_image_into(&slot.path, area.length, area.width, slot.fit)?;
+            image::imageops::overlay(&mut image, &fitted, area.x, area.y);
+        }
+        for text_box in &self.text_boxes {
+            let text = fields
+                .get(text_box.field.as_str())
+                .ok_or_else(|| RasterizeErrorKind::MissingField(text_box.field.clone()))?;
+            let area = text_box.area.scaled(scale);
+            let unit_height = {
+                let v_metrics = combined_v_metrics(style.fonts, text, Scale::uniform(1.0));
+                v_metrics.ascent - v_metrics.descent
+            };
+            let max_font_size = (text_box.max_font_size * scale as f32)
+                .min((area.width as f32 / unit_height).floor());
+            let (max_font_size, min_font_size) = rasterizer.font_sizes(max_font_size);
+            let resized = ResizedText::create(
+                style,
+                text,
+                area.length,
+                max_font_size,
+                min_font_size,
+                rasterizer.overflow,
+            )?;
+            rasterizer.check_readable(text, resized.font_size);
+            let offset = text_box.anchor.offset(area, resized.rendered_size);
+            rasterizer.draw_text_row(&mut image, &resized, offset, area.rect(), false);
+        }
+        rasterizer.fit_length_to_content(&mut [&mut image], (false, false));
+        Ok(rasterizer.finish_image(image))
+    }
+
+    /// Same as `render()` but converts the image into raster lines ready to print
+    pub fn rasterize(&self, fields: &HashMap<&str, &str>) -> Result<RasterLines> {
+        let image = self.render(fields)?;
+        self.rasterizer.to_raster_lines(&image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anchor, Area, Template};
+    use crate::printer::constants::label_data;
+    use crate::text::{RasterizeError, RasterizeErrorKind, TextRasterizer};
+    use std::collections::HashMap;
+
+    const TEST_FONT: &[u8] = include_bytes!("../../test-fonts/DejaVuSansMono.ttf");
+
+    /// The smallest `(x, y, x, y)` box around the black pixels of `image`
+    fn ink_bounds(image: &image::GrayImage) -> (u32, u32, u32, u32) {
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] <= crate::text::DEFAULT_THRESHOLD)
+            .fold((u32::MAX, u32::MAX, 0, 0), |(x0, y0, x1, y1), (x, y, _)| {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            })
+    }
+
+    #[test]
+    fn text_boxes_are_filled_in() {
+        let label = label_data(29, Some(90)).unwrap();
+        let mut template =
+            Template::new(TextRasterizer::from_font_bytes(label, TEST_FONT.to_vec()));
+        let name = Area {
+            x: 0,
+            y: 0,
+            length: 500,
+            width: 150,
+        };
+        let title = Area {
+            x: 500,
+            y: 150,
+            length: 491,
+            width: 150,
+        };
+        template.add_text_box(name, Anchor::TopLeft, 80.0, "name");
+        template.add_text_box(title, Anchor::BottomRight, 40.0, "title");
+
+        let mut fields = HashMap::new();
+        fields.insert("name", "Ada Lovelace");
+        fields.insert("title", "Analyst");
+        let image = template.render(&fields).unwrap();
+        assert_eq!(image.dimensions(), (991, label.raster_width()));
+
+        // Each box only draws inside its own area, in the corner it's anchored to
+        let (x0, y0, x1, y1) =
+            ink_bounds(&image::imageops::crop_imm(&image, 0, 0, 500, 150).to_image());
+        assert!(x0 < 10 && y0 < 40 && x1 < 500 && y1 < 150);
+        let (x0, y0, x1, y1) =
+            ink_bounds(&image::imageops::crop_imm(&image, 500, 150, 491, 156).to_image());
+        assert!(x0 > 100 && y0 > 80 && x1 > 470, "{:?}", (x0, y0, x1, y1));
+        assert_eq!(
+            ink_bounds(&image::imageops::crop_imm(&image, 500, 0, 491, 150).to_image()).2,
+            0
+        );
+
+        assert_eq!(template.rasterize(&fields).unwrap().len(), 991);
+        fields.remove("title");
+        match template.render(&fields) {
+            Err(RasterizeError(RasterizeErrorKind::MissingField(field), _)) => {
+                assert_eq!(field, "title")
+            }
+            other => panic!(
+                "Expected MissingField, got {:?}",
+                other.map(|image| image.dimensions())
+            ),
+        }
+    }
+}